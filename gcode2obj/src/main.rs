@@ -12,35 +12,169 @@
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::BufWriter;
 use std::io::Read;
+use std::io::Write;
 use std::io::stdin;
+use std::io::stdout;
 use std::path::PathBuf;
 
 use clap::ArgAction;
 use clap::Parser;
+use clap::ValueEnum;
 use gcode_nom::binary::bgcode_parser;
+use gcode_nom::binary::gcode_block::decode::decode_blocks;
+use gcode_nom::command::Command;
+use gcode_nom::tessellation::Tessellation;
 use log::info;
+use obj::Groups;
 use obj::Obj;
+use tube::Profile;
+use tube::Tube;
 
 mod obj;
+mod tube;
 
 // Occasionally want to apply Blender specific transform.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Blender compatibility mode.
+    /// Blender compatibility mode, swaps the Y and Z axes.
+    ///
+    /// Not supported by glb, which is always Y up.
     #[clap(long, short, action=ArgAction::SetTrue)]
     apply_blender_transform: bool,
+    /// Output a solid mesh, sweeping this profile along each extrusion.
+    #[clap(long, value_enum)]
+    tube: Option<ProfileArg>,
+    /// Extrusion width in mm, unless given by a ";WIDTH:" comment.
+    #[clap(long, default_value_t = 0.45_f64, value_parser = positive)]
+    width: f64,
+    /// Layer height in mm, unless given by a ";HEIGHT:" comment.
+    #[clap(long, default_value_t = 0.2_f64, value_parser = positive)]
+    height: f64,
+    /// Add "g" groups to the OBJ file, may be repeated.
+    #[clap(long, value_enum)]
+    group: Vec<GroupArg>,
+    /// Write a material library, with a colour per object and tool.
+    #[clap(long, value_name = "MTL_FILE")]
+    mtl: Option<PathBuf>,
+    /// Arcs are split into segments which stray at most this far from the arc, in mm.
+    #[clap(long, default_value_t = Tessellation::default().chord_tolerance, value_parser = positive)]
+    arc_tolerance: f64,
+    /// The largest angle an arc segment may sweep, in degrees.
+    #[clap(long)]
+    arc_max_angle: Option<f64>,
+    /// Output file format.
+    #[clap(long, value_enum, default_value_t = Format::Obj)]
+    format: Format,
     /// Name of the file to convert.
     file: Option<PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Wavefront OBJ (text).
+    Obj,
+    /// Binary STL, always a solid mesh.
+    Stl,
+    /// Binary PLY, with the object of each vertex.
+    Ply,
+    /// Binary glTF, one node per object.
+    Glb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum GroupArg {
+    /// A group per layer.
+    Layer,
+    /// A group per feature type, from the slicer's ";TYPE:" comments.
+    Feature,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ProfileArg {
+    /// Flat top, bottom and sides.
+    Rectangle,
+    /// Flat top and bottom, with rounded sides.
+    Capsule,
+}
+
+impl Args {
+    fn tessellation(&self) -> Tessellation {
+        Tessellation {
+            chord_tolerance: self.arc_tolerance,
+            max_angle: self.arc_max_angle.map(f64::to_radians),
+        }
+    }
+
+    fn tube(&self) -> Option<Tube> {
+        self.tube.map(|profile| Tube {
+            profile: match profile {
+                ProfileArg::Rectangle => Profile::Rectangle,
+                ProfileArg::Capsule => Profile::Capsule,
+            },
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    // Writes the obj to stdout in the selected format.
+    fn write(&self, mut obj: Obj) -> std::io::Result<()> {
+        obj.apply_blender_transform = self.apply_blender_transform;
+        obj.tube = self.tube();
+        obj.groups = Groups {
+            layer: self.group.contains(&GroupArg::Layer),
+            feature: self.group.contains(&GroupArg::Feature),
+        };
+        if let Some(path) = &self.mtl {
+            let mut file = BufWriter::new(File::create(path)?);
+            obj.write_mtl(&mut file)?;
+            file.flush()?;
+            // Referenced relative to the OBJ file.
+            obj.mtllib = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+        }
+        let mut out = BufWriter::new(stdout().lock());
+        match self.format {
+            Format::Obj => writeln!(out, "{obj}")?,
+            Format::Stl => {
+                // STL can only describe a solid.
+                let tube = obj.tube.unwrap_or_else(|| Tube {
+                    profile: Profile::default(),
+                    width: self.width,
+                    height: self.height,
+                });
+                obj.write_stl(&mut out, &tube)?;
+            }
+            Format::Ply => obj.write_ply(&mut out, obj.tube.as_ref())?,
+            Format::Glb => obj.write_glb(&mut out, obj.tube.as_ref())?,
+        }
+        out.flush()
+    }
+}
+
+// Parses a number greater than zero.
+fn positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0_f64 && number.is_finite() => Ok(number),
+        Ok(_) => Err("must be greater than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse(line: &str) -> Command {
+    let (_, command) = Command::parse_line(line).expect("Command not parsable");
+    command
+}
+
 fn main() -> std::io::Result<()> {
     env_logger::init();
 
     let args = Args::parse();
 
-    if let Some(file) = args.file {
+    if let Some(file) = &args.file {
         info!("File: {}", file.display());
         if file.exists() {
             if let Some(ext) = file.extension() {
@@ -48,9 +182,9 @@ fn main() -> std::io::Result<()> {
                     info!("Reading gcode file");
                     let file = File::open(file)?;
                     let buffer = BufReader::new(file);
-                    let mut obj = buffer.lines().map(|l| l.unwrap()).collect::<Obj>();
-                    obj.apply_blender_transform = args.apply_blender_transform;
-                    println!("{obj}");
+                    let commands = buffer.lines().map(|l| parse(&l.unwrap()));
+                    let obj = Obj::from_commands(commands, args.tessellation());
+                    args.write(obj)?;
                 } else if ext == "bgcode" {
                     info!("Reading bgcode file");
                     let file = File::open(file)?;
@@ -60,9 +194,10 @@ fn main() -> std::io::Result<()> {
                         match bgcode_parser(&buffer) {
                             Ok((_remain, bgcode)) => {
                                 log::info!("parser succeeded: Valid input");
-                                let obj = bgcode.gcode.into_iter().collect::<Obj>();
-
-                                println!("{obj}");
+                                let commands = decode_blocks(&bgcode.gcode)
+                                    .expect("fail to decompress data block");
+                                let obj = Obj::from_commands(commands, args.tessellation());
+                                args.write(obj)?;
                             }
                             Err(e) => {
                                 log::error!("Unhandled error decoding file {e}");
@@ -81,9 +216,12 @@ fn main() -> std::io::Result<()> {
         }
     } else {
         info!("Reading from stdin");
-        let mut obj = stdin().lock().lines().map(|l| l.unwrap()).collect::<Obj>();
-        obj.apply_blender_transform = args.apply_blender_transform;
-        println!("{obj}");
+        // Stdin is unlocked before the output is written.
+        let obj = Obj::from_commands(
+            stdin().lock().lines().map(|l| parse(&l.unwrap())),
+            args.tessellation(),
+        );
+        args.write(obj)?;
     }
 
     Ok(())
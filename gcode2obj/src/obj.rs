@@ -5,24 +5,42 @@
 //! a list of vertices, V
 //! a list of indices into V.
 //!
-use core::f64::consts::TAU;
 use core::fmt::Display;
 use core::hash::Hash;
 use core::hash::Hasher;
 use core::mem;
 use core::panic;
+use std::collections::BTreeMap;
+use std::io;
 
 use hashbrown::HashMap;
 
-use gcode_nom::ArcParams;
-use gcode_nom::MM_PER_ARC_SEGMENT;
+use gcode_nom::Plane;
 use gcode_nom::PositionMode;
+use gcode_nom::arc::ArcVal;
+use gcode_nom::arc::Form as ArcForm;
+use gcode_nom::arc_end;
 use gcode_nom::binary::gcode_block::GCodeBlock;
-use gcode_nom::binary::inflate::decompress_data_block;
+use gcode_nom::binary::gcode_block::decode::decode_blocks;
 use gcode_nom::command::Command;
-use gcode_nom::compute_arc;
+use gcode_nom::compute_arc_to;
+use gcode_nom::layer::LayerTracker;
+use gcode_nom::objects::KlipperObjects;
 use gcode_nom::params::head::PosVal;
 use gcode_nom::params::mp::MultiPartVal;
+use gcode_nom::tessellation::Tessellation;
+
+use crate::tube::Mesh;
+use crate::tube::Tube;
+
+/// Binary glTF 2.0 export.
+mod glb;
+/// Material library export.
+mod mtl;
+/// Binary PLY export.
+mod ply;
+/// Binary STL export.
+mod stl;
 
 #[derive(Debug, Clone)]
 struct Vertex(f64, f64, f64);
@@ -45,7 +63,7 @@ impl Hash for Vertex {
 }
 
 ///  Structure to compute a index and vertex like buffers.
-#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Obj {
     /// De-duplicating structure.
     //   Given a point return the position in the vertex_buffer
@@ -54,16 +72,186 @@ pub struct Obj {
 
     // Multipart: One "OBJ" file can contain multiple objects.
     //
-    // Keyed by the slot id.
+    // Keyed by the slot id. Ordered, so objects are always written in
+    // the same sequence, purge towers (negative ids) first.
     //
-    // Each slot is a vector of point indexes representing line.
-    lines_store: HashMap<i128, Vec<Vec<usize>>>,
+    // Each slot is a vector of lines.
+    lines_store: BTreeMap<i128, Vec<Line>>,
 
     // Keyed by the slot id.
-    name_store: HashMap<i128, String>,
+    name_store: BTreeMap<i128, String>,
 
     // Blender axes compatible mode.
     pub apply_blender_transform: bool,
+
+    // When set, output a solid mesh rather than lines.
+    pub tube: Option<Tube>,
+
+    // Feature types, from the slicer's ";TYPE:" comments.
+    features: Vec<String>,
+
+    // Pairs of slot id and tool, in order of first use.
+    materials: Vec<(i128, u8)>,
+
+    // Which "g" groups are written to the OBJ file.
+    pub groups: Groups,
+
+    // When set, the name of the material library referenced by the OBJ file.
+    pub mtllib: Option<String>,
+}
+
+/// Selects the groups written to the OBJ file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Groups {
+    /// A group per layer, `layer_3`.
+    pub layer: bool,
+    /// A group per feature type, `external_perimeter`.
+    pub feature: bool,
+}
+
+/// Converts a feature type into a group name.
+///
+/// "External perimeter" becomes `external_perimeter`.
+fn group_name(feature: &str) -> String {
+    feature
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The contents of a single object, ready for export.
+#[derive(Debug)]
+enum Geometry {
+    Mesh(Mesh),
+    Lines {
+        points: Vec<[f64; 3]>,
+        segments: Vec<[usize; 2]>,
+    },
+}
+
+impl Geometry {
+    // Swaps the Y and Z axes. A reflection, so the winding of each
+    // triangle is reversed.
+    fn swap_y_z(&mut self) {
+        match self {
+            Self::Mesh(mesh) => {
+                for [_, y, z] in mesh.positions.iter_mut().chain(&mut mesh.normals) {
+                    mem::swap(y, z);
+                }
+                for [_, b, c] in &mut mesh.triangles {
+                    mem::swap(b, c);
+                }
+            }
+            Self::Lines { points, .. } => {
+                for [_, y, z] in points {
+                    mem::swap(y, z);
+                }
+            }
+        }
+    }
+}
+
+/// Binary formats store single precision floats.
+#[allow(clippy::cast_possible_truncation)]
+const fn to_f32(value: f64) -> f32 {
+    value as f32
+}
+
+/// Binary formats store 32 bit indices and counts.
+fn to_u32(value: usize) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| io::Error::other("too many vertices for a 32 bit index"))
+}
+
+/// A sequence of point indexes, extruded without a break.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Line {
+    points: Vec<usize>,
+    // Changes to the size of the extrusion along the line.
+    sizes: Vec<Size>,
+    // Changes to what is being printed, keyed by index into points.
+    tags: Vec<(usize, Tag)>,
+}
+
+/// Extrusion width and layer height, when given by slicer comments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Size {
+    // Applies to the segments starting at this index into points.
+    from: usize,
+    width: Option<f64>,
+    height: Option<f64>,
+}
+
+/// What is being printed, used to group lines and pick materials.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Tag {
+    layer: Option<usize>,
+    // An index into the feature names.
+    feature: Option<usize>,
+    tool: u8,
+}
+
+impl Line {
+    // Appends a point, recording any change in the size of the extrusion
+    // or in what is being printed.
+    fn push(&mut self, index: usize, width: Option<f64>, height: Option<f64>, tag: Tag) {
+        let from = self.points.len().saturating_sub(1);
+        match self.tags.last_mut() {
+            Some((_, last)) if *last == tag => {}
+            // The first point is the end of a travel move, it takes the
+            // tag of the extrusion which follows.
+            Some((last_from, last)) if *last_from == from => *last = tag,
+            _ => self.tags.push((from, tag)),
+        }
+        if self
+            .sizes
+            .last()
+            .is_none_or(|size| size.width != width || size.height != height)
+        {
+            self.sizes.push(Size {
+                from,
+                width,
+                height,
+            });
+        }
+        self.points.push(index);
+    }
+
+    // Splits the line wherever the tag changes.
+    fn split(&self) -> Vec<(Tag, Self)> {
+        let mut runs = vec![];
+        for (n, (from, tag)) in self.tags.iter().enumerate() {
+            let last = self.points.len().saturating_sub(1);
+            let to = self.tags.get(n + 1).map_or(last, |(next, _)| *next);
+            if to <= *from {
+                continue;
+            }
+            let mut run = Self::default();
+            for i in *from..=to {
+                // The size of the segment ending at this point.
+                let segment = i.max(from + 1) - 1;
+                let size = self
+                    .sizes
+                    .iter()
+                    .rev()
+                    .find(|size| size.from <= segment)
+                    .copied()
+                    .unwrap_or(Size {
+                        from: 0,
+                        width: None,
+                        height: None,
+                    });
+                run.push(self.points[i], size.width, size.height, *tag);
+            }
+            runs.push((*tag, run));
+        }
+        runs
+    }
 }
 
 // Display the object
@@ -80,9 +268,16 @@ pub struct Obj {
 // Quote: Campbell Barton
 impl Display for Obj {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(tube) = &self.tube {
+            return self.fmt_mesh(f, tube);
+        }
+
         // Write out vertex buffer
         // "List of geometric vertices, with (x, y, z, [w]) coordinates, w is optional and defaults to 1.0."
         // [spec](<https://en.wikipedia.org/wiki/Wavefront_.obj_file>)
+        if let Some(mtllib) = &self.mtllib {
+            writeln!(f, "mtllib {mtllib}")?;
+        }
         if self.apply_blender_transform {
             for Vertex(x, y, z) in &self.vertex_buffer {
                 writeln!(f, "v {x} {z} {y}")?;
@@ -102,25 +297,20 @@ impl Display for Obj {
         for (slot_id, lines) in &self.lines_store {
             if display_object_name {
                 // "o object_name"  - the name of the object.
-                // The slot_id is the object name.
-                if slot_id < &0 {
-                    writeln!(f, "o purge_tower_{slot_id}")?;
-                } else {
-                    writeln!(f, "o object_{slot_id}")?;
-                }
+                // From M486, otherwise derived from the slot_id.
+                writeln!(f, "o {}", self.object_name(*slot_id))?;
             }
 
             // Write out sequence of index buffers.
+            let mut last = None;
             for line in lines {
-                // line "l 1 2 3"  list of vertex indices.
-                if line.len() > 1 {
-                    write!(f, "l")?;
-                    for i in line {
-                        // '+1' convert from zero based counting.
-                        // The first index is '1'.
-                        write!(f, " {}", i + 1)?;
+                if self.is_split() {
+                    for (tag, run) in line.split() {
+                        self.fmt_tag(f, *slot_id, tag, &mut last)?;
+                        fmt_line(f, &run)?;
                     }
-                    writeln!(f)?;
+                } else {
+                    fmt_line(f, line)?;
                 }
             }
         }
@@ -128,32 +318,324 @@ impl Display for Obj {
     }
 }
 
+// line "l 1 2 3"  list of vertex indices.
+fn fmt_line(f: &mut core::fmt::Formatter<'_>, line: &Line) -> core::fmt::Result {
+    if line.points.len() > 1 {
+        write!(f, "l")?;
+        for i in &line.points {
+            // '+1' convert from zero based counting.
+            // The first index is '1'.
+            write!(f, " {}", i + 1)?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+impl Obj {
+    // Sweeps the tube's profile along each line of an object.
+    fn mesh(&self, lines: &[Line], tube: &Tube) -> Mesh {
+        let mut mesh = Mesh::default();
+        for line in lines {
+            self.sweep(line, tube, &mut mesh);
+        }
+        mesh
+    }
+
+    // Sweeps the tube's profile along a single line.
+    fn sweep(&self, line: &Line, tube: &Tube, mesh: &mut Mesh) {
+        let points = line
+            .points
+            .iter()
+            .map(|i| {
+                let Vertex(x, y, z) = self.vertex_buffer[*i];
+                [x, y, z]
+            })
+            .collect::<Vec<_>>();
+        // Each change of size starts a new section of tube.
+        for (n, size) in line.sizes.iter().enumerate() {
+            let to = line
+                .sizes
+                .get(n + 1)
+                .map_or(points.len() - 1, |next| next.from);
+            tube.sweep(
+                &points[size.from..=to],
+                size.width.unwrap_or(tube.width),
+                size.height.unwrap_or(tube.height),
+                mesh,
+            );
+        }
+    }
+
+    // Lines are split into runs when writing groups or materials.
+    const fn is_split(&self) -> bool {
+        self.groups.layer || self.groups.feature || self.mtllib.is_some()
+    }
+
+    // The names of the groups holding lines with this tag.
+    fn group_names(&self, tag: Tag) -> Option<String> {
+        let mut names = vec![];
+        if self.groups.layer {
+            names.push(
+                tag.layer
+                    .map_or_else(|| "no_layer".to_string(), |index| format!("layer_{index}")),
+            );
+        }
+        if self.groups.feature {
+            names.push(tag.feature.map_or_else(
+                || "no_feature".to_string(),
+                |index| group_name(&self.features[index]),
+            ));
+        }
+        (!names.is_empty()).then(|| names.join(" "))
+    }
+
+    // Writes "g" and "usemtl" statements, when they change.
+    fn fmt_tag(
+        &self,
+        f: &mut core::fmt::Formatter<'_>,
+        slot_id: i128,
+        tag: Tag,
+        last: &mut Option<Tag>,
+    ) -> core::fmt::Result {
+        let names = self.group_names(tag);
+        if last.is_none_or(|last| self.group_names(last) != names)
+            && let Some(names) = names
+        {
+            writeln!(f, "g {names}")?;
+        }
+        if self.mtllib.is_some() && last.is_none_or(|last| last.tool != tag.tool) {
+            writeln!(f, "usemtl {}", Self::material_name(slot_id, tag.tool))?;
+        }
+        *last = Some(tag);
+        Ok(())
+    }
+
+    // The name of the material used by an object, when printed with a tool.
+    fn material_name(slot_id: i128, tool: u8) -> String {
+        format!("{}_t{tool}", Self::slot_name(slot_id))
+    }
+
+    // Records the use of a material.
+    fn use_material(&mut self, slot_id: i128, tool: u8) {
+        if !self.materials.contains(&(slot_id, tool)) {
+            self.materials.push((slot_id, tool));
+        }
+    }
+
+    // Returns the index of a feature type, adding it if new.
+    fn feature(&mut self, name: &str) -> usize {
+        self.features
+            .iter()
+            .position(|feature| feature == name)
+            .unwrap_or_else(|| {
+                self.features.push(name.to_string());
+                self.features.len() - 1
+            })
+    }
+
+    // Returns the points used by the lines of an object, and the
+    // segments joining them as pairs of indices into the points.
+    fn segments(&self, lines: &[Line]) -> (Vec<[f64; 3]>, Vec<[usize; 2]>) {
+        // Maps an index into vertex_buffer to an index into points.
+        let mut reindex = HashMap::new();
+        let mut points = vec![];
+        let mut segments = vec![];
+        for line in lines {
+            let indices = line
+                .points
+                .iter()
+                .map(|i| {
+                    *reindex.entry(*i).or_insert_with(|| {
+                        let Vertex(x, y, z) = self.vertex_buffer[*i];
+                        points.push([x, y, z]);
+                        points.len() - 1
+                    })
+                })
+                .collect::<Vec<_>>();
+            segments.extend(indices.windows(2).map(|w| [w[0], w[1]]));
+        }
+        (points, segments)
+    }
+
+    // The geometry of each object, a mesh when given a tube, otherwise lines.
+    //
+    // Objects with nothing to draw are omitted. With the Blender transform
+    // the Y and Z axes are swapped, as in the OBJ output.
+    fn geometry(&self, tube: Option<&Tube>) -> Vec<(i128, Geometry)> {
+        self.lines_store
+            .iter()
+            .filter_map(|(slot_id, lines)| {
+                let geometry = tube.map_or_else(
+                    || {
+                        let (points, segments) = self.segments(lines);
+                        (!segments.is_empty()).then_some(Geometry::Lines { points, segments })
+                    },
+                    |tube| {
+                        let mesh = self.mesh(lines, tube);
+                        (!mesh.triangles.is_empty()).then_some(Geometry::Mesh(mesh))
+                    },
+                );
+                geometry.map(|mut geometry| {
+                    if self.apply_blender_transform {
+                        geometry.swap_y_z();
+                    }
+                    (*slot_id, geometry)
+                })
+            })
+            .collect()
+    }
+
+    // The name of each object, as given by M486, or derived from its slot id.
+    fn object_name(&self, slot_id: i128) -> String {
+        self.name_store
+            .get(&slot_id)
+            .cloned()
+            .unwrap_or_else(|| Self::slot_name(slot_id))
+    }
+
+    // A name derived from the slot id, without spaces.
+    fn slot_name(slot_id: i128) -> String {
+        if slot_id < 0 {
+            format!("purge_tower_{slot_id}")
+        } else {
+            format!("object_{slot_id}")
+        }
+    }
+
+    // Writes each object as a solid mesh, with vertex normals.
+    fn fmt_mesh(&self, f: &mut core::fmt::Formatter<'_>, tube: &Tube) -> core::fmt::Result {
+        if let Some(mtllib) = &self.mtllib {
+            writeln!(f, "mtllib {mtllib}")?;
+        }
+        let display_object_name = self.lines_store.keys().len() != 1;
+
+        // OBJ indices are global, and start at 1.
+        let mut offset = 1;
+        for (slot_id, lines) in &self.lines_store {
+            if display_object_name {
+                writeln!(f, "o {}", self.object_name(*slot_id))?;
+            }
+
+            // The first triangle of each run, and its tag.
+            let mut tags = vec![];
+            let mesh = if self.is_split() {
+                let mut mesh = Mesh::default();
+                for line in lines {
+                    for (tag, run) in line.split() {
+                        tags.push((mesh.triangles.len(), tag));
+                        self.sweep(&run, tube, &mut mesh);
+                    }
+                }
+                mesh
+            } else {
+                self.mesh(lines, tube)
+            };
+
+            // The Blender transform swaps the Y and Z axes, a reflection,
+            // so the winding of each triangle is reversed.
+            if self.apply_blender_transform {
+                for [x, y, z] in &mesh.positions {
+                    writeln!(f, "v {x} {z} {y}")?;
+                }
+                for [x, y, z] in &mesh.normals {
+                    writeln!(f, "vn {x} {z} {y}")?;
+                }
+            } else {
+                for [x, y, z] in &mesh.positions {
+                    writeln!(f, "v {x} {y} {z}")?;
+                }
+                for [x, y, z] in &mesh.normals {
+                    writeln!(f, "vn {x} {y} {z}")?;
+                }
+            }
+            let mut tags = tags.into_iter().peekable();
+            let mut last = None;
+            for (n, [a, b, c]) in mesh.triangles.iter().enumerate() {
+                // Runs without any triangles are skipped.
+                let mut tag = None;
+                while let Some((_, next)) = tags.next_if(|(first, _)| *first <= n) {
+                    tag = Some(next);
+                }
+                if let Some(tag) = tag {
+                    self.fmt_tag(f, *slot_id, tag, &mut last)?;
+                }
+                let (a, b, c) = (a + offset, b + offset, c + offset);
+                if self.apply_blender_transform {
+                    writeln!(f, "f {a}//{a} {c}//{c} {b}//{b}")?;
+                } else {
+                    writeln!(f, "f {a}//{a} {b}//{b} {c}//{c}")?;
+                }
+            }
+            offset += mesh.positions.len();
+        }
+        Ok(())
+    }
+}
+
 impl<'a> FromIterator<GCodeBlock<'a>> for Obj {
     fn from_iter<I>(iter: I) -> Self
     where
         I: IntoIterator<Item = GCodeBlock<'a>>,
     {
-        iter.into_iter()
-            .flat_map(|gcode| {
-                let (_remain, data) =
-                    decompress_data_block(gcode.data, &gcode.param.encoding, &gcode.header)
-                        .expect("fail to decompress data block");
-
-                String::from_utf8_lossy(&data)
-                    .to_string()
-                    .lines()
-                    .map(std::string::ToString::to_string)
-                    .collect::<Vec<_>>()
-            })
+        let blocks = iter.into_iter().collect::<Vec<_>>();
+        decode_blocks(&blocks)
+            .expect("fail to decompress data block")
+            .into_iter()
             .collect::<Self>()
     }
 }
+
 /// TODO: Want to iterate over something more flexible
 /// ie. Drop String for something more generic `AsRef<&str>`?
 impl FromIterator<String> for Obj {
     fn from_iter<I>(iter: I) -> Self
     where
         I: IntoIterator<Item = String>,
+    {
+        iter.into_iter()
+            .map(|line| {
+                let (_, command) = Command::parse_line(&line).expect("Command not parsable");
+                command
+            })
+            .collect()
+    }
+}
+
+impl FromIterator<Command> for Obj {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Command>,
+    {
+        Self::from_commands(iter, Tessellation::default())
+    }
+}
+
+impl Obj {
+    // Returns the index of a vertex, adding it to the vertex buffer if new.
+    fn vertex_index(&mut self, vertex: Vertex) -> usize {
+        if let Some(index) = self.vertex_store.get(&vertex) {
+            return *index;
+        }
+        let index = self.vertex_buffer.len();
+        self.vertex_store.insert(vertex.clone(), index);
+        self.vertex_buffer.push(vertex);
+        index
+    }
+
+    // Moves a completed line into the object's store.
+    //
+    // TODO: set the capacity of the complete_line
+    // to the last good capacity.
+    fn end_line(&mut self, id: i128, line_buffer: &mut Line) {
+        let complete_line = mem::take(line_buffer);
+        self.lines_store.entry(id).or_default().push(complete_line);
+    }
+
+    /// Replays the commands, splitting arcs into segments as directed.
+    pub fn from_commands<I>(iter: I, tessellation: Tessellation) -> Self
+    where
+        I: IntoIterator<Item = Command>,
     {
         let mut obj = Self::default();
 
@@ -167,11 +649,16 @@ impl FromIterator<String> for Obj {
         // Option as job can be cancelled without specifying the next object.
         let mut object_id = Some(0);
         // keyed by object_id
-        let mut line_buffer_store: HashMap<i128, Vec<usize>> = HashMap::from([(0, vec![])]);
+        let mut line_buffer_store: HashMap<i128, Line> = HashMap::from([(0, Line::default())]);
 
         let mut is_extruding = true;
+        let mut layers = LayerTracker::default();
+        let mut tag = Tag::default();
+        // From ";WIDTH:" and ";HEIGHT:" comments.
+        let mut width = None;
+        let mut height = None;
         let mut position_mode = PositionMode::default();
-        let mut next_vertex_pos = 0;
+        let mut plane = Plane::default();
 
         let mut current_x = 0_f64;
         let mut current_y = 0_f64;
@@ -181,8 +668,12 @@ impl FromIterator<String> for Obj {
         let mut origin_y = 0_f64;
         let mut origin_z = 0_f64;
 
-        for line in iter {
-            let (_, command) = Command::parse_line(&line).expect("Command not parsable");
+        // Klipper's named objects share the M486 slot model.
+        let mut klipper = KlipperObjects::default();
+
+        for command in iter {
+            let command = klipper.translate(command);
+            let clockwise = matches!(command, Command::G2(_));
             match command {
                 // Treat G0 and G1 command identically.
                 //
@@ -236,205 +727,89 @@ impl FromIterator<String> for Obj {
                         origin_z + current_z,
                     );
                     if is_extruding {
-                        if let Some(v_index) = obj.vertex_store.get(&vertex) {
-                            // Push record of exiting vertex to index_buffer.
-                            if let Some(id) = object_id {
-                                if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                                    line_buffer.push(*v_index);
-                                } else {
-                                    debug_assert!(
-                                        false,
-                                        "failed to get line buffer for object id {id}"
-                                    );
-                                }
-                            }
-                        } else {
-                            // New entry in vertex_buffer and index_buffer.
-                            if let Some(id) = object_id {
-                                if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                                    obj.vertex_store.insert(vertex.clone(), next_vertex_pos);
-                                    line_buffer.push(next_vertex_pos);
-                                    obj.vertex_buffer.push(vertex);
-                                    next_vertex_pos += 1;
-                                } else {
-                                    debug_assert!(
-                                        false,
-                                        "failed to get line buffer for object id {id}"
-                                    );
-                                }
-                            }
+                        if !x_param.is_nan() || !y_param.is_nan() {
+                            layers.extrude(current_z);
+                            tag.layer = layers.current().map(|layer| layer.index);
                         }
-                    } else {
-                        // Not extruding
-                        //
                         if let Some(id) = object_id {
+                            obj.use_material(id, tag.tool);
                             if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                                // TODO: set the capacity of the complete_line
-                                // to the last good capacity.
-                                let mut complete_line = vec![];
-                                mem::swap(line_buffer, &mut complete_line);
-                                if let Some(obj_line_store) = obj.lines_store.get_mut(&id) {
-                                    // If the line store already has a line for this part_id
-                                    // then append to it.
-                                    obj_line_store.push(complete_line);
-                                } else {
-                                    // Otherwise create a new entry in the lines_store.
-                                    obj.lines_store.insert(id, vec![complete_line]);
-                                }
-
-                                // The first entry in the new line buffer is current position.
-                                if let Some(v_index) = obj.vertex_store.get(&vertex) {
-                                    // Push record of exiting vertex to index_buffer.
-                                    line_buffer.push(*v_index);
-                                } else {
-                                    // New entry in vertex_buffer and index_buffer.
-                                    obj.vertex_store.insert(vertex.clone(), next_vertex_pos);
-                                    line_buffer.push(next_vertex_pos);
-                                    obj.vertex_buffer.push(vertex);
-                                    next_vertex_pos += 1;
-                                }
+                                // Push record of the vertex to index_buffer.
+                                line_buffer.push(obj.vertex_index(vertex), width, height, tag);
                             } else {
                                 debug_assert!(
                                     false,
                                     "failed to get line buffer for object id {id}"
                                 );
-                                panic!("failed to get line buffer for object id {id}");
                             }
                         }
-                    }
-                }
-                Command::G2(arc_form) => {
-                    if let Some(id) = object_id {
+                    } else if let Some(id) = object_id {
+                        // Not extruding
+                        //
                         if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                            // Clockwise arc
-                            let ArcParams {
-                                center,
-                                radius,
-                                mut theta_start,
-                                theta_end,
-                            } = compute_arc(current_x, current_y, &arc_form);
-
-                            // Regarding the Ambiguity/Equivalence  of the angles 0 and 2PI
-                            // All values here are in the range 0<=theta<2PI
-                            // We are rotating clockwise
-                            // in this cased the start angle of 0 should be read as 2PI
-                            if theta_start == 0_f64 {
-                                theta_start = TAU;
-                            }
-
-                            let delta_theta = if theta_start < theta_end {
-                                // Adjust for zero crossing
-                                // say 115 -> 304 degrees
-                                // delta_theta = 115 + (360 - 304 ) = 170
-                                theta_start + (TAU - theta_end)
-                            } else {
-                                theta_start - theta_end
-                            };
-                            let total_arc_length = delta_theta * radius;
-                            // n_steps must be a number > 0
-                            let n_steps = (total_arc_length / MM_PER_ARC_SEGMENT).ceil();
-                            let theta_step = delta_theta / n_steps;
-
-                            // x,y are the position of the head in absolute units.
-                            let mut x = f64::NAN;
-                            let mut y = f64::NAN;
-
-                            // For loop: f64 has a problem with numerical accuracy
-                            // specifically the comparing limit.
-                            // rust idiomatically insists on indexed here
-                            for i in 0..=n_steps as u64 {
-                                let theta = (i as f64).mul_add(-theta_step, theta_start) % TAU;
-                                x = radius.mul_add(theta.cos(), center.0);
-                                y = radius.mul_add(theta.sin(), center.1);
-                                let vertex =
-                                    Vertex(origin_x + x, origin_y + y, origin_z + current_z);
-
-                                // This command is always extruding.
-                                if let Some(v_index) = obj.vertex_store.get(&vertex) {
-                                    // Push record of exiting vertex to index_buffer.
-                                    line_buffer.push(*v_index);
-                                } else {
-                                    // New entry in vertex_buffer and index_buffer.
-                                    obj.vertex_store.insert(vertex.clone(), next_vertex_pos);
-                                    line_buffer.push(next_vertex_pos);
-                                    obj.vertex_buffer.push(vertex);
-                                    next_vertex_pos += 1;
-                                }
-                            }
-
-                            current_x = x;
-                            current_y = y;
+                            obj.end_line(id, line_buffer);
+                            // The first entry in the new line buffer is current position.
+                            line_buffer.push(obj.vertex_index(vertex), width, height, tag);
                         } else {
+                            debug_assert!(false, "failed to get line buffer for object id {id}");
                             panic!("failed to get line buffer for object id {id}");
                         }
                     }
                 }
-                Command::G3(arc_form) => {
-                    if let Some(id) = object_id {
-                        if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                            // Counter-clockwise arc
-                            let ArcParams {
-                                center,
-                                radius,
-                                theta_start,
-                                mut theta_end,
-                            } = compute_arc(current_x, current_y, &arc_form);
-
-                            // Regarding the Ambiguity/Equivalence of the angles 0 and 2PI
-                            // All values here are in the range 0<=theta<2PI
-                            // We are rotating clockwise
-                            // in this cased the start angle of 0 should be read as 2PI
-                            if theta_end == 0_f64 {
-                                theta_end = 2_f64 * std::f64::consts::PI;
-                            }
+                Command::G2(arc_form) | Command::G3(arc_form) => {
+                    let (ArcForm::IJ(vals) | ArcForm::R(vals)) = &arc_form;
+                    let is_extruding_arc = vals
+                        .iter()
+                        .any(|val| matches!(val, ArcVal::E(e) if *e > 0_f64));
+                    let start = [current_x, current_y, current_z];
+                    let end = arc_end(start, &arc_form, position_mode);
+                    [current_x, current_y, current_z] = end;
 
-                            let delta_theta = if theta_start > theta_end {
-                                // Adjust for zero crossing
-                                // say 306 -> 115 degrees
-                                // delta_theta = (360 - 305 ) + 115 = 170
-                                TAU - theta_start + theta_end
-                            } else {
-                                theta_end - theta_start
-                            };
-                            let total_arc_length = delta_theta * radius;
-                            // n_steps must be a number > 0
-                            let n_steps = (total_arc_length / MM_PER_ARC_SEGMENT).ceil();
-                            let theta_step = delta_theta / n_steps;
-
-                            // x,y are the position of the head in absolute units.
-                            let mut x = f64::NAN;
-                            let mut y = f64::NAN;
-
-                            // For loop: f64 has a problem with numerical accuracy
-                            // specifically the comparing limit.
-                            // rust idiomatically insists on indexed here
-                            for i in 0..=n_steps as u64 {
-                                let theta = (i as f64).mul_add(theta_step, theta_start) % TAU;
-                                x = radius.mul_add(theta.cos(), center.0);
-                                y = radius.mul_add(theta.sin(), center.1);
-                                let vertex =
-                                    Vertex(origin_x + x, origin_y + y, origin_z + current_z);
-
-                                // This command is always extruding.
-                                if let Some(v_index) = obj.vertex_store.get(&vertex) {
-                                    // Push record of exiting vertex to index_buffer.
-                                    line_buffer.push(*v_index);
-                                } else {
-                                    // New entry in vertex_buffer and index_buffer.
-                                    obj.vertex_store.insert(vertex.clone(), next_vertex_pos);
-                                    line_buffer.push(next_vertex_pos);
-                                    obj.vertex_buffer.push(vertex);
-                                    next_vertex_pos += 1;
-                                }
+                    if let Some(id) = object_id {
+                        let Some(line_buffer) = line_buffer_store.get_mut(&id) else {
+                            panic!("failed to get line buffer for object id {id}");
+                        };
+                        if is_extruding_arc {
+                            layers.extrude(current_z);
+                            tag.layer = layers.current().map(|layer| layer.index);
+                            obj.use_material(id, tag.tool);
+                            let [first, second, normal] = plane.axes();
+                            let params = compute_arc_to(
+                                (start[first], start[second]),
+                                (end[first], end[second]),
+                                &arc_form,
+                                plane,
+                            );
+                            for [u, v, t] in tessellation.points(&params, params.sweep(clockwise)) {
+                                // The third axis changes along a helix.
+                                let mut point = [0_f64; 3];
+                                point[first] = u;
+                                point[second] = v;
+                                point[normal] =
+                                    (end[normal] - start[normal]).mul_add(t, start[normal]);
+                                let vertex = Vertex(
+                                    origin_x + point[0],
+                                    origin_y + point[1],
+                                    origin_z + point[2],
+                                );
+                                line_buffer.push(obj.vertex_index(vertex), width, height, tag);
                             }
-
-                            current_x = x;
-                            current_y = y;
                         } else {
-                            panic!("failed to get line buffer for object id {id}");
+                            // A travel move, the next line starts at the end of the arc.
+                            obj.end_line(id, line_buffer);
+                            let vertex = Vertex(
+                                origin_x + current_x,
+                                origin_y + current_y,
+                                origin_z + current_z,
+                            );
+                            line_buffer.push(obj.vertex_index(vertex), width, height, tag);
                         }
                     }
                 }
+                // G17, G18 and G19 select the plane of arcs.
+                Command::G17 => plane = Plane::XY,
+                Command::G18 => plane = Plane::ZX,
+                Command::G19 => plane = Plane::YZ,
                 // G90 and G91 set the position mode.
                 Command::G90 => position_mode = PositionMode::Absolute,
                 Command::G91 => position_mode = PositionMode::Relative,
@@ -450,25 +825,10 @@ impl FromIterator<String> for Obj {
                                     // enter "move mode" ..ie not laying down filament.
                                     is_extruding = false;
                                     // For Visualization we start a new line.
-                                    //
-                                    // TODO: set the capacity of the complete_line
-                                    // to the last good capacity.
-                                    if let Some(id) = object_id {
-                                        if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                                            let mut complete_line = vec![];
-                                            mem::swap(line_buffer, &mut complete_line);
-                                            // obj.lines.push(complete_line);
-                                            if let Some(obj_line_store) =
-                                                obj.lines_store.get_mut(&id)
-                                            {
-                                                // If the line store already has a line for this part_id
-                                                // then append to it.
-                                                obj_line_store.push(complete_line);
-                                            } else {
-                                                // Otherwise create a new entry in the lines_store.
-                                                obj.lines_store.insert(id, vec![complete_line]);
-                                            }
-                                        }
+                                    if let Some(id) = object_id
+                                        && let Some(line_buffer) = line_buffer_store.get_mut(&id)
+                                    {
+                                        obj.end_line(id, line_buffer);
                                     }
                                 } else {
                                     // Starting to extrude
@@ -511,13 +871,27 @@ impl FromIterator<String> for Obj {
                         }
                     }
                 }
+                Command::Comment(text) => {
+                    let size = |prefix| {
+                        text.strip_prefix(prefix)
+                            .and_then(|v: &str| v.trim().parse::<f64>().ok())
+                    };
+                    width = size("WIDTH:").or(width);
+                    height = size("HEIGHT:").or(height);
+                    if let Some(feature) = text.strip_prefix("TYPE:") {
+                        tag.feature = Some(obj.feature(feature.trim()));
+                    }
+                    if layers.comment(&text) {
+                        tag.layer = layers.current().map(|layer| layer.index);
+                    }
+                }
+                Command::T(tool) => tag.tool = tool,
                 Command::M486(val) => {
                     match val {
                         MultiPartVal::A(new_name) => {
+                            // Names the current object.
                             if let Some(id) = object_id {
-                                if let Some(name) = obj.name_store.get_mut(&id) {
-                                    *name = new_name;
-                                }
+                                obj.name_store.insert(id, new_name);
                             }
                         }
                         MultiPartVal::C => {
@@ -527,20 +901,16 @@ impl FromIterator<String> for Obj {
                         MultiPartVal::P(_) => {
                             object_id = None;
                         }
-                        MultiPartVal::S(val, n) => {
-                            // TODO used name once parsing is implemented.
+                        MultiPartVal::S(val, name) => {
                             // Start and un-cancel are the same action.
                             object_id = Some(val);
                             // Initialize both the entry in the line_buffer_store and the
                             // entry in the global lines_store.
-                            line_buffer_store.entry(val).or_insert(vec![]);
+                            line_buffer_store.entry(val).or_default();
                             obj.lines_store.entry(val).or_insert(vec![]);
 
-                            if let Some(name) = n {
+                            if let Some(name) = name {
                                 obj.name_store.insert(val, name);
-                            } else {
-                                // If no name is given then use the default.
-                                obj.name_store.insert(val, format!("object_{val}"));
                             }
                         }
                         MultiPartVal::T(_) => {
@@ -554,34 +924,144 @@ impl FromIterator<String> for Obj {
                             object_id = Some(val);
                             // Initialize both the entry in the line_buffer_store and the
                             // entry in the global lines_store.
-                            line_buffer_store.entry(val).or_insert(vec![]);
+                            line_buffer_store.entry(val).or_default();
                             obj.lines_store.entry(val).or_insert(vec![]);
                         }
                     }
                 }
+                // Klipper numbers its objects from 0, and prints its skirt or
+                // purge line outside of any object. So, once objects are
+                // declared, slot 0 is not the default.
+                Command::ExcludeObjectDefine(_)
+                    if object_id == Some(0) && obj.lines_store.is_empty() =>
+                {
+                    object_id = Some(-1);
+                    let line_buffer = line_buffer_store.remove(&0).unwrap_or_default();
+                    line_buffer_store.insert(-1, line_buffer);
+                }
                 _ => {
                     // println!("Dropping command {command:#?}");
                 }
             }
         }
 
-        if let Some(id) = object_id {
-            if let Some(line_buffer) = line_buffer_store.get_mut(&id) {
-                if !line_buffer.is_empty() {
-                    // Print head is still extruding at end.
-
-                    if let Some(obj_line_store) = obj.lines_store.get_mut(&id) {
-                        // If the line store already has a line for this part_id
-                        // then append to it.
-                        obj_line_store.push(line_buffer.clone());
-                    } else {
-                        // Otherwise create a new entry in the lines_store.
-                        obj.lines_store.insert(id, vec![line_buffer.clone()]);
-                    }
-                }
+        if let Some(id) = object_id
+            && let Some(line_buffer) = line_buffer_store.get_mut(&id)
+            && !line_buffer.points.is_empty()
+        {
+            // Print head is still extruding at end.
+
+            if let Some(obj_line_store) = obj.lines_store.get_mut(&id) {
+                // If the line store already has a line for this part_id
+                // then append to it.
+                obj_line_store.push(line_buffer.clone());
+            } else {
+                // Otherwise create a new entry in the lines_store.
+                obj.lines_store.insert(id, vec![line_buffer.clone()]);
             }
         }
 
         obj
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use insta::assert_snapshot;
+
+    #[test]
+    fn groups() {
+        let mut obj = [
+            ";LAYER_CHANGE",
+            ";Z:0.2",
+            ";TYPE:External perimeter",
+            "G1 X0 Y0 Z0.2",
+            "G1 X10 E1",
+            ";TYPE:Solid infill",
+            "G1 Y10 E2",
+            ";LAYER_CHANGE",
+            ";Z:0.4",
+            "G1 Z0.4",
+            "G1 X0 E3",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect::<Obj>();
+
+        // Without groups, a single unbroken line.
+        let text = obj.to_string();
+        assert_eq!(text.lines().filter(|l| l.starts_with('l')).count(), 1);
+
+        obj.groups = Groups {
+            layer: true,
+            feature: true,
+        };
+        let text = obj.to_string();
+        assert_eq!(
+            text.lines()
+                .filter(|line| !line.starts_with('v'))
+                .collect::<Vec<_>>(),
+            [
+                "g layer_0 external_perimeter",
+                "l 1 2",
+                "g layer_0 solid_infill",
+                "l 2 3",
+                "g layer_1 solid_infill",
+                "l 3 4 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn klipper() {
+        let obj = [
+            "EXCLUDE_OBJECT_DEFINE NAME=cube_a CENTER=10,10 POLYGON=[[5,5],[15,5],[15,15]]",
+            "EXCLUDE_OBJECT_DEFINE NAME=cube_b CENTER=30,10 POLYGON=[[25,5],[35,5],[35,15]]",
+            // A skirt, outside of any object.
+            "G1 X0 Y0 Z0.2 E-1",
+            "G1 X40 E1",
+            "G1 E-1",
+            "EXCLUDE_OBJECT_START NAME=cube_b",
+            "G1 X25 Y5 E-1",
+            "G1 X35 E1",
+            "G1 E-1",
+            "EXCLUDE_OBJECT_END NAME=cube_b",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect::<Obj>();
+
+        let text = obj.to_string();
+        assert_eq!(
+            text.lines()
+                .filter(|line| !line.starts_with('v'))
+                .collect::<Vec<_>>(),
+            ["o purge_tower_-1", "l 1 2", "o cube_b", "l 3 4"]
+        );
+    }
+
+    #[test]
+    fn arc_demo() {
+        // SNAPSHOT tests
+        let buffer = include_str!("../../assets/arc_demo.gcode");
+        let obj = buffer
+            .lines()
+            .map(std::string::ToString::to_string)
+            .collect::<Obj>();
+        assert_snapshot!(obj.to_string());
+    }
+
+    #[test]
+    fn multipart() {
+        // SNAPSHOT tests
+        //
+        // Objects must be written in the same order on every run.
+        let buffer = include_str!("../../assets/both.gcode");
+        let obj = buffer
+            .lines()
+            .map(std::string::ToString::to_string)
+            .collect::<Obj>();
+        assert_snapshot!(obj.to_string());
+    }
+}
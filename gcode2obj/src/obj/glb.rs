@@ -0,0 +1,251 @@
+//! Binary glTF 2.0 (.glb) export.
+//!
+//! Each object becomes a named node holding a single mesh, drawn either as
+//! lines or as triangles with vertex normals.
+//!
+//! glTF is Y up and measured in metres, G-code is Z up and measured in
+//! millimetres. The root node rotates and scales the print accordingly.
+//!
+//! [spec](<https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html>)
+
+use core::fmt::Write as _;
+use std::io;
+use std::io::Write;
+
+use super::Geometry;
+use super::Obj;
+use super::to_f32;
+use super::to_u32;
+use crate::tube::Tube;
+
+/// "glTF"
+const MAGIC: u32 = 0x4654_6C67;
+const VERSION: u32 = 2;
+/// "JSON"
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+/// "BIN\0"
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const MODE_LINES: u32 = 1;
+const MODE_TRIANGLES: u32 = 4;
+
+/// Rotates Z up to Y up, a quarter turn about the X axis.
+const ROTATION: &str = "[-0.7071067811865476,0,0,0.7071067811865476]";
+/// Millimetres to metres.
+const SCALE: &str = "[0.001,0.001,0.001]";
+
+// Accumulates the binary chunk, and the JSON describing its contents.
+#[derive(Debug, Default)]
+struct Builder {
+    bin: Vec<u8>,
+    accessors: Vec<String>,
+    buffer_views: Vec<String>,
+}
+
+impl Builder {
+    // Appends a VEC3 float accessor, returning its index.
+    fn vec3(&mut self, values: &[[f64; 3]], bounds: bool) -> io::Result<usize> {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        let view = self.view(values.len() * 12, ARRAY_BUFFER)?;
+        for value in values {
+            for (i, v) in value.iter().enumerate() {
+                let v = to_f32(*v);
+                min[i] = min[i].min(v);
+                max[i] = max[i].max(v);
+                self.bin.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        // POSITION accessors must give their bounds.
+        let bounds = if bounds {
+            format!(
+                r#","min":[{},{},{}],"max":[{},{},{}]"#,
+                min[0], min[1], min[2], max[0], max[1], max[2]
+            )
+        } else {
+            String::new()
+        };
+        self.accessors.push(format!(
+            r#"{{"bufferView":{view},"componentType":{FLOAT},"count":{},"type":"VEC3"{bounds}}}"#,
+            values.len()
+        ));
+        Ok(self.accessors.len() - 1)
+    }
+
+    // Appends a scalar index accessor, returning its index.
+    fn indices(&mut self, indices: &[usize]) -> io::Result<usize> {
+        let view = self.view(indices.len() * 4, ELEMENT_ARRAY_BUFFER)?;
+        for index in indices {
+            self.bin.extend_from_slice(&to_u32(*index)?.to_le_bytes());
+        }
+        self.accessors.push(format!(
+            r#"{{"bufferView":{view},"componentType":{UNSIGNED_INT},"count":{},"type":"SCALAR"}}"#,
+            indices.len()
+        ));
+        Ok(self.accessors.len() - 1)
+    }
+
+    // Appends a buffer view, starting at the end of the binary chunk.
+    //
+    // Every element is 4 bytes wide, so views are always aligned.
+    fn view(&mut self, length: usize, target: u32) -> io::Result<usize> {
+        self.buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{target}}}"#,
+            to_u32(self.bin.len())?,
+            to_u32(length)?
+        ));
+        Ok(self.buffer_views.len() - 1)
+    }
+}
+
+impl Obj {
+    /// Writes a binary glTF file.
+    ///
+    /// When `tube` is given the extrusions are swept into a solid mesh,
+    /// otherwise they are written as lines.
+    ///
+    /// # Errors
+    ///   When writing fails, or the file would exceed 4GB.
+    ///
+    ///   When the Blender transform is set. glTF is always Y up, and
+    ///   Blender's importer handles the axes itself.
+    pub fn write_glb<W: Write>(&self, out: &mut W, tube: Option<&Tube>) -> io::Result<()> {
+        if self.apply_blender_transform {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the Blender transform does not apply to glTF, which is always Y up",
+            ));
+        }
+        let mut builder = Builder::default();
+        let mut meshes = vec![];
+        let mut nodes = vec![];
+        for (slot_id, geometry) in self.geometry(tube) {
+            let primitive = match geometry {
+                Geometry::Mesh(mesh) => {
+                    let position = builder.vec3(&mesh.positions, true)?;
+                    let normal = builder.vec3(&mesh.normals, false)?;
+                    let indices = builder.indices(&mesh.triangles.concat())?;
+                    format!(
+                        r#"{{"attributes":{{"POSITION":{position},"NORMAL":{normal}}},"indices":{indices},"mode":{MODE_TRIANGLES}}}"#
+                    )
+                }
+                Geometry::Lines { points, segments } => {
+                    let position = builder.vec3(&points, true)?;
+                    let indices = builder.indices(&segments.concat())?;
+                    format!(
+                        r#"{{"attributes":{{"POSITION":{position}}},"indices":{indices},"mode":{MODE_LINES}}}"#
+                    )
+                }
+            };
+            let name = escape(&self.object_name(slot_id));
+            nodes.push(format!(r#"{{"name":"{name}","mesh":{}}}"#, meshes.len()));
+            meshes.push(format!(r#"{{"name":"{name}","primitives":[{primitive}]}}"#));
+        }
+
+        // Node 0 is the root, node n + 1 holds mesh n.
+        let children = (1..=meshes.len())
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let root = format!(
+            r#"{{"name":"print","rotation":{ROTATION},"scale":{SCALE},"children":[{children}]}}"#
+        );
+
+        // glTF forbids empty arrays.
+        let data = if meshes.is_empty() {
+            String::new()
+        } else {
+            format!(
+                r#","meshes":[{}],"accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]"#,
+                meshes.join(","),
+                builder.accessors.join(","),
+                builder.buffer_views.join(","),
+                builder.bin.len()
+            )
+        };
+        let json = format!(
+            r#"{{"asset":{{"version":"2.0","generator":"gcode2obj"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{}]{data}}}"#,
+            [vec![root], nodes].concat().join(",")
+        );
+
+        // Chunks are padded to 4 bytes, JSON with spaces.
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let bin = builder.bin;
+
+        let mut length = 12 + 8 + json.len();
+        if !bin.is_empty() {
+            length += 8 + bin.len();
+        }
+        out.write_all(&MAGIC.to_le_bytes())?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&to_u32(length)?.to_le_bytes())?;
+
+        out.write_all(&to_u32(json.len())?.to_le_bytes())?;
+        out.write_all(&CHUNK_JSON.to_le_bytes())?;
+        out.write_all(&json)?;
+
+        if !bin.is_empty() {
+            out.write_all(&to_u32(bin.len())?.to_le_bytes())?;
+            out.write_all(&CHUNK_BIN.to_le_bytes())?;
+            out.write_all(&bin)?;
+        }
+        Ok(())
+    }
+}
+
+// Escapes text for use in a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                write!(escaped, "\\u{:04x}", u32::from(c)).expect("writing to a String");
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let obj = ["G1 X0 Y0 Z0.2", "G1 X10 E1", "G1 Y10 E2"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Obj>();
+        let mut out = vec![];
+        obj.write_glb(&mut out, None).unwrap();
+
+        assert_eq!(&out[0..4], b"glTF");
+        let length = u32::from_le_bytes(out[8..12].try_into().unwrap());
+        assert_eq!(length as usize, out.len());
+        assert_eq!(out.len() % 4, 0);
+
+        let json_length = u32::from_le_bytes(out[12..16].try_into().unwrap()) as usize;
+        let json = core::str::from_utf8(&out[20..20 + json_length]).unwrap();
+        assert!(json.contains(r#""mode":1"#));
+        assert_eq!(&out[24 + json_length..28 + json_length], b"BIN\0");
+
+        // glTF is always Y up.
+        let mut obj = obj;
+        obj.apply_blender_transform = true;
+        let error = obj.write_glb(&mut vec![], None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("cube \"A\"\n"), "cube \\\"A\\\"\\u000a");
+    }
+}
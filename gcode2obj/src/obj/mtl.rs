@@ -0,0 +1,86 @@
+//! Material library export.
+//!
+//! Each object gets a material per tool, in a distinct colour. The OBJ
+//! file selects them with "usemtl".
+
+use std::io;
+use std::io::Write;
+
+use super::Obj;
+
+/// Diffuse colours assigned to materials in order of first use.
+const PALETTE: [[f64; 3]; 10] = [
+    [0.122, 0.467, 0.706],
+    [1.000, 0.498, 0.055],
+    [0.173, 0.627, 0.173],
+    [0.839, 0.153, 0.157],
+    [0.580, 0.404, 0.741],
+    [0.549, 0.337, 0.294],
+    [0.890, 0.467, 0.761],
+    [0.498, 0.498, 0.498],
+    [0.737, 0.741, 0.133],
+    [0.090, 0.745, 0.812],
+];
+
+impl Obj {
+    /// Writes the companion ".mtl" file.
+    ///
+    /// # Errors
+    ///   When writing fails.
+    pub fn write_mtl<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (n, (slot_id, tool)) in self.materials.iter().enumerate() {
+            let [r, g, b] = PALETTE[n % PALETTE.len()];
+            if n > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "newmtl {}", Self::material_name(*slot_id, *tool))?;
+            writeln!(out, "Ka 0 0 0")?;
+            writeln!(out, "Kd {r} {g} {b}")?;
+            writeln!(out, "Ks 0 0 0")?;
+            writeln!(out, "d 1")?;
+            writeln!(out, "illum 1")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn materials() {
+        let mut obj = [
+            "G1 X0 Y0 Z0.2",
+            "G1 X10 E1",
+            "T1",
+            "G1 Y10 E2",
+            "T0",
+            "G1 X0 E3",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect::<Obj>();
+        obj.mtllib = Some("print.mtl".to_string());
+
+        let mut out = vec![];
+        obj.write_mtl(&mut out).unwrap();
+        let mtl = String::from_utf8(out).unwrap();
+        assert_eq!(mtl.matches("newmtl").count(), 2);
+        assert!(mtl.starts_with("newmtl object_0_t0\n"));
+        assert!(mtl.contains("newmtl object_0_t1\n"));
+
+        let text = obj.to_string();
+        assert!(text.starts_with("mtllib print.mtl\n"));
+        assert_eq!(
+            text.lines()
+                .filter(|line| line.starts_with("usemtl"))
+                .collect::<Vec<_>>(),
+            [
+                "usemtl object_0_t0",
+                "usemtl object_0_t1",
+                "usemtl object_0_t0"
+            ]
+        );
+    }
+}
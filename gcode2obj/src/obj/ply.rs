@@ -0,0 +1,156 @@
+//! Binary PLY export.
+//!
+//! Each vertex records the object it belongs to. Lines are written as
+//! edges, solid meshes as faces with vertex normals.
+
+use std::io;
+use std::io::Write;
+
+use super::Geometry;
+use super::Obj;
+use super::to_f32;
+use super::to_u32;
+use crate::tube::Tube;
+
+impl Obj {
+    /// Writes a binary little endian PLY file.
+    ///
+    /// When `tube` is given the extrusions are swept into a solid mesh,
+    /// otherwise they are written as edges.
+    ///
+    /// # Errors
+    ///   When writing fails, or there are more than `u32::MAX` vertices.
+    pub fn write_ply<W: Write>(&self, out: &mut W, tube: Option<&Tube>) -> io::Result<()> {
+        let objects = self.geometry(tube);
+
+        let mut vertices = 0;
+        let mut elements = 0;
+        for (_, geometry) in &objects {
+            match geometry {
+                Geometry::Mesh(mesh) => {
+                    vertices += mesh.positions.len();
+                    elements += mesh.triangles.len();
+                }
+                Geometry::Lines { points, segments } => {
+                    vertices += points.len();
+                    elements += segments.len();
+                }
+            }
+        }
+
+        writeln!(out, "ply")?;
+        writeln!(out, "format binary_little_endian 1.0")?;
+        writeln!(out, "comment written by gcode2obj")?;
+        writeln!(out, "element vertex {vertices}")?;
+        writeln!(out, "property float x")?;
+        writeln!(out, "property float y")?;
+        writeln!(out, "property float z")?;
+        if tube.is_some() {
+            writeln!(out, "property float nx")?;
+            writeln!(out, "property float ny")?;
+            writeln!(out, "property float nz")?;
+        }
+        writeln!(out, "property int object")?;
+        if tube.is_some() {
+            writeln!(out, "element face {elements}")?;
+            writeln!(out, "property list uchar uint vertex_indices")?;
+        } else {
+            writeln!(out, "element edge {elements}")?;
+            writeln!(out, "property uint vertex1")?;
+            writeln!(out, "property uint vertex2")?;
+        }
+        writeln!(out, "end_header")?;
+
+        // Vertices.
+        for (slot_id, geometry) in &objects {
+            // Slot ids are small, saturate rather than fail.
+            let object = i32::try_from(*slot_id)
+                .unwrap_or(if *slot_id < 0 { i32::MIN } else { i32::MAX })
+                .to_le_bytes();
+            match geometry {
+                Geometry::Mesh(mesh) => {
+                    for (position, normal) in mesh.positions.iter().zip(&mesh.normals) {
+                        for value in position.iter().chain(normal) {
+                            out.write_all(&to_f32(*value).to_le_bytes())?;
+                        }
+                        out.write_all(&object)?;
+                    }
+                }
+                Geometry::Lines { points, .. } => {
+                    for point in points {
+                        for value in point {
+                            out.write_all(&to_f32(*value).to_le_bytes())?;
+                        }
+                        out.write_all(&object)?;
+                    }
+                }
+            }
+        }
+
+        // Faces or edges, indices are global.
+        let mut offset = 0;
+        for (_, geometry) in &objects {
+            match geometry {
+                Geometry::Mesh(mesh) => {
+                    for triangle in &mesh.triangles {
+                        out.write_all(&[3])?;
+                        for index in triangle {
+                            out.write_all(&to_u32(offset + index)?.to_le_bytes())?;
+                        }
+                    }
+                    offset += mesh.positions.len();
+                }
+                Geometry::Lines { points, segments } => {
+                    for segment in segments {
+                        for index in segment {
+                            out.write_all(&to_u32(offset + index)?.to_le_bytes())?;
+                        }
+                    }
+                    offset += points.len();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edges() {
+        let obj = ["G1 X0 Y0 Z0.2", "G1 X10 E1", "G1 Y10 E2"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Obj>();
+        let mut out = vec![];
+        obj.write_ply(&mut out, None).unwrap();
+
+        let end = b"end_header\n";
+        let split = out
+            .windows(end.len())
+            .position(|w| w == end)
+            .expect("missing header")
+            + end.len();
+        let header = String::from_utf8(out[..split].to_vec()).unwrap();
+        assert!(header.contains("element vertex 3\n"));
+        assert!(header.contains("element edge 2\n"));
+        // 3 vertices of x, y, z and object; 2 edges of 2 indices.
+        assert_eq!(out.len() - split, 3 * 16 + 2 * 8);
+
+        // The Blender transform swaps Y and Z, as in the OBJ output.
+        let mut obj = obj;
+        obj.apply_blender_transform = true;
+        let mut swapped = vec![];
+        obj.write_ply(&mut swapped, None).unwrap();
+        let vertex = |out: &[u8]| -> Vec<f32> {
+            out[split..split + 12]
+                .chunks(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect()
+        };
+        assert_eq!(vertex(&out), [0_f32, 0_f32, 0.2]);
+        assert_eq!(vertex(&swapped), [0_f32, 0.2, 0_f32]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Binary STL export.
+//!
+//! STL has no notion of objects, lines or vertex normals. Every object is
+//! swept into a solid mesh, and the meshes are merged into one solid.
+
+use std::io;
+use std::io::Write;
+
+use super::Geometry;
+use super::Obj;
+use super::to_f32;
+use super::to_u32;
+use crate::tube::Tube;
+
+/// The 80 byte header, which must not start with "solid".
+const HEADER: &[u8] = b"binary STL written by gcode2obj";
+
+impl Obj {
+    /// Writes a binary STL file, sweeping the tube's profile along each
+    /// extrusion.
+    ///
+    /// # Errors
+    ///   When writing fails, or the mesh has more than `u32::MAX` triangles.
+    pub fn write_stl<W: Write>(&self, out: &mut W, tube: &Tube) -> io::Result<()> {
+        let meshes = self
+            .geometry(Some(tube))
+            .into_iter()
+            .filter_map(|(_, geometry)| match geometry {
+                Geometry::Mesh(mesh) => Some(mesh),
+                Geometry::Lines { .. } => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut header = [0_u8; 80];
+        header[..HEADER.len()].copy_from_slice(HEADER);
+        out.write_all(&header)?;
+        let count = meshes.iter().map(|mesh| mesh.triangles.len()).sum();
+        out.write_all(&to_u32(count)?.to_le_bytes())?;
+
+        for mesh in &meshes {
+            for [a, b, c] in &mesh.triangles {
+                let [a, b, c] = [mesh.positions[*a], mesh.positions[*b], mesh.positions[*c]];
+                for v in [face_normal(a, b, c), a, b, c] {
+                    for value in v {
+                        out.write_all(&to_f32(value).to_le_bytes())?;
+                    }
+                }
+                // Attribute byte count, unused.
+                out.write_all(&[0, 0])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The unit normal of an anti-clockwise triangle, zero when degenerate.
+fn face_normal(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> [f64; 3] {
+    let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let normal = [
+        ab[1].mul_add(ac[2], -ab[2] * ac[1]),
+        ab[2].mul_add(ac[0], -ab[0] * ac[2]),
+        ab[0].mul_add(ac[1], -ab[1] * ac[0]),
+    ];
+    let len = normal[0].hypot(normal[1]).hypot(normal[2]);
+    if len > 0_f64 {
+        [normal[0] / len, normal[1] / len, normal[2] / len]
+    } else {
+        [0_f64; 3]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tube::Profile;
+
+    #[test]
+    fn size() {
+        let obj = ["G1 X0 Y0 Z0.2", "G1 X10 E1", "G1 Y10 E2"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Obj>();
+        let tube = Tube {
+            profile: Profile::Rectangle,
+            width: 0.45,
+            height: 0.2,
+        };
+        let mut out = vec![];
+        obj.write_stl(&mut out, &tube).unwrap();
+
+        let count = u32::from_le_bytes(out[80..84].try_into().unwrap());
+        assert!(count > 0);
+        assert_eq!(out.len(), 84 + 50 * count as usize);
+        assert!(!out.starts_with(b"solid"));
+    }
+}
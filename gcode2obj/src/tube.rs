@@ -0,0 +1,307 @@
+//! Sweeps a profile along each extrusion, to make a solid mesh.
+//!
+//! The nozzle sits on top of the extruded bead, so the profile hangs
+//! below the path of the nozzle. Extrusions are assumed to be close to
+//! horizontal, the profile is always upright.
+//!
+//! At each corner the profile is mitered, so consecutive segments join
+//! without gaps.
+
+/// Points closer than this (in XY) are merged, the direction of travel
+/// between them is undefined.
+const MIN_SEGMENT: f64 = 1e-6;
+
+/// Limits the growth of the profile at sharp corners.
+const MAX_MITER: f64 = 2_f64;
+
+/// Points on each semicircular end of a capsule profile.
+const CAPSULE_STEPS: u32 = 4;
+
+/// The cross section of an extrusion.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Profile {
+    /// Flat top, bottom and sides.
+    #[default]
+    Rectangle,
+    /// Flat top and bottom, with rounded sides.
+    Capsule,
+}
+
+/// Settings used to convert polylines into a solid mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tube {
+    /// The cross section of an extrusion.
+    pub profile: Profile,
+    /// Extrusion width in mm, unless given by a ";WIDTH:" comment.
+    pub width: f64,
+    /// Layer height in mm, unless given by a ";HEIGHT:" comment.
+    pub height: f64,
+}
+
+/// A triangle mesh, with a normal for each vertex.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    /// Vertex positions.
+    pub positions: Vec<[f64; 3]>,
+    /// Unit normals, one per position.
+    pub normals: Vec<[f64; 3]>,
+    /// Indices into positions, anti-clockwise when viewed from outside.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+// A point on the profile, and its outward normal.
+//
+// 'a' is measured to the right of the direction of travel, 'b' upwards.
+#[derive(Clone, Copy, Debug)]
+struct ProfilePoint {
+    a: f64,
+    b: f64,
+    normal_a: f64,
+    normal_b: f64,
+}
+
+// The profile's points, ordered anti-clockwise when looking back along
+// the direction of travel. Each strip is a pair of points joined by a
+// face of the tube.
+struct Section {
+    points: Vec<ProfilePoint>,
+    strips: Vec<(usize, usize)>,
+}
+
+impl Section {
+    fn new(profile: Profile, width: f64, height: f64) -> Self {
+        let half_width = width / 2_f64;
+        let half_height = height / 2_f64;
+        match profile {
+            Profile::Rectangle => {
+                // Each side has its own pair of points, so the corners are sharp.
+                let corners = [
+                    (half_width, -half_height),
+                    (half_width, half_height),
+                    (-half_width, half_height),
+                    (-half_width, -half_height),
+                ];
+                let normals = [
+                    (1_f64, 0_f64),
+                    (0_f64, 1_f64),
+                    (-1_f64, 0_f64),
+                    (0_f64, -1_f64),
+                ];
+                let mut points = Vec::with_capacity(8);
+                for (i, (normal_a, normal_b)) in normals.into_iter().enumerate() {
+                    for (a, b) in [corners[i], corners[(i + 1) % 4]] {
+                        points.push(ProfilePoint {
+                            a,
+                            b,
+                            normal_a,
+                            normal_b,
+                        });
+                    }
+                }
+                let strips = (0..4).map(|i| (2 * i, 2 * i + 1)).collect();
+                Self { points, strips }
+            }
+            Profile::Capsule => {
+                // Semicircular ends, squashed into an ellipse when the
+                // extrusion is narrower than it is high.
+                let radius = half_width.min(half_height);
+                let flat = half_width - radius;
+                let mut points = Vec::with_capacity(2 * (CAPSULE_STEPS as usize + 1));
+                for (center, start) in [(flat, -0.5_f64), (-flat, 0.5_f64)] {
+                    for step in 0..=CAPSULE_STEPS {
+                        let theta = (start + f64::from(step) / f64::from(CAPSULE_STEPS))
+                            * core::f64::consts::PI;
+                        let (sin, cos) = theta.sin_cos();
+                        let normal_a = cos * half_height;
+                        let normal_b = sin * radius;
+                        let len = normal_a.hypot(normal_b);
+                        points.push(ProfilePoint {
+                            a: radius.mul_add(cos, center),
+                            b: half_height * sin,
+                            normal_a: normal_a / len,
+                            normal_b: normal_b / len,
+                        });
+                    }
+                }
+                let n = points.len();
+                let strips = (0..n).map(|i| (i, (i + 1) % n)).collect();
+                Self { points, strips }
+            }
+        }
+    }
+}
+
+impl Tube {
+    /// Appends the mesh of a single extrusion to `mesh`.
+    ///
+    /// `points` is the path of the nozzle.
+    pub fn sweep(&self, points: &[[f64; 3]], width: f64, height: f64, mesh: &mut Mesh) {
+        let mut path: Vec<[f64; 3]> = Vec::with_capacity(points.len());
+        for p in points {
+            if path
+                .last()
+                .is_none_or(|last| (p[0] - last[0]).hypot(p[1] - last[1]) > MIN_SEGMENT)
+            {
+                path.push(*p);
+            }
+        }
+        if path.len() < 2 {
+            return;
+        }
+
+        let section = Section::new(self.profile, width, height);
+        let ring = section.points.len();
+
+        // A horizontal unit vector, to the right of each segment.
+        let sides = path
+            .windows(2)
+            .map(|w| {
+                let dx = w[1][0] - w[0][0];
+                let dy = w[1][1] - w[0][1];
+                let len = dx.hypot(dy);
+                [dy / len, -dx / len]
+            })
+            .collect::<Vec<_>>();
+
+        // The rings of vertices, one per point of the path.
+        let first = mesh.positions.len();
+        for (k, p) in path.iter().enumerate() {
+            let (side, scale) = joint(&sides, k);
+            for point in &section.points {
+                let a = point.a * scale;
+                mesh.positions.push([
+                    side[0].mul_add(a, p[0]),
+                    side[1].mul_add(a, p[1]),
+                    p[2] - height / 2_f64 + point.b,
+                ]);
+                mesh.normals.push(normalize([
+                    side[0] * point.normal_a,
+                    side[1] * point.normal_a,
+                    point.normal_b,
+                ]));
+            }
+        }
+
+        // The sides of the tube.
+        for k in 0..path.len() - 1 {
+            let this = first + k * ring;
+            let next = this + ring;
+            for &(i, j) in &section.strips {
+                mesh.triangles.push([this + i, next + j, this + j]);
+                mesh.triangles.push([this + i, next + i, next + j]);
+            }
+        }
+
+        // The end caps, facing backwards at the start and forwards at the end.
+        let last = path.len() - 1;
+        for (k, side, facing) in [(0, sides[0], -1_f64), (last, sides[last - 1], 1_f64)] {
+            let tangent = [-side[1] * facing, side[0] * facing, 0_f64];
+            let center = mesh.positions.len();
+            let p = path[k];
+            mesh.positions.push([p[0], p[1], p[2] - height / 2_f64]);
+            mesh.normals.push(tangent);
+            for i in 0..ring {
+                mesh.positions.push(mesh.positions[first + k * ring + i]);
+                mesh.normals.push(tangent);
+            }
+            for &(i, j) in &section.strips {
+                if facing < 0_f64 {
+                    mesh.triangles
+                        .push([center, center + 1 + i, center + 1 + j]);
+                } else {
+                    mesh.triangles
+                        .push([center, center + 1 + j, center + 1 + i]);
+                }
+            }
+        }
+    }
+}
+
+// Returns the direction of the profile at the k'th point of the path,
+// and how much it must be stretched to keep the width of the extrusion.
+fn joint(sides: &[[f64; 2]], k: usize) -> ([f64; 2], f64) {
+    if k == 0 {
+        return (sides[0], 1_f64);
+    }
+    if k == sides.len() {
+        return (sides[k - 1], 1_f64);
+    }
+    let sum = [sides[k - 1][0] + sides[k][0], sides[k - 1][1] + sides[k][1]];
+    let len = sum[0].hypot(sum[1]);
+    if len < MIN_SEGMENT {
+        // Reversing direction.
+        return (sides[k], 1_f64);
+    }
+    let side = [sum[0] / len, sum[1] / len];
+    let cos = side[0].mul_add(sides[k][0], side[1] * sides[k][1]);
+    (side, (1_f64 / cos).min(MAX_MITER))
+}
+
+fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let len = v[0].hypot(v[1]).hypot(v[2]);
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+        [
+            a[1].mul_add(b[2], -a[2] * b[1]),
+            a[2].mul_add(b[0], -a[0] * b[2]),
+            a[0].mul_add(b[1], -a[1] * b[0]),
+        ]
+    }
+
+    fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+        a[2].mul_add(b[2], a[0].mul_add(b[0], a[1] * b[1]))
+    }
+
+    #[test]
+    fn outward_faces() {
+        // The winding of every face agrees with its vertex normals.
+        for profile in [Profile::Rectangle, Profile::Capsule] {
+            let tube = Tube {
+                profile,
+                width: 0.45,
+                height: 0.2,
+            };
+            let mut mesh = Mesh::default();
+            let path = [[0.0, 0.0, 0.2], [10.0, 0.0, 0.2], [10.0, 10.0, 0.2]];
+            tube.sweep(&path, 0.45, 0.2, &mut mesh);
+
+            assert_eq!(mesh.positions.len(), mesh.normals.len());
+            for [a, b, c] in &mesh.triangles {
+                let face = cross(
+                    sub(mesh.positions[*b], mesh.positions[*a]),
+                    sub(mesh.positions[*c], mesh.positions[*a]),
+                );
+                // Ignore degenerate triangles.
+                if dot(face, face) > 1e-12 {
+                    assert!(dot(face, mesh.normals[*a]) > 0.0, "{profile:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        // The bead hangs below the nozzle.
+        let tube = Tube {
+            profile: Profile::Rectangle,
+            width: 0.4,
+            height: 0.2,
+        };
+        let mut mesh = Mesh::default();
+        tube.sweep(&[[0.0, 0.0, 0.4], [10.0, 0.0, 0.4]], 0.4, 0.2, &mut mesh);
+        let min_z = mesh.positions.iter().map(|p| p[2]).fold(f64::MAX, f64::min);
+        let max_y = mesh.positions.iter().map(|p| p[1]).fold(f64::MIN, f64::max);
+        assert!((min_z - 0.2).abs() < 1e-9);
+        assert!((max_y - 0.2).abs() < 1e-9);
+    }
+}
@@ -5,32 +5,175 @@
 //! Pass a gcode file into stdin a obj file will be output to `StdOut`
 //!
 use std::fs::File;
+use std::fs::create_dir_all;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::BufWriter;
 use std::io::Read;
+use std::io::Write;
 use std::io::stdin;
+use std::io::stdout;
 use std::path::PathBuf;
 
 use clap::Parser;
+use clap::ValueEnum;
+use gcode_nom::binary::gcode_block::decode::decode_blocks;
 use gcode_nom::binary::gcode_block::extractor::extract_gcode;
-use gcode_nom::binary::gcode_block::svg::Svg;
-use gcode_nom::binary::inflate::decompress_data_block;
+use gcode_nom::binary::gcode_block::svg::group::GroupBy;
+use gcode_nom::binary::gcode_block::svg::projection::Projection;
+use gcode_nom::binary::gcode_block::svg::projection::View;
+use gcode_nom::binary::gcode_block::svg::writer::LayerSvgWriter;
+use gcode_nom::binary::gcode_block::svg::writer::SvgWriter;
+use gcode_nom::command::Command;
+use gcode_nom::layer::LayerFilter;
+use gcode_nom::tessellation::Tessellation;
 use log::info;
 
 // Occasionally want to apply Blender specific transform.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// The direction the print is viewed from.
+    #[clap(long, value_enum, default_value_t = ViewArg::Isometric)]
+    view: ViewArg,
+    /// Custom view: rotation of the print about the Z axis, in degrees.
+    #[clap(long, default_value_t = 0_f64, allow_negative_numbers = true)]
+    rotate_z: f64,
+    /// Custom view: angle of the viewer above the bed, in degrees.
+    ///
+    /// 90 is a top down view.
+    #[clap(long, default_value_t = 45_f64, allow_negative_numbers = true)]
+    elevation: f64,
+    /// Scale factor applied to all coordinates.
+    #[clap(long, default_value_t = 1_f64)]
+    scale: f64,
+    /// Draw a single layer, layers are numbered from 0.
+    #[clap(long, conflicts_with_all = ["z_min", "z_max"])]
+    layer: Option<usize>,
+    /// Draw only the layers at or above this height.
+    #[clap(long)]
+    z_min: Option<f64>,
+    /// Draw only the layers at or below this height.
+    #[clap(long)]
+    z_max: Option<f64>,
+    /// Write one SVG per layer into this directory, rather than to `StdOut`.
+    ///
+    /// Files are named by layer number, `layer_0000.svg`, `layer_0001.svg` ...
+    #[clap(long)]
+    per_layer: Option<PathBuf>,
+    /// Split the drawing into colour coded groups, with a legend.
+    #[clap(long, value_enum, default_value_t = GroupByArg::None)]
+    group_by: GroupByArg,
+    /// Group by speed: the width of each speed range, in mm/s.
+    #[clap(long, default_value_t = 10_f64, value_parser = positive)]
+    speed_bucket: f64,
+    /// Draw travel moves as grey dashed lines.
+    #[clap(long)]
+    travel: bool,
+    /// Arcs are split into segments which stray at most this far from the arc, in mm.
+    #[clap(long, default_value_t = Tessellation::default().chord_tolerance, value_parser = positive)]
+    arc_tolerance: f64,
+    /// The largest angle an arc segment may sweep, in degrees.
+    #[clap(long)]
+    arc_max_angle: Option<f64>,
     /// Name of the file to convert.
     file: Option<PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ViewArg {
+    /// Plan view of the XY plane.
+    Top,
+    /// The XZ plane.
+    Front,
+    /// The YZ plane.
+    Side,
+    /// View from above the front right corner of the bed.
+    Isometric,
+    /// Set by --rotate-z and --elevation.
+    Custom,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum GroupByArg {
+    /// A single path.
+    None,
+    /// By M486 object.
+    Object,
+    /// By the slicer's feature type (perimeter, infill, support ...).
+    Feature,
+    /// By tool (T0, T1 ...).
+    Tool,
+    /// By speed, see --speed-bucket.
+    Speed,
+}
+
+impl Args {
+    const fn projection(&self) -> Projection {
+        let view = match self.view {
+            ViewArg::Top => View::Top,
+            ViewArg::Front => View::Front,
+            ViewArg::Side => View::Side,
+            ViewArg::Isometric => View::Isometric,
+            ViewArg::Custom => View::Custom {
+                rotate_z: self.rotate_z,
+                elevation: self.elevation,
+            },
+        };
+        Projection::new(view).with_scale(self.scale)
+    }
+
+    const fn group_by(&self) -> GroupBy {
+        match self.group_by {
+            GroupByArg::None => GroupBy::None,
+            GroupByArg::Object => GroupBy::Object,
+            GroupByArg::Feature => GroupBy::Feature,
+            GroupByArg::Tool => GroupBy::Tool,
+            GroupByArg::Speed => GroupBy::Speed {
+                bucket: self.speed_bucket,
+            },
+        }
+    }
+
+    fn tessellation(&self) -> Tessellation {
+        Tessellation {
+            chord_tolerance: self.arc_tolerance,
+            max_angle: self.arc_max_angle.map(f64::to_radians),
+        }
+    }
+
+    fn filter(&self) -> LayerFilter {
+        self.layer.map_or_else(
+            || {
+                if self.z_min.is_some() || self.z_max.is_some() {
+                    LayerFilter::ZRange {
+                        min: self.z_min.unwrap_or(f64::NEG_INFINITY),
+                        max: self.z_max.unwrap_or(f64::INFINITY),
+                    }
+                } else {
+                    LayerFilter::All
+                }
+            },
+            LayerFilter::Index,
+        )
+    }
+}
+
+// Parses a number greater than zero.
+fn positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0_f64 && number.is_finite() => Ok(number),
+        Ok(_) => Err("must be greater than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 fn main() -> std::io::Result<()> {
     env_logger::init();
 
     let args = Args::parse();
 
-    if let Some(file) = args.file {
+    if let Some(file) = &args.file {
         info!("File: {}", file.display());
         if file.exists() {
             if let Some(ext) = file.extension() {
@@ -38,8 +181,7 @@ fn main() -> std::io::Result<()> {
                     info!("Reading gcode file");
                     let file = File::open(file)?;
                     let buffer = BufReader::new(file);
-                    let svg = buffer.lines().map(|l| l.unwrap()).collect::<Svg>();
-                    println!("{svg}");
+                    write_svg(buffer.lines().map(|l| parse(&l.unwrap())), &args)?;
                 } else if ext == "bgcode" {
                     info!("Reading bgcode file");
                     let file = File::open(file)?;
@@ -50,22 +192,9 @@ fn main() -> std::io::Result<()> {
                         match extract_gcode(&buffer) {
                             Ok((_remain, gcode_blocks)) => {
                                 log::info!("parser succeeded: Valid input");
-                                let svg = &gcode_blocks
-                                    .iter()
-                                    .map(|gcode| {
-                                        let (_remain, data) = decompress_data_block(
-                                            gcode.data,
-                                            &gcode.param.encoding,
-                                            &gcode.header,
-                                        )
-                                        .expect("fail to decompress data block");
-                                        String::from_utf8_lossy(&data).to_string()
-                                    })
-                                    .collect::<String>()
-                                    .lines()
-                                    .map(std::string::ToString::to_string)
-                                    .collect::<Svg>();
-                                println!("{svg}");
+                                let commands = decode_blocks(&gcode_blocks)
+                                    .expect("fail to decompress data block");
+                                write_svg(commands, &args)?;
                             }
                             Err(e) => {
                                 log::error!("Unhandled error decoding file {e}");
@@ -84,9 +213,51 @@ fn main() -> std::io::Result<()> {
         }
     } else {
         info!("Reading from stdin");
-        let svg = stdin().lock().lines().map(|l| l.unwrap()).collect::<Svg>();
-        println!("{svg}");
+        write_svg(stdin().lock().lines().map(|l| parse(&l.unwrap())), &args)?;
     }
 
     Ok(())
 }
+
+fn parse(line: &str) -> Command {
+    let (_, command) = Command::parse_line(line).expect("Command is not parsable");
+    command
+}
+
+// Streams the SVG to `StdOut`, or a series of files one per layer.
+fn write_svg<I>(commands: I, args: &Args) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = Command>,
+{
+    if let Some(dir) = &args.per_layer {
+        create_dir_all(dir)?;
+        let mut writer = LayerSvgWriter::new(args.projection(), |layer| {
+            let path = dir.join(format!("layer_{:04}.svg", layer.index));
+            info!(
+                "Writing layer {} (Z={}) to {}",
+                layer.index,
+                layer.z,
+                path.display()
+            );
+            Ok(BufWriter::new(File::create(path)?))
+        })
+        .with_filter(args.filter())
+        .with_group_by(args.group_by())
+        .with_travel(args.travel)
+        .with_tessellation(args.tessellation());
+        writer.write_commands(commands)?;
+        let count = writer.finish()?;
+        info!("Wrote {count} layers");
+        return Ok(());
+    }
+
+    let mut writer = SvgWriter::with_projection(BufWriter::new(stdout().lock()), args.projection())
+        .with_filter(args.filter())
+        .with_group_by(args.group_by())
+        .with_travel(args.travel)
+        .with_tessellation(args.tessellation());
+    writer.write_commands(commands)?;
+    let mut out = writer.finish()?;
+    writeln!(out)?;
+    out.flush()
+}
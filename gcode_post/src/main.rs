@@ -0,0 +1,314 @@
+//! `gcode_post`
+//!
+//! Post-processing tools which rewrite a G-code file.
+//!
+//! The input is a .gcode or .bgcode file, or `StdIn`. The rewritten G-code
+//! is sent to `StdOut`, as text, except for `cancel` and `pause` which
+//! keep a .bgcode file binary.
+//!
+//! cargo run --release -- cancel --object 1 ../assets/both.gcode > one.gcode
+//! cargo run --release -- arcs ../assets/3DBenchy.gcode > benchy.gcode
+//! cargo run --release -- pause --z 5 ../assets/benchy2-mk4s.bgcode > swap.bgcode
+//! cargo run --release -- resume --layer 120 ../assets/3DBenchy.gcode > resume.gcode
+//! cargo run --release -- transform --mirror-x --center-x 125 ../assets/3DBenchy.gcode > mirrored.gcode
+//!
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::io::stdin;
+use std::io::stdout;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Parser;
+use clap::Subcommand;
+use gcode_nom::arc_fit::ArcFitter;
+use gcode_nom::binary::gcode_block::decode::decode_text;
+use gcode_nom::binary::gcode_block::extractor::extract_gcode;
+use gcode_nom::cancel::Canceller;
+use gcode_nom::layer::LayerFilter;
+use gcode_nom::pause::Pauser;
+use gcode_nom::pause::find_layer;
+use gcode_nom::resume::Resumer;
+use gcode_nom::transform::Transform;
+use gcode_nom::transform::Transformer;
+use log::info;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    tool: Tool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Tool {
+    /// Turns the extrusions of the selected objects into travel moves.
+    ///
+    /// Objects are selected by M486 id, or by name. Klipper's objects are
+    /// numbered in order of appearance, starting at 0.
+    ///
+    /// A .bgcode file stays binary, only the blocks holding a cancelled
+    /// object are rewritten.
+    Cancel {
+        /// The M486 id of an object to cancel, may be repeated.
+        #[clap(long = "object", allow_negative_numbers = true)]
+        objects: Vec<i128>,
+        /// The name of an object to cancel, may be repeated.
+        #[clap(long = "name")]
+        names: Vec<String>,
+        /// Name of the file to rewrite.
+        file: Option<PathBuf>,
+    },
+    /// Compresses runs of short extrusions into G2/G3 arcs.
+    Arcs {
+        /// The maximum distance between an arc and the moves it replaces, in mm.
+        #[clap(long, default_value_t = 0.025)]
+        tolerance: f64,
+        /// Larger arcs are left as lines, in mm.
+        #[clap(long, default_value_t = 1000.0)]
+        max_radius: f64,
+        /// The fewest moves replaced by an arc.
+        #[clap(long, default_value_t = 3)]
+        min_segments: usize,
+        /// Name of the file to rewrite.
+        file: Option<PathBuf>,
+    },
+    /// Inserts a filament change, or a pause, where a layer starts.
+    ///
+    /// A .bgcode file stays binary, only the block holding the change is
+    /// rewritten.
+    Pause {
+        /// The number of the layer, layers are numbered from 0.
+        #[clap(long, required_unless_present = "z", conflicts_with = "z")]
+        layer: Option<usize>,
+        /// Pause at the first layer at or above this height, in mm.
+        #[clap(long)]
+        z: Option<f64>,
+        /// A line of G-code to insert, may be repeated.
+        #[clap(long, default_value = "M600")]
+        gcode: Vec<String>,
+        /// Name of the file to rewrite.
+        file: Option<PathBuf>,
+    },
+    /// Resumes a failed print, from a layer or a line.
+    ///
+    /// The start G-code is kept, without its moves. The head is raised,
+    /// moved over the resume point and lowered onto the part.
+    Resume {
+        /// The number of the layer, layers are numbered from 0.
+        #[clap(long, required_unless_present = "line", conflicts_with = "line")]
+        layer: Option<usize>,
+        /// The number of the line, counting from 1.
+        #[clap(long)]
+        line: Option<usize>,
+        /// Home Z, and level the bed, as the start G-code does.
+        ///
+        /// Only safe when the probe cannot reach the part.
+        #[clap(long)]
+        home_z: bool,
+        /// The height the head is raised above the part, in mm.
+        #[clap(long, default_value_t = 5.0)]
+        clearance: f64,
+        /// Name of the file to resume.
+        file: Option<PathBuf>,
+    },
+    /// Moves, rotates, scales or mirrors the print.
+    ///
+    /// The print is scaled, mirrored and rotated about the center, then
+    /// moved. E and F are left unchanged.
+    Transform(TransformArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct TransformArgs {
+    /// Moves the print along X, in mm.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    dx: f64,
+    /// Moves the print along Y, in mm.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    dy: f64,
+    /// Moves the print along Z, in mm.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    dz: f64,
+    /// Turns the print counter-clockwise, in degrees.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    rotate: f64,
+    /// Scales the print along every axis.
+    #[clap(long, default_value_t = 1.0)]
+    scale: f64,
+    /// Reverses the X axis.
+    #[clap(long)]
+    mirror_x: bool,
+    /// Reverses the Y axis.
+    #[clap(long)]
+    mirror_y: bool,
+    /// The X of the point to scale, mirror and rotate about, in mm.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    center_x: f64,
+    /// The Y of the point to scale, mirror and rotate about, in mm.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    center_y: f64,
+    /// Name of the file to rewrite.
+    file: Option<PathBuf>,
+}
+
+impl TransformArgs {
+    /// Scales, mirrors and rotates about the center, then moves.
+    fn transform(&self) -> Transform {
+        let mut transform = Transform::scale(self.scale);
+        if self.mirror_x {
+            transform = transform.then(Transform::mirror_x());
+        }
+        if self.mirror_y {
+            transform = transform.then(Transform::mirror_y());
+        }
+        transform
+            .then(Transform::rotate(self.rotate))
+            .about([self.center_x, self.center_y])
+            .then(Transform::translate(self.dx, self.dy, self.dz))
+    }
+}
+
+/// Returns the text of a .gcode or .bgcode file, or of `StdIn`.
+fn read_gcode(file: Option<&Path>) -> std::io::Result<String> {
+    let Some(file) = file else {
+        info!("Reading from stdin");
+        let mut text = String::new();
+        stdin().lock().read_to_string(&mut text)?;
+        return Ok(text);
+    };
+
+    info!("File: {}", file.display());
+    let mut buffer = vec![];
+    File::open(file)?.read_to_end(&mut buffer)?;
+    match file.extension() {
+        Some(ext) if ext == "gcode" => Ok(String::from_utf8_lossy(&buffer).into_owned()),
+        Some(ext) if ext == "bgcode" => {
+            let (_remain, blocks) = extract_gcode(&buffer).map_err(|e| {
+                log::error!("Unhandled error decoding file {e}");
+                std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+            })?;
+            decode_text(&blocks)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{e:?}")))
+        }
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "File extension is not supported",
+        )),
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let mut out = BufWriter::new(stdout().lock());
+    match args.tool {
+        Tool::Cancel {
+            objects,
+            names,
+            file,
+        } => {
+            let mut canceller = Canceller::default();
+            canceller.ids.extend(objects);
+            canceller.names.extend(names);
+            match file {
+                Some(file) if file.extension().is_some_and(|ext| ext == "bgcode") => {
+                    let mut buffer = vec![];
+                    File::open(file)?.read_to_end(&mut buffer)?;
+                    out.write_all(&canceller.rewrite_bgcode(&buffer)?)?;
+                }
+                _ => {
+                    let text = read_gcode(file.as_deref())?;
+                    for line in text.lines() {
+                        canceller.write_line(line, &mut out)?;
+                    }
+                }
+            }
+        }
+        Tool::Arcs {
+            tolerance,
+            max_radius,
+            min_segments,
+            file,
+        } => {
+            let text = read_gcode(file.as_deref())?;
+            let mut fitter = ArcFitter::default();
+            fitter.tolerance = tolerance;
+            fitter.max_radius = max_radius;
+            fitter.min_segments = min_segments;
+            for line in text.lines() {
+                fitter.write_line(line, &mut out)?;
+            }
+            fitter.finish(&mut out)?;
+        }
+        Tool::Pause {
+            layer,
+            z,
+            gcode,
+            file,
+        } => {
+            let text = read_gcode(file.as_deref())?;
+            let filter = match (layer, z) {
+                (Some(index), _) => LayerFilter::Index(index),
+                (None, min) => LayerFilter::ZRange {
+                    min: min.unwrap_or_default(),
+                    max: f64::INFINITY,
+                },
+            };
+            let line = find_layer(text.lines(), filter).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "The layer was not found")
+            })?;
+            let mut pauser = Pauser::new(line);
+            pauser.gcode = gcode;
+            match file {
+                Some(file) if file.extension().is_some_and(|ext| ext == "bgcode") => {
+                    let mut buffer = vec![];
+                    File::open(file)?.read_to_end(&mut buffer)?;
+                    out.write_all(&pauser.rewrite_bgcode(&buffer)?)?;
+                }
+                _ => {
+                    for line in text.lines() {
+                        pauser.write_line(line, &mut out)?;
+                    }
+                }
+            }
+        }
+        Tool::Resume {
+            layer,
+            line,
+            home_z,
+            clearance,
+            file,
+        } => {
+            let text = read_gcode(file.as_deref())?;
+            let line = match (layer, line) {
+                (Some(index), _) => find_layer(text.lines(), LayerFilter::Index(index)),
+                (None, line) => line.and_then(|line| line.checked_sub(1)),
+            }
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "The layer, or line, was not found",
+                )
+            })?;
+            let resumer = Resumer {
+                home_z,
+                clearance,
+                ..Resumer::default()
+            };
+            resumer.write(&text, line, &mut out)?;
+        }
+        Tool::Transform(args) => {
+            let text = read_gcode(args.file.as_deref())?;
+            let mut transformer = Transformer::new(args.transform());
+            for line in text.lines() {
+                transformer.write_line(line, &mut out)?;
+            }
+        }
+    }
+    out.flush()
+}
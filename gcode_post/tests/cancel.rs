@@ -0,0 +1,69 @@
+//! Runs `gcode_post cancel` on a small multipart file.
+
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+// Two objects, one move and one extrusion each.
+const INPUT: &str = "\
+M486 S0
+M486 ABlock
+M486 S-1
+M486 S1
+M486 ACone
+M486 S-1
+G90
+M83
+G1 Z0.2 F720
+M486 S0
+G1 X10 Y10 F6000
+G1 X20 Y10 E0.5 F1200 ; block
+M486 S-1
+M486 S1
+G1 X40 Y10 F6000
+G1 X50 Y10 E0.5 F1200 ; cone
+M486 S-1
+";
+
+fn cancel(args: &[&str]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gcode_post"))
+        .arg("cancel")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(INPUT.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn by_id() {
+    // Only the cone's extrusion is dropped, its move is kept at travel speed.
+    let expected = INPUT.replace("G1 X50 Y10 E0.5 F1200 ; cone", "G1 X50 Y10 ; cone");
+    assert_eq!(cancel(&["--object", "1"]), expected);
+}
+
+#[test]
+fn by_name() {
+    let expected = INPUT.replace("G1 X20 Y10 E0.5 F1200 ; block", "G1 X20 Y10 ; block");
+    assert_eq!(cancel(&["--name", "Block"]), expected);
+}
+
+#[test]
+fn bgcode() {
+    // A binary file is written back out as a binary file.
+    let output = Command::new(env!("CARGO_BIN_EXE_gcode_post"))
+        .args(["cancel", "--object", "0", "../assets/both parts.bgcode"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"GCDE"));
+}
@@ -0,0 +1,492 @@
+//! `gcode_report`
+//!
+//! Reports on a G-code file, the report is sent to `StdOut`.
+//!
+//! The input is a .gcode or .bgcode file, or `StdIn`.
+//!
+//! ```bash
+//! cargo run --release -- time --layers ../assets/mini_cube_b.bgcode
+//! ```
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::io::stdin;
+use std::io::stdout;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Parser;
+use clap::Subcommand;
+use gcode_nom::binary::bgcode_parser;
+use gcode_nom::binary::gcode_block::decode::decode_blocks;
+use gcode_nom::bounds::BoundsChecker;
+use gcode_nom::bounds::PrintVolume;
+use gcode_nom::bounds::parse_bed_shape;
+use gcode_nom::command::Command;
+use gcode_nom::estimate::Estimator;
+use gcode_nom::estimate::SLICER_ESTIMATE_KEY;
+use gcode_nom::estimate::parse_duration;
+use gcode_nom::filament::Filament;
+use gcode_nom::filament::Usage;
+use gcode_nom::layer::segment;
+use gcode_nom::objects::BoundingBox;
+use log::info;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    report: Report,
+}
+
+#[derive(Subcommand, Debug)]
+enum Report {
+    /// Estimates the print time, and compares it with the slicer's estimate.
+    Time {
+        /// Also report the time spent on each layer.
+        #[clap(long)]
+        layers: bool,
+        /// Name of the file to inspect.
+        file: Option<PathBuf>,
+    },
+    /// Sums the filament used per tool and per object, with its mass and cost.
+    Filament {
+        /// Filament diameter in mm, overrides the file's settings.
+        #[clap(long)]
+        diameter: Option<f64>,
+        /// Filament density in g/cm^3, overrides the file's settings.
+        #[clap(long)]
+        density: Option<f64>,
+        /// Filament cost per kg, overrides the file's settings.
+        #[clap(long)]
+        cost: Option<f64>,
+        /// Name of the file to inspect.
+        file: Option<PathBuf>,
+    },
+    /// Lists the layers, with their lines and the distance moved.
+    Layers {
+        /// Name of the file to inspect.
+        file: Option<PathBuf>,
+    },
+    /// Checks that every move lies within the printable volume.
+    ///
+    /// The bed shape and maximum height are read from the file, or taken
+    /// from the printer model.
+    Bounds {
+        /// Bed shape, as a list of corners "0x0,250x0,250x210,0x210".
+        #[clap(long)]
+        bed: Option<String>,
+        /// Maximum print height in mm.
+        #[clap(long)]
+        height: Option<f64>,
+        /// Printer model, e.g. MK4S, used when the file has no bed shape.
+        #[clap(long)]
+        model: Option<String>,
+        /// Allows moves this far beyond the edges, in mm.
+        #[clap(long, default_value_t = 0_f64)]
+        margin: f64,
+        /// Name of the file to inspect.
+        file: Option<PathBuf>,
+    },
+}
+
+// The commands of a file, and the "key=value" pairs of its metadata.
+struct Input {
+    commands: Vec<Command>,
+    metadata: Vec<(String, String)>,
+}
+
+/// Reads a .gcode or .bgcode file, or `StdIn`.
+fn read_input(file: Option<&Path>) -> std::io::Result<Input> {
+    // One command per line, so the line number of a command is its index + 1.
+    let parse =
+        |line: String| Command::parse_line(&line).map_or(Command::Nop, |(_, command)| command);
+    let Some(file) = file else {
+        info!("Reading from stdin");
+        let commands = stdin()
+            .lock()
+            .lines()
+            .map_while(Result::ok)
+            .map(parse)
+            .collect();
+        return Ok(Input {
+            commands,
+            metadata: vec![],
+        });
+    };
+
+    info!("File: {}", file.display());
+    match file.extension() {
+        Some(ext) if ext == "gcode" => {
+            let commands = BufReader::new(File::open(file)?)
+                .lines()
+                .map_while(Result::ok)
+                .map(parse)
+                .collect();
+            Ok(Input {
+                commands,
+                metadata: vec![],
+            })
+        }
+        Some(ext) if ext == "bgcode" => {
+            let mut buffer = vec![];
+            File::open(file)?.read_to_end(&mut buffer)?;
+            let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+            let (_remain, bgcode) = bgcode_parser(&buffer).map_err(|e| {
+                log::error!("Unhandled error decoding file {e}");
+                invalid(e.to_string())
+            })?;
+            let commands = decode_blocks(&bgcode.gcode).map_err(|e| invalid(format!("{e:?}")))?;
+            let mut metadata = bgcode
+                .print_metadata
+                .entries()
+                .map_err(|e| invalid(format!("{e:?}")))?;
+            metadata.extend(
+                bgcode
+                    .printer_metadata
+                    .entries()
+                    .map_err(|e| invalid(format!("{e:?}")))?,
+            );
+            metadata.extend(
+                bgcode
+                    .slicer
+                    .entries()
+                    .map_err(|e| invalid(format!("{e:?}")))?,
+            );
+            Ok(Input { commands, metadata })
+        }
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "File extension is not supported",
+        )),
+    }
+}
+
+// Formats seconds in the slicer's style "1h 16m 44s".
+fn format_duration(seconds: f64) -> String {
+    let mut remain = seconds.round();
+    let mut parts = vec![];
+    for (unit, size) in [("d", 86400_f64), ("h", 3600_f64), ("m", 60_f64)] {
+        if remain >= size || !parts.is_empty() {
+            parts.push(format!("{}{unit}", (remain / size).floor()));
+            remain %= size;
+        }
+    }
+    parts.push(format!("{remain}s"));
+    parts.join(" ")
+}
+
+fn time<W>(input: &Input, layers: bool, out: &mut W) -> std::io::Result<()>
+where
+    W: Write,
+{
+    let mut estimator = Estimator::default();
+    for command in &input.commands {
+        estimator.push(command);
+    }
+    let mut estimate = estimator.finish();
+    if estimate.slicer.is_none() {
+        estimate.slicer = input
+            .metadata
+            .iter()
+            .find(|(key, _)| key == SLICER_ESTIMATE_KEY)
+            .and_then(|(_, value)| parse_duration(value));
+    }
+
+    writeln!(out, "Estimated time: {}", format_duration(estimate.total))?;
+    match estimate.slicer {
+        Some(slicer) => {
+            let difference = 100_f64 * (estimate.total - slicer) / slicer;
+            writeln!(
+                out,
+                "Slicer's estimate: {} ({difference:+.1}%)",
+                format_duration(slicer)
+            )?;
+        }
+        None => writeln!(out, "Slicer's estimate: not found")?,
+    }
+
+    if layers {
+        writeln!(out)?;
+        writeln!(out, "layer\tz\ttime")?;
+        for layer in &estimate.layers {
+            writeln!(
+                out,
+                "{}\t{}\t{}",
+                layer.layer.index,
+                layer.layer.z,
+                format_duration(layer.time)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn filament<W>(input: Input, overrides: &Filament, out: &mut W) -> std::io::Result<()>
+where
+    W: Write,
+{
+    let mut usage = input.commands.into_iter().collect::<Usage>();
+    for (key, value) in &input.metadata {
+        usage.set(key, value);
+    }
+    for (filament, value) in [
+        (&mut usage.filament.diameter, &overrides.diameter),
+        (&mut usage.filament.density, &overrides.density),
+        (&mut usage.filament.cost, &overrides.cost),
+    ] {
+        if !value.is_empty() {
+            filament.clone_from(value);
+        }
+    }
+    let filament = &usage.filament;
+    let value =
+        |value: Option<f64>| value.map_or_else(|| "-".to_string(), |value| format!("{value:.2}"));
+
+    writeln!(out, "tool\tlength [mm]\tvolume [cm3]\tmass [g]\tcost")?;
+    for (tool, length) in usage.by_tool() {
+        writeln!(
+            out,
+            "T{tool}\t{length:.2}\t{:.2}\t{:.2}\t{}",
+            filament.volume(tool, length) / 1000_f64,
+            filament.mass(tool, length),
+            value(filament.cost(tool, length))
+        )?;
+    }
+
+    // Negative ids, and moves outside of any object, are all non-objects.
+    let mut objects = BTreeMap::<Option<i128>, (f64, f64, Option<f64>)>::new();
+    for ((object, tool), length) in &usage.lengths {
+        let entry =
+            objects
+                .entry(object.filter(|id| *id >= 0))
+                .or_insert((0_f64, 0_f64, Some(0_f64)));
+        entry.0 += length;
+        entry.1 += filament.mass(*tool, *length);
+        entry.2 = entry
+            .2
+            .zip(filament.cost(*tool, *length))
+            .map(|(a, b)| a + b);
+    }
+    if objects.keys().any(Option::is_some) {
+        writeln!(out)?;
+        writeln!(out, "object\tlength [mm]\tmass [g]\tcost")?;
+        for (object, (length, mass, object_cost)) in objects {
+            let name = match object {
+                Some(id) => usage
+                    .objects
+                    .get(id)
+                    .and_then(|object| object.name.clone())
+                    .unwrap_or_else(|| format!("object {id}")),
+                _ => "non-object".to_string(),
+            };
+            writeln!(
+                out,
+                "{name}\t{length:.2}\t{mass:.2}\t{}",
+                value(object_cost)
+            )?;
+        }
+    }
+
+    writeln!(out)?;
+    let total_cost = usage
+        .by_tool()
+        .into_iter()
+        .map(|(tool, length)| filament.cost(tool, length))
+        .sum::<Option<f64>>();
+    writeln!(
+        out,
+        "Total: {:.2}mm {:.2}g cost {}",
+        usage.total(),
+        usage.mass(),
+        value(total_cost)
+    )?;
+    let slicer = usage.slicer;
+    writeln!(
+        out,
+        "Slicer's filament used: {}mm {}g cost {}",
+        value(slicer.length),
+        value(slicer.mass),
+        value(slicer.cost)
+    )?;
+    Ok(())
+}
+
+fn layers<W>(input: &Input, out: &mut W) -> std::io::Result<()>
+where
+    W: Write,
+{
+    writeln!(
+        out,
+        "layer\tz\theight\tlines\textrusion [mm]\ttravel [mm]\tobjects"
+    )?;
+    for stats in segment(&input.commands) {
+        let objects = stats
+            .objects
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(
+            out,
+            "{}\t{}\t{:.3}\t{}-{}\t{:.2}\t{:.2}\t{objects}",
+            stats.layer.index,
+            stats.layer.z,
+            stats.height,
+            stats.commands.start + 1,
+            stats.commands.end,
+            stats.extrusion,
+            stats.travel
+        )?;
+    }
+    Ok(())
+}
+
+// Formats a bounding box as "X 0.00..250.00 Y ...".
+fn format_box(bbox: Option<BoundingBox>) -> String {
+    bbox.map_or_else(
+        || "none".to_string(),
+        |bbox| {
+            ["X", "Y", "Z"]
+                .iter()
+                .enumerate()
+                .map(|(axis, name)| format!("{name} {:.2}..{:.2}", bbox.min[axis], bbox.max[axis]))
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+    )
+}
+
+fn bounds<W>(input: &Input, overrides: PrintVolume, margin: f64, out: &mut W) -> std::io::Result<()>
+where
+    W: Write,
+{
+    let mut volume = PrintVolume::default();
+    let comments = input.commands.iter().filter_map(|command| match command {
+        Command::Comment(text) => text
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim())),
+        _ => None,
+    });
+    for (key, value) in input
+        .metadata
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(comments)
+    {
+        volume.set(key, value);
+    }
+    if !overrides.bed.is_empty() {
+        volume.bed = overrides.bed;
+    }
+    volume.height = overrides.height.or(volume.height);
+
+    if volume.bed.is_empty() {
+        writeln!(out, "Bed shape: not found, X and Y are not checked")?;
+    } else {
+        let corners = volume
+            .bed
+            .iter()
+            .map(|[x, y]| format!("{x}x{y}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(out, "Bed shape: {corners}")?;
+    }
+    match volume.height {
+        Some(height) => writeln!(out, "Max height: {height}")?,
+        None => writeln!(out, "Max height: not found")?,
+    }
+
+    let mut checker = BoundsChecker::new(volume, margin);
+    for (index, command) in input.commands.iter().enumerate() {
+        checker.push(index + 1, command);
+    }
+    let bounds = checker.finish();
+    writeln!(out, "Extrusion: {}", format_box(bounds.extrusion))?;
+    writeln!(out, "Travel: {}", format_box(bounds.travel))?;
+
+    writeln!(out)?;
+    if bounds.violations.is_empty() {
+        writeln!(out, "All moves are within bounds.")?;
+        return Ok(());
+    }
+    writeln!(out, "{} moves out of bounds", bounds.violations.len())?;
+    writeln!(out, "line\tx\ty\tz\tmove")?;
+    for violation in &bounds.violations {
+        let [x, y, z] = violation.position;
+        let kind = if violation.extruding {
+            "extrusion"
+        } else {
+            "travel"
+        };
+        writeln!(out, "{}\t{x:.2}\t{y:.2}\t{z:.2}\t{kind}", violation.line)?;
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let mut out = BufWriter::new(stdout().lock());
+    match args.report {
+        Report::Time { layers, file } => time(&read_input(file.as_deref())?, layers, &mut out)?,
+        Report::Filament {
+            diameter,
+            density,
+            cost,
+            file,
+        } => {
+            let overrides = Filament {
+                diameter: diameter.into_iter().collect(),
+                density: density.into_iter().collect(),
+                cost: cost.into_iter().collect(),
+            };
+            filament(read_input(file.as_deref())?, &overrides, &mut out)?;
+        }
+        Report::Layers { file } => layers(&read_input(file.as_deref())?, &mut out)?,
+        Report::Bounds {
+            bed,
+            height,
+            model,
+            margin,
+            file,
+        } => {
+            let mut input = read_input(file.as_deref())?;
+            if let Some(model) = model {
+                // Read last, only filling in what the file does not give.
+                input.metadata.push(("printer_model".to_string(), model));
+            }
+            let bed = match bed {
+                Some(bed) => parse_bed_shape(&bed).ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Invalid bed shape \"{bed}\""),
+                    )
+                })?,
+                None => vec![],
+            };
+            let overrides = PrintVolume { bed, height };
+            bounds(&input, overrides, margin, &mut out)?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(4604_f64), "1h 16m 44s");
+        assert_eq!(format_duration(3600_f64), "1h 0m 0s");
+        assert_eq!(format_duration(59.6), "1m 0s");
+        assert_eq!(parse_duration(&format_duration(93784_f64)), Some(93784_f64));
+    }
+}
@@ -0,0 +1,31 @@
+//! Decompresses and parses the G-code blocks of a binary file.
+//!
+//! Compare the serial and the parallel paths with :-
+//!
+//! ```bash
+//! cargo bench --bench decode
+//! cargo bench --bench decode --features parallel
+//! ```
+
+use criterion::Criterion;
+use criterion::criterion_group;
+use criterion::criterion_main;
+
+use gcode_nom::binary::gcode_block::decode::decode_blocks;
+use gcode_nom::binary::gcode_block::extractor::extract_gcode;
+
+fn decode(c: &mut Criterion) {
+    let input = include_bytes!("../../assets/both parts.bgcode");
+    let (_, blocks) = extract_gcode(input).expect("a valid bgcode file");
+    c.bench_function("decode_blocks", |b| {
+        b.iter(|| decode_blocks(&blocks).expect("blocks decompress"));
+    });
+}
+
+// Each run takes about half a second.
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = decode
+}
+criterion_main!(benches);
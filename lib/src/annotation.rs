@@ -0,0 +1,243 @@
+//! Typed annotations, from the comments slicers add to their G-code.
+//!
+//! Slicers label each run of moves with the feature being printed, and
+//! often with the width and height of the extrusion :-
+//!
+//! ```text
+//! ;TYPE:External perimeter   ; PrusaSlicer
+//! ;TYPE:Outer wall           ; Orca, Bambu Studio
+//! ;TYPE:WALL-OUTER           ; Cura
+//! ;WIDTH:0.45
+//! ;HEIGHT:0.2
+//! ;MESH:cube.stl             ; Cura
+//! ```
+//!
+//! The names used by each slicer are mapped onto a common [`Feature`].
+
+use crate::command::Command;
+
+/// The part of the model a move belongs to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// An inner perimeter, or wall.
+    Perimeter,
+    /// The outermost perimeter, the visible surface.
+    ExternalPerimeter,
+    /// A perimeter printed over nothing.
+    OverhangPerimeter,
+    /// Sparse infill.
+    InternalInfill,
+    /// Solid infill, inside the part.
+    SolidInfill,
+    /// Solid infill, on an upper surface.
+    TopSolidInfill,
+    /// Solid infill, on a lower surface.
+    BottomSolidInfill,
+    /// Infill spanning a gap.
+    BridgeInfill,
+    /// Fills the gaps between perimeters.
+    GapFill,
+    /// Smooths a top surface.
+    Ironing,
+    /// A skirt or brim.
+    Skirt,
+    /// Support material.
+    Support,
+    /// The layers of support which touch the part.
+    SupportInterface,
+    /// A wipe, or prime, tower.
+    WipeTower,
+    /// Custom G-code, such as the start and end G-code.
+    Custom,
+    /// A name which is not known.
+    Other(String),
+}
+
+impl From<&str> for Feature {
+    fn from(name: &str) -> Self {
+        match name.trim() {
+            "Perimeter" | "Inner wall" | "WALL-INNER" => Self::Perimeter,
+            "External perimeter" | "Outer wall" | "WALL-OUTER" => Self::ExternalPerimeter,
+            "Overhang perimeter" | "Overhang wall" => Self::OverhangPerimeter,
+            "Internal infill" | "Sparse infill" | "FILL" => Self::InternalInfill,
+            "Solid infill" | "Internal solid infill" | "SKIN" => Self::SolidInfill,
+            "Top solid infill" | "Top surface" => Self::TopSolidInfill,
+            "Bottom surface" => Self::BottomSolidInfill,
+            "Bridge infill" | "Bridge" | "Internal Bridge" => Self::BridgeInfill,
+            "Gap fill" | "Gap infill" => Self::GapFill,
+            "Ironing" => Self::Ironing,
+            "Skirt" | "Skirt/Brim" | "Brim" | "SKIRT" => Self::Skirt,
+            "Support material" | "Support" | "Support transition" | "SUPPORT" => Self::Support,
+            "Support material interface" | "Support interface" | "SUPPORT-INTERFACE" => {
+                Self::SupportInterface
+            }
+            "Wipe tower" | "Prime tower" | "PRIME-TOWER" => Self::WipeTower,
+            "Custom" => Self::Custom,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// A comment which carries information about the moves which follow.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Annotation {
+    /// `;TYPE:` the feature being printed.
+    Feature(Feature),
+    /// `;WIDTH:` the extrusion width, in mm.
+    Width(f64),
+    /// `;HEIGHT:` the extrusion height, in mm.
+    Height(f64),
+    /// `;MESH:` Cura's model name, `None` for "NONMESH".
+    Mesh(Option<String>),
+    /// `;LAYER_CHANGE` a new layer follows.
+    LayerChange,
+    /// `;LAYER:` Cura's layer number, negative for a raft.
+    Layer(i64),
+    /// `;Z:` the height of a new layer, in mm.
+    Z(f64),
+}
+
+/// Classifies the text of a comment (without the leading ';').
+///
+/// Returns `None` for comments which are not annotations.
+///
+/// ```rust
+/// use gcode_nom::annotation::Annotation;
+/// use gcode_nom::annotation::Feature;
+/// use gcode_nom::annotation::classify;
+///
+/// assert_eq!(
+///     classify("TYPE:Outer wall"),
+///     Some(Annotation::Feature(Feature::ExternalPerimeter))
+/// );
+/// assert_eq!(classify("WIDTH:0.45"), Some(Annotation::Width(0.45)));
+/// assert_eq!(classify(" a remark"), None);
+/// ```
+#[must_use]
+pub fn classify(text: &str) -> Option<Annotation> {
+    let text = text.trim();
+    if text == "LAYER_CHANGE" {
+        return Some(Annotation::LayerChange);
+    }
+    let (key, value) = text.split_once(':')?;
+    let value = value.trim();
+    match key {
+        "TYPE" => Some(Annotation::Feature(Feature::from(value))),
+        "WIDTH" => value.parse().ok().map(Annotation::Width),
+        "HEIGHT" => value.parse().ok().map(Annotation::Height),
+        "MESH" => Some(Annotation::Mesh(
+            (value != "NONMESH").then(|| value.to_string()),
+        )),
+        "LAYER" => value.parse().ok().map(Annotation::Layer),
+        "Z" => value.parse().ok().map(Annotation::Z),
+        _ => None,
+    }
+}
+
+/// The annotations in force, as commands are replayed.
+///
+/// ```rust
+/// use gcode_nom::annotation::Annotations;
+/// use gcode_nom::annotation::Feature;
+/// use gcode_nom::command::Command;
+///
+/// let mut annotations = Annotations::default();
+/// for line in [";TYPE:Perimeter", ";WIDTH:0.45", "G1 X10 E1"] {
+///     annotations.push(&Command::parse_line(line).unwrap().1);
+/// }
+/// assert_eq!(annotations.feature, Some(Feature::Perimeter));
+/// assert_eq!(annotations.width, Some(0.45));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    /// The feature being printed.
+    pub feature: Option<Feature>,
+    /// The extrusion width, in mm.
+    pub width: Option<f64>,
+    /// The extrusion height, in mm.
+    pub height: Option<f64>,
+    /// Cura's model name.
+    pub mesh: Option<String>,
+}
+
+impl Annotations {
+    /// Updates the annotations with a single command.
+    ///
+    /// Returns the annotation found, if any.
+    pub fn push(&mut self, command: &Command) -> Option<Annotation> {
+        let Command::Comment(text) = command else {
+            return None;
+        };
+        let annotation = classify(text)?;
+        match &annotation {
+            Annotation::Feature(feature) => self.feature = Some(feature.clone()),
+            Annotation::Width(width) => self.width = Some(*width),
+            Annotation::Height(height) => self.height = Some(*height),
+            Annotation::Mesh(mesh) => self.mesh.clone_from(mesh),
+            Annotation::LayerChange | Annotation::Layer(_) | Annotation::Z(_) => {}
+        }
+        Some(annotation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dialects() {
+        for (prusa, orca, cura) in [
+            ("Perimeter", "Inner wall", "WALL-INNER"),
+            ("External perimeter", "Outer wall", "WALL-OUTER"),
+            ("Internal infill", "Sparse infill", "FILL"),
+            ("Skirt/Brim", "Brim", "SKIRT"),
+            ("Support material", "Support", "SUPPORT"),
+            ("Wipe tower", "Prime tower", "PRIME-TOWER"),
+        ] {
+            assert_eq!(Feature::from(prusa), Feature::from(orca));
+            assert_eq!(Feature::from(prusa), Feature::from(cura));
+        }
+        assert_eq!(Feature::from("Seam"), Feature::Other("Seam".to_string()));
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(classify("LAYER_CHANGE"), Some(Annotation::LayerChange));
+        assert_eq!(classify("LAYER:-2"), Some(Annotation::Layer(-2)));
+        assert_eq!(classify("LAYER_COUNT:20"), None);
+        assert_eq!(classify("Z:0.35"), Some(Annotation::Z(0.35)));
+        assert_eq!(classify("HEIGHT:0.2"), Some(Annotation::Height(0.2)));
+        assert_eq!(classify("WIDTH:wide"), None);
+        assert_eq!(
+            classify("MESH:cube.stl"),
+            Some(Annotation::Mesh(Some("cube.stl".to_string())))
+        );
+        assert_eq!(classify("MESH:NONMESH"), Some(Annotation::Mesh(None)));
+        assert_eq!(classify(" WIPE_START"), None);
+        assert_eq!(classify("TIME_ELAPSED:12.5"), None);
+    }
+
+    #[test]
+    fn slicer_file() {
+        let input = include_str!("../../assets/II 2x4_0.15mm_PLA_MK3S_1h17m.gcode");
+        let mut annotations = Annotations::default();
+        let mut features = vec![];
+        for line in input.lines() {
+            let command = Command::parse_line(line).unwrap().1;
+            if let Some(Annotation::Feature(feature)) = annotations.push(&command)
+                && !features.contains(&feature)
+            {
+                features.push(feature);
+            }
+        }
+        assert!(features.contains(&Feature::ExternalPerimeter));
+        assert!(features.contains(&Feature::Support));
+        assert!(features.contains(&Feature::Custom));
+        assert!(
+            !features
+                .iter()
+                .any(|feature| matches!(feature, Feature::Other(_))),
+            "{features:?}"
+        );
+    }
+}
@@ -14,7 +14,7 @@ use nom::sequence::preceded;
 /// Payload has two forms,
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Form {
-    /// Arc with center offset in I and J
+    /// Arc with center offset in I, J and K
     IJ(HashSet<ArcVal>),
     /// Arc with radius R
     R(HashSet<ArcVal>),
@@ -23,7 +23,7 @@ pub enum Form {
 /// Parameters for `Command::G2` and `Command::G3`
 ///
 /// Similar to `PosVal` but with additional parameters
-/// I,J, P, R.
+/// I, J, K, P, R.
 #[derive(Clone, Debug)]
 pub enum ArcVal {
     /// Axis A
@@ -45,6 +45,8 @@ pub enum ArcVal {
     I(f64),
     /// Arc center offset in the J direction
     J(f64),
+    /// Arc center offset in the K direction
+    K(f64),
 
     /// `P<Count>` number of complete circles.
     P(f64),
@@ -81,6 +83,7 @@ impl PartialEq for ArcVal {
             | (Self::F(x), Self::F(y))
             | (Self::I(x), Self::I(y))
             | (Self::J(x), Self::J(y))
+            | (Self::K(x), Self::K(y))
             | (Self::P(x), Self::P(y))
             | (Self::R(x), Self::R(y))
             | (Self::S(x), Self::S(y))
@@ -113,6 +116,7 @@ impl Hash for ArcVal {
             Self::F(_) => "F".hash(state),
             Self::I(_) => "I".hash(state),
             Self::J(_) => "J".hash(state),
+            Self::K(_) => "K".hash(state),
             Self::P(_) => "P".hash(state),
             Self::R(_) => "R".hash(state),
             Self::S(_) => "S".hash(state),
@@ -157,6 +161,7 @@ parse_arc_val!(parse_arc_e, "E", E);
 
 parse_arc_val!(parse_arc_i, "I", I);
 parse_arc_val!(parse_arc_j, "J", J);
+parse_arc_val!(parse_arc_k, "K", K);
 parse_arc_val!(parse_arc_p, "P", P);
 parse_arc_val!(parse_arc_r, "R", R);
 
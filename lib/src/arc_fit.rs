@@ -0,0 +1,545 @@
+//! Compresses runs of short G1 moves into G2/G3 arcs, as ArcWelder does.
+//!
+//! Slicers approximate curves with many short lines. Where consecutive
+//! extrusions lie on a circle, within a tolerance, they are replaced by a
+//! single arc. The file shrinks, and printers fed over a serial line no
+//! longer stutter through the curve.
+//!
+//! Each arc is checked with [`crate::compute_arc`], using the rounded
+//! values written to the file, and carries the filament of the moves it
+//! replaces.
+//!
+//! Only extrusions in absolute XY mode, at a constant height and feedrate,
+//! are fitted. All other lines are copied unchanged.
+
+use core::f64::consts::TAU;
+use core::fmt::Write as _;
+use std::io::Write;
+
+use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::arc_end;
+use crate::command::Command;
+use crate::compute_arc;
+use crate::params::head::PosVal;
+
+// The extrusion per mm of each move may differ from the arc's by this fraction.
+const EXTRUSION_TOLERANCE: f64 = 0.05;
+
+// A G1 move, waiting to be fitted.
+#[derive(Clone, Debug)]
+struct Segment {
+    line: String,
+    end: [f64; 2],
+    // The E parameter, as written.
+    e: f64,
+    // The length of filament extruded.
+    extruded: f64,
+    // A new feedrate, set by the move.
+    feedrate: Option<f64>,
+}
+
+/// Replaces runs of G1 moves with G2/G3 arcs.
+///
+/// Moves are held back until the run they belong to is complete, so
+/// [`ArcFitter::finish`] must be called after the last line.
+///
+/// ```rust
+/// use gcode_nom::arc_fit::ArcFitter;
+///
+/// let mut fitter = ArcFitter::default();
+/// let mut out = vec![];
+/// fitter.write_line("M83", &mut out).unwrap();
+/// fitter.write_line("G1 X10 Y0", &mut out).unwrap();
+/// // A quarter circle, of radius 10mm, as eighteen lines.
+/// for step in 1..=18 {
+///     let theta = f64::from(step * 5).to_radians();
+///     let line = format!("G1 X{:.3} Y{:.3} E0.05", 10.0 * theta.cos(), 10.0 * theta.sin());
+///     fitter.write_line(&line, &mut out).unwrap();
+/// }
+/// fitter.finish(&mut out).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "M83\nG1 X10 Y0\nG3 X0 Y10 I-10 J0 E0.9\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArcFitter {
+    /// The largest distance between an arc and the moves it replaces, in mm.
+    pub tolerance: f64,
+    /// Curves with a larger radius are left as lines, in mm.
+    pub max_radius: f64,
+    /// The fewest moves replaced by an arc.
+    pub min_segments: usize,
+    // X, Y and Z, as given by the file. NaN when unknown.
+    position: [f64; 3],
+    position_mode: PositionMode,
+    // Set by M83, or G91.
+    relative_e: bool,
+    // The position of the E axis, as given by the file.
+    e: f64,
+    feedrate: Option<f64>,
+    // Where the run of moves starts.
+    start: [f64; 2],
+    // Moves which, with the start, lie on an arc.
+    run: Vec<Segment>,
+}
+
+impl Default for ArcFitter {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.025,
+            max_radius: 1000_f64,
+            min_segments: 3,
+            position: [0_f64; 3],
+            position_mode: PositionMode::Absolute,
+            relative_e: false,
+            e: 0_f64,
+            feedrate: None,
+            start: [0_f64; 2],
+            run: vec![],
+        }
+    }
+}
+
+impl ArcFitter {
+    /// Writes a single line of G-code, or holds it back while it may
+    /// become part of an arc.
+    ///
+    /// # Errors
+    ///   When a write fails.
+    pub fn write_line<W>(&mut self, line: &str, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let Ok((_, command)) = Command::parse_line(line) else {
+            self.flush(out)?;
+            return writeln!(out, "{line}");
+        };
+        let Some(segment) = self.segment(line, &command) else {
+            self.flush(out)?;
+            self.update(&command);
+            return writeln!(out, "{line}");
+        };
+
+        // A change of feedrate starts a new run.
+        if segment.feedrate.is_some() {
+            self.flush(out)?;
+        }
+        if self.run.is_empty() {
+            self.start = [self.position[0], self.position[1]];
+        }
+        [self.position[0], self.position[1]] = segment.end;
+        self.e = if self.relative_e {
+            self.e + segment.e
+        } else {
+            segment.e
+        };
+        self.feedrate = segment.feedrate.or(self.feedrate);
+
+        self.run.push(segment);
+        while self.run.len() > 1 && self.fit().is_none() {
+            // The run ended with the previous move.
+            let Some(segment) = self.run.pop() else {
+                break;
+            };
+            if self.run.len() >= self.min_segments {
+                self.flush(out)?;
+            } else {
+                let first = self.run.remove(0);
+                writeln!(out, "{}", first.line)?;
+                self.start = first.end;
+            }
+            self.run.push(segment);
+        }
+        Ok(())
+    }
+
+    /// Writes the moves still held back.
+    ///
+    /// # Errors
+    ///   When a write fails.
+    pub fn finish<W>(&mut self, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        self.flush(out)
+    }
+
+    // Writes the run, as an arc if it is long enough.
+    fn flush<W>(&mut self, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let arc = (self.run.len() >= self.min_segments)
+            .then(|| self.fit())
+            .flatten();
+        if let Some(arc) = arc {
+            writeln!(out, "{arc}")?;
+        } else {
+            for segment in &self.run {
+                writeln!(out, "{}", segment.line)?;
+            }
+        }
+        if let Some(last) = self.run.last() {
+            self.start = last.end;
+        }
+        self.run.clear();
+        Ok(())
+    }
+
+    // Returns a move which may be fitted to an arc.
+    fn segment(&self, line: &str, command: &Command) -> Option<Segment> {
+        let Command::G1(params) = command else {
+            return None;
+        };
+        // Comments would be lost.
+        if line.contains(';') || self.position_mode == PositionMode::Relative {
+            return None;
+        }
+        let mut end = [self.position[0], self.position[1]];
+        let mut e = None;
+        let mut feedrate = None;
+        for param in params {
+            match param {
+                PosVal::X(val) => end[0] = *val,
+                PosVal::Y(val) => end[1] = *val,
+                PosVal::E(val) => e = Some(*val),
+                PosVal::F(val) => feedrate = Some(*val),
+                _ => return None,
+            }
+        }
+        let e = e?;
+        let extruded = if self.relative_e { e } else { e - self.e };
+        let length = (end[0] - self.position[0]).hypot(end[1] - self.position[1]);
+        (extruded > 0_f64 && length > 0_f64).then(|| Segment {
+            line: line.to_string(),
+            end,
+            e,
+            extruded,
+            feedrate: feedrate.filter(|f| Some(*f) != self.feedrate),
+        })
+    }
+
+    // Tracks the position of the head, for lines which are copied.
+    fn update(&mut self, command: &Command) {
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.set_axis(0, *val),
+                        PosVal::Y(val) => self.set_axis(1, *val),
+                        PosVal::Z(val) => self.set_axis(2, *val),
+                        PosVal::E(val) => self.set_e(*val),
+                        PosVal::F(val) => self.feedrate = Some(*val),
+                        _ => {}
+                    }
+                }
+            }
+            Command::G2(form) | Command::G3(form) => {
+                self.position = arc_end(self.position, form, self.position_mode);
+                let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+                for param in params {
+                    match param {
+                        ArcVal::E(val) => self.set_e(*val),
+                        ArcVal::F(val) => self.feedrate = Some(*val),
+                        _ => {}
+                    }
+                }
+            }
+            Command::G90 => {
+                self.position_mode = PositionMode::Absolute;
+                self.relative_e = false;
+            }
+            Command::G91 => {
+                self.position_mode = PositionMode::Relative;
+                self.relative_e = true;
+            }
+            Command::MDrop(82) => self.relative_e = false,
+            Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.position[0] = *val,
+                        PosVal::Y(val) => self.position[1] = *val,
+                        PosVal::Z(val) => self.position[2] = *val,
+                        PosVal::E(val) => self.e = *val,
+                        _ => {}
+                    }
+                }
+            }
+            // Homing, the position is unknown until the next move.
+            Command::GDrop(28) => self.position = [f64::NAN; 3],
+            _ => {}
+        }
+    }
+
+    fn set_axis(&mut self, axis: usize, val: f64) {
+        self.position[axis] = match self.position_mode {
+            PositionMode::Absolute => val,
+            PositionMode::Relative => self.position[axis] + val,
+        };
+    }
+
+    fn set_e(&mut self, e: f64) {
+        self.e = if self.relative_e { self.e + e } else { e };
+    }
+
+    // Returns the arc which replaces the run, if the moves lie on one.
+    fn fit(&self) -> Option<String> {
+        let last = self.run.last()?;
+        if self.run.len() < 2 {
+            return None;
+        }
+        let start = self.start;
+        let middle = self.run[(self.run.len() - 1) / 2].end;
+        let end = last.end;
+
+        // The circle through the first, middle and last points.
+        let (ax, ay) = (middle[0] - start[0], middle[1] - start[1]);
+        let (bx, by) = (end[0] - start[0], end[1] - start[1]);
+        let cross = ax.mul_add(by, -ay * bx);
+        if cross.abs() < f64::EPSILON {
+            return None;
+        }
+        let a2 = ax.mul_add(ax, ay * ay);
+        let b2 = bx.mul_add(bx, by * by);
+        let i = round(by.mul_add(a2, -ay * b2) / (2_f64 * cross), 1e3);
+        let j = round(ax.mul_add(b2, -bx * a2) / (2_f64 * cross), 1e3);
+        // Points on the circle turn the same way as the first three.
+        let clockwise = cross < 0_f64;
+
+        let form = ArcForm::IJ(
+            [
+                ArcVal::X(end[0]),
+                ArcVal::Y(end[1]),
+                ArcVal::I(i),
+                ArcVal::J(j),
+            ]
+            .into(),
+        );
+        let params = compute_arc(start[0], start[1], &form);
+        let sweep = params.sweep(clockwise).abs();
+        let max_radius = self.max_radius;
+        if params.radius > max_radius || sweep >= TAU {
+            return None;
+        }
+
+        // Each point lies on the arc, further along than the last, and each
+        // line strays from the arc by no more than the tolerance.
+        let (cx, cy) = params.center;
+        let along = |p: [f64; 2]| {
+            let theta = (p[1] - cy).atan2(p[0] - cx);
+            if clockwise {
+                (params.theta_start - theta).rem_euclid(TAU)
+            } else {
+                (theta - params.theta_start).rem_euclid(TAU)
+            }
+        };
+        let mut previous = start;
+        let mut travelled = 0_f64;
+        let mut lengths = Vec::with_capacity(self.run.len());
+        for segment in &self.run {
+            let p = segment.end;
+            if ((p[0] - cx).hypot(p[1] - cy) - params.radius).abs() > self.tolerance {
+                return None;
+            }
+            let angle = along(p);
+            if angle <= travelled {
+                return None;
+            }
+            travelled = angle;
+            let mid = [
+                f64::midpoint(previous[0], p[0]),
+                f64::midpoint(previous[1], p[1]),
+            ];
+            if params.radius - (mid[0] - cx).hypot(mid[1] - cy) > self.tolerance {
+                return None;
+            }
+            lengths.push((p[0] - previous[0]).hypot(p[1] - previous[1]));
+            previous = p;
+        }
+
+        // The filament is spread evenly along the arc.
+        let extruded = self.run.iter().map(|segment| segment.extruded).sum::<f64>();
+        let rate = extruded / lengths.iter().sum::<f64>();
+        if self.run.iter().zip(&lengths).any(|(segment, length)| {
+            (segment.extruded / length - rate).abs() > rate * EXTRUSION_TOLERANCE
+        }) {
+            return None;
+        }
+        let e = if self.relative_e {
+            round(extruded, 1e5)
+        } else {
+            last.e
+        };
+
+        let code = if clockwise { "G2" } else { "G3" };
+        let mut arc = format!("{code} X{} Y{} I{i} J{j} E{e}", end[0], end[1]);
+        if let Some(feedrate) = self.run[0].feedrate {
+            // Writing to a String cannot fail.
+            let _ = write!(arc, " F{feedrate}");
+        }
+        Some(arc)
+    }
+}
+
+// Rounds to the given scale, 1e3 for 3 decimal places, without a negative zero.
+fn round(value: f64, scale: f64) -> f64 {
+    (value * scale).round() / scale + 0_f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filament::Usage;
+    use crate::layer::segment;
+
+    fn fit(fitter: &mut ArcFitter, input: &str) -> String {
+        let mut out = vec![];
+        for line in input.lines() {
+            fitter.write_line(line, &mut out).unwrap();
+        }
+        fitter.finish(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // Moves along a circle centered on [50, 50], in degrees.
+    fn circle(radius: f64, degrees: impl Iterator<Item = i32>, e: &str) -> String {
+        let mut out = String::new();
+        for degree in degrees {
+            let (sin, cos) = f64::from(degree).to_radians().sin_cos();
+            writeln!(
+                out,
+                "G1 X{:.3} Y{:.3} {e}",
+                radius.mul_add(cos, 50_f64),
+                radius.mul_add(sin, 50_f64)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn arcs() {
+        // Anticlockwise, then back again.
+        let input = format!(
+            "M83\nG1 X60 Y50 F1200\n{}{}",
+            circle(10_f64, (5..=90).step_by(5), "E0.05"),
+            circle(10_f64, (0..90).step_by(5).rev(), "E0.05")
+        );
+        let expected = "M83
+G1 X60 Y50 F1200
+G3 X50 Y60 I-10 J0 E0.9
+G2 X60 Y50 I0 J-10 E0.9
+";
+        assert_eq!(fit(&mut ArcFitter::default(), &input), expected);
+    }
+
+    #[test]
+    fn lines() {
+        // Straight lines, corners, travel and retractions are kept.
+        let input = "G1 X0 Y0
+G1 X10 Y0 E1
+G1 X20 Y0 E2
+G1 X30 Y0 E3
+G1 X30 Y10 E4
+G1 E3
+G1 X0 Y10
+G1 X0 Y0 E5 ; comment
+";
+        assert_eq!(fit(&mut ArcFitter::default(), input), input);
+    }
+
+    #[test]
+    fn runs() {
+        // Two short moves, too few for an arc, then a curve and a corner.
+        let curve = circle(10_f64, (5..=90).step_by(5), "E0.1");
+        let input =
+            format!("M83\nG1 X40 Y40\nG1 X50 Y40 E1\nG1 X60 Y50 E1.4\n{curve}G1 X50 Y70 E1\n");
+        let expected = "M83
+G1 X40 Y40
+G1 X50 Y40 E1
+G1 X60 Y50 E1.4
+G3 X50 Y60 I-10 J0 E1.8
+G1 X50 Y70 E1
+";
+        assert_eq!(fit(&mut ArcFitter::default(), &input), expected);
+
+        // The curve strays from the arc, by 0.2mm at 50°.
+        let bumpy = curve.replace("G1 X56.428 Y57.660", "G1 X56.557 Y57.813");
+        let output = fit(
+            &mut ArcFitter::default(),
+            &format!("M83\nG1 X60 Y50\n{bumpy}"),
+        );
+        assert!(output.contains("G1 X56.557 Y57.813 E0.1"), "{output}");
+        let mut loose = ArcFitter {
+            tolerance: 0.25,
+            ..Default::default()
+        };
+        let output = fit(&mut loose, &format!("M83\nG1 X60 Y50\n{bumpy}"));
+        assert_eq!(output.lines().count(), 3, "{output}");
+    }
+
+    #[test]
+    fn absolute() {
+        // The arc ends at the last E, a new feedrate starts a new run.
+        let input = format!(
+            "M82\nG92 E0\nG1 X60 Y50\n{}{}",
+            circle(10_f64, (5..=40).step_by(5), "E{}"),
+            circle(10_f64, (45..=90).step_by(5), "E{} F600")
+        );
+        let mut e = 0;
+        let input = input
+            .lines()
+            .map(|line| {
+                if line.contains("E{}") {
+                    e += 1;
+                    format!("{}\n", line.replace("E{}", &format!("E{e}")))
+                } else {
+                    format!("{line}\n")
+                }
+            })
+            .collect::<String>();
+        let output = fit(&mut ArcFitter::default(), &input);
+        let expected = "M82
+G92 E0
+G1 X60 Y50
+G3 X57.66 Y56.428 I-9.998 J0 E8
+G3 X50 Y60 I-7.66 J-6.428 E18 F600
+";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn slicer_file() {
+        let input = include_str!("../../assets/3DBenchy.gcode");
+        let output = fit(&mut ArcFitter::default(), input);
+        assert!(output.lines().count() < input.lines().count() * 9 / 10);
+
+        let commands = |text: &str| {
+            text.lines()
+                .map(|line| Command::parse_line(line).unwrap().1)
+                .collect::<Vec<_>>()
+        };
+        let (before, after) = (commands(input), commands(&output));
+        assert!(
+            after
+                .iter()
+                .any(|command| matches!(command, Command::G3(_)))
+        );
+
+        // The same filament, laid along almost the same path.
+        let usage = |commands: &[Command]| commands.iter().cloned().collect::<Usage>().total();
+        assert!((usage(&before) - usage(&after)).abs() < 1e-3);
+        let length = |commands: &[Command]| {
+            segment(commands)
+                .iter()
+                .map(|layer| layer.extrusion)
+                .sum::<f64>()
+        };
+        let ratio = length(&after) / length(&before);
+        assert!((ratio - 1_f64).abs() < 1e-3, "{ratio}");
+    }
+}
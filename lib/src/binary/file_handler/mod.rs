@@ -33,6 +33,13 @@ pub struct FileHeader {
     checksum_type: ChecksumType,
 }
 
+impl FileHeader {
+    /// Returns true if each block ends with a CRC32 checksum.
+    pub(crate) const fn has_checksum(&self) -> bool {
+        matches!(self.checksum_type, ChecksumType::CRC32)
+    }
+}
+
 impl Display for FileHeader {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         writeln!(f, "File Header")?;
@@ -0,0 +1,180 @@
+//! Decompresses a series of `GCodeBlock`s into a stream of commands.
+//!
+//! Each block is independently compressed, so both decompression and
+//! parsing can run across threads when the "parallel" feature is enabled.
+//!
+//! The order of the commands is always the order of the blocks in the file.
+//!
+//! Parsing is stateless; the machine state (position, positioning mode,
+//! extrusion ) is only ever updated by the consumer of the returned
+//! commands, which replays them in order. So the state at the end of one
+//! block is always handed to the start of the next.
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::binary::inflate::DecompressError;
+use crate::binary::inflate::decompress_data_block;
+use crate::command::Command;
+
+use super::GCodeBlock;
+
+/// Decompresses and parses a sequence of `GCodeBlock`s.
+///
+/// A line of G-code may straddle a block boundary, the trailing fragment
+/// of one block is joined to the leading fragment of the next before
+/// parsing.
+///
+/// With the "parallel" feature enabled the blocks are processed across
+/// threads.
+///
+/// # Errors
+///   When a block cannot be decompressed.
+///
+/// # Panics
+///   When a line cannot be parsed as a command.
+pub fn decode_blocks(blocks: &[GCodeBlock<'_>]) -> Result<Vec<Command>, DecompressError> {
+    #[cfg(feature = "parallel")]
+    let decompressed = blocks
+        .par_iter()
+        .map(decompress)
+        .collect::<Result<Vec<_>, _>>()?;
+    #[cfg(not(feature = "parallel"))]
+    let decompressed = blocks
+        .iter()
+        .map(decompress)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let chunks = join_at_line_boundaries(decompressed);
+
+    #[cfg(feature = "parallel")]
+    let commands = chunks
+        .par_iter()
+        .flat_map_iter(|chunk| parse(chunk))
+        .collect();
+    #[cfg(not(feature = "parallel"))]
+    let commands = chunks.iter().flat_map(|chunk| parse(chunk)).collect();
+
+    Ok(commands)
+}
+
+/// Decompresses a sequence of `GCodeBlock`s into the text of the G-code.
+///
+/// Unlike [`decode_blocks`] the lines are not parsed, so comments and
+/// formatting survive. Used by tools which rewrite the G-code.
+///
+/// # Errors
+///   When a block cannot be decompressed.
+pub fn decode_text(blocks: &[GCodeBlock<'_>]) -> Result<String, DecompressError> {
+    let mut data = vec![];
+    for block in blocks {
+        data.extend(decompress(block)?);
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn decompress(block: &GCodeBlock<'_>) -> Result<Vec<u8>, DecompressError> {
+    match decompress_data_block(block.data, &block.param.encoding, &block.header) {
+        Ok((_remain, data)) => Ok(data),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+        Err(nom::Err::Incomplete(_)) => Err(DecompressError::None),
+    }
+}
+
+// Moves any partial line found at the end of a block onto the front
+// of the next block.
+//
+// Blocks written by the slicer normally end on a line boundary, so the
+// carry is usually empty and no data is copied.
+fn join_at_line_boundaries(decompressed: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut chunks = Vec::with_capacity(decompressed.len());
+    let mut carry: Vec<u8> = vec![];
+    for mut data in decompressed {
+        if !carry.is_empty() {
+            carry.extend_from_slice(&data);
+            data = core::mem::take(&mut carry);
+        }
+        // Everything after the last newline belongs to the next block.
+        if let Some(last_newline) = data.iter().rposition(|b| *b == b'\n') {
+            carry = data.split_off(last_newline + 1);
+        } else {
+            carry = data;
+            continue;
+        }
+        chunks.push(data);
+    }
+    if !carry.is_empty() {
+        chunks.push(carry);
+    }
+    chunks
+}
+
+fn parse(chunk: &[u8]) -> Vec<Command> {
+    String::from_utf8_lossy(chunk)
+        .lines()
+        .map(|line| {
+            let (_, command) = Command::parse_line(line).expect("Command is not parsable");
+            command
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binary::block_header::BlockHeader;
+    use crate::binary::compression_type::CompressionType;
+    use crate::binary::default_params::Param;
+    use crate::binary::gcode_block::extractor::extract_gcode;
+    use crate::params::head::PosVal;
+
+    fn uncompressed_block(data: &[u8]) -> GCodeBlock<'_> {
+        GCodeBlock {
+            header: BlockHeader {
+                compression_type: CompressionType::None,
+                uncompressed_size: u32::try_from(data.len()).unwrap(),
+                compressed_size: None,
+            },
+            param: Param::default(),
+            data,
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn line_split_across_blocks() {
+        // The second command is split between the two blocks.
+        let blocks = [
+            uncompressed_block(b"G1 X1\nG1 X"),
+            uncompressed_block(b"2 Y3\nG1 Z4"),
+        ];
+
+        let commands = decode_blocks(&blocks).unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                Command::G1([PosVal::X(1_f64)].into()),
+                Command::G1([PosVal::X(2_f64), PosVal::Y(3_f64)].into()),
+                Command::G1([PosVal::Z(4_f64)].into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn order_is_preserved() {
+        // Decoding must produce exactly what a serial line by line parse
+        // of the concatenated blocks would produce.
+        let buffer = include_bytes!("../../../../assets/mini_cube_b.bgcode");
+        let (_remain, blocks) = extract_gcode(buffer).unwrap();
+
+        let text = blocks
+            .iter()
+            .map(|block| String::from_utf8_lossy(&decompress(block).unwrap()).to_string())
+            .collect::<String>();
+        let expected = text
+            .lines()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<Vec<_>>();
+
+        assert_eq!(decode_blocks(&blocks).unwrap(), expected);
+    }
+}
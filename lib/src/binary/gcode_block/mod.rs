@@ -16,8 +16,12 @@ use super::block_header::block_header_parser;
 use super::default_params::Param;
 use super::inflate::decompress_data_block;
 
+/// Decompresses and parses blocks into commands.
+pub mod decode;
 /// Parser extracts `Vec<GCodeBlock>` from file.
 pub mod extractor;
+/// Rewrites the G-code blocks of a file, with new checksums.
+pub mod rewrite;
 /// Converts a gcode block into a SVG file.
 pub mod svg;
 
@@ -0,0 +1,167 @@
+//! Rewrites the G-code of a binary file, block by block.
+//!
+//! Every block before the G-code, and each G-code block left unchanged, is
+//! copied byte for byte. A changed block is written uncompressed, with a
+//! new checksum when the file header asks for one.
+//!
+//! Each block is handed over as whole lines. A line which runs on into the
+//! next block is handed over with that block, and both blocks are written
+//! again.
+
+use std::io::Error;
+use std::io::ErrorKind;
+
+use nom::Parser;
+use nom::combinator::opt;
+use nom::multi::many0;
+
+use crate::binary::BlockError;
+use crate::binary::file_handler::FileHeader;
+use crate::binary::file_handler::file_header_parser;
+use crate::binary::file_metadata_block::file_metadata_parser;
+use crate::binary::print_metadata_block::print_metadata_parser;
+use crate::binary::printer_metadata_block::printer_metadata_parser;
+use crate::binary::slicer_block::slicer_parser;
+use crate::binary::thumbnail_block::thumbnail_parser;
+
+use super::CODE_BLOCK_ID;
+use super::decode::decode_text;
+use super::gcode_parser;
+
+/// Passes the text of each G-code block to `rewrite`, which returns the
+/// new text of a block, or `None` to keep it.
+///
+/// Returns the rewritten file.
+///
+/// # Errors
+///   When the file cannot be parsed, or a block decompressed.
+///
+///   When `rewrite` fails.
+pub fn rewrite_blocks<F>(input: &[u8], mut rewrite: F) -> std::io::Result<Vec<u8>>
+where
+    F: FnMut(&str) -> std::io::Result<Option<String>>,
+{
+    let (header, mut remain) = split_header(input)?;
+    let checksum = header.has_checksum();
+    let mut out = input[..input.len() - remain.len()].to_vec();
+
+    // The start of a line which runs on into the next block.
+    let mut carry = String::new();
+    while !remain.is_empty() {
+        let (after, block) = gcode_parser(remain).map_err(|e| invalid(&e))?;
+        let text = decode_text(core::slice::from_ref(&block))
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{e:?}")))?;
+        let starts_whole = carry.is_empty();
+        carry.push_str(&text);
+        let end = if after.is_empty() {
+            carry.len()
+        } else {
+            carry.rfind('\n').map_or(0, |i| i + 1)
+        };
+        let tail = carry.split_off(end);
+        let lines = core::mem::replace(&mut carry, tail);
+        match rewrite(&lines)? {
+            Some(text) => encode(text.as_bytes(), checksum, &mut out)?,
+            None if starts_whole && carry.is_empty() => {
+                out.extend_from_slice(&remain[..remain.len() - after.len()]);
+            }
+            None if lines.is_empty() => {}
+            None => encode(lines.as_bytes(), checksum, &mut out)?,
+        }
+        remain = after;
+    }
+    Ok(out)
+}
+
+// Returns the file header, and the blocks which follow the slicer block.
+fn split_header(input: &[u8]) -> std::io::Result<(FileHeader, &[u8])> {
+    let (remain, (header, ..)) = (
+        file_header_parser,
+        opt(file_metadata_parser),
+        printer_metadata_parser,
+        many0(thumbnail_parser),
+        print_metadata_parser,
+        slicer_parser,
+    )
+        .parse(input)
+        .map_err(|e| invalid(&e))?;
+    Ok((header, remain))
+}
+
+// Appends an uncompressed G-code block, and its checksum if asked for.
+fn encode(data: &[u8], checksum: bool, out: &mut Vec<u8>) -> std::io::Result<()> {
+    let size = u32::try_from(data.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "G-code block is too large"))?;
+    let start = out.len();
+    out.extend_from_slice(&CODE_BLOCK_ID.to_le_bytes());
+    // No compression.
+    out.extend_from_slice(&0_u16.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    // No encoding.
+    out.extend_from_slice(&0_u16.to_le_bytes());
+    out.extend_from_slice(data);
+    if checksum {
+        let crc = crc32fast::hash(&out[start..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn invalid(e: &nom::Err<BlockError>) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{e:?}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binary::bgcode_parser_with_checksum;
+    use crate::binary::gcode_block::extractor::extract_gcode;
+
+    #[test]
+    fn rewrite() {
+        let input = include_bytes!("../../../../assets/mini_cube_b.bgcode");
+        let (_, blocks) = extract_gcode(input).unwrap();
+        let before = decode_text(&blocks).unwrap();
+
+        // Unchanged, the file is copied exactly.
+        assert_eq!(rewrite_blocks(input, |_| Ok(None)).unwrap(), input);
+
+        // A comment is added to the second block.
+        let mut index = 0;
+        let output = rewrite_blocks(input, |text| {
+            index += 1;
+            Ok((index == 2).then(|| format!(";rewritten\n{text}")))
+        })
+        .unwrap();
+        assert!(bgcode_parser_with_checksum(&output).is_ok());
+        let (_, blocks) = extract_gcode(&output).unwrap();
+        assert_eq!(blocks.len(), index);
+        let first = decode_text(&blocks[..1]).unwrap();
+        let after = decode_text(&blocks).unwrap();
+        assert_eq!(
+            after,
+            format!("{first};rewritten\n{}", &before[first.len()..])
+        );
+    }
+
+    #[test]
+    fn straddle() {
+        // The second line runs on from the first block into the second.
+        let input = include_bytes!("../../../../assets/mini_cube_b.bgcode");
+        let (header, blocks) = split_header(input).unwrap();
+        let mut file = input[..input.len() - blocks.len()].to_vec();
+        encode(b"G1 X1\nG1 X", header.has_checksum(), &mut file).unwrap();
+        encode(b"2\nG1 X3\n", header.has_checksum(), &mut file).unwrap();
+
+        let mut texts = vec![];
+        let output = rewrite_blocks(&file, |text| {
+            texts.push(text.to_string());
+            Ok(None)
+        })
+        .unwrap();
+        assert_eq!(texts, ["G1 X1\n", "G1 X2\nG1 X3\n"]);
+        assert!(bgcode_parser_with_checksum(&output).is_ok());
+        let (_, blocks) = extract_gcode(&output).unwrap();
+        assert_eq!(decode_text(&blocks).unwrap(), "G1 X1\nG1 X2\nG1 X3\n");
+    }
+}
@@ -1,13 +1,33 @@
 use core::f64;
-use core::f64::consts::TAU;
 use core::fmt::Display;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 
-use crate::ArcParams;
-use crate::MM_PER_ARC_SEGMENT;
+use crate::Plane;
 use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::arc_end;
 use crate::command::Command;
-use crate::compute_arc;
+use crate::compute_arc_to;
+use crate::layer::Layer;
+use crate::layer::LayerFilter;
+use crate::layer::LayerTracker;
+use crate::objects::KlipperObjects;
 use crate::params::head::PosVal;
+use crate::params::mp::MultiPartVal;
+use crate::tessellation::Tessellation;
+
+use group::GroupBy;
+use group::Key;
+use projection::Projection;
+
+/// Colour coded groups, by object, feature type, tool or speed.
+pub mod group;
+/// Views and scaling of the SVG.
+pub mod projection;
+/// Streams a SVG file, holding only a bounded amount of path data in memory.
+pub mod writer;
 
 /// SVG representation of a G-Code file.
 ///
@@ -33,23 +53,6 @@ impl Default for Svg {
     }
 }
 
-impl Svg {
-    fn update_view_box(&mut self, proj_x: f64, proj_y: f64) {
-        // Record min max x, y
-        if proj_x > self.max_x {
-            self.max_x = proj_x;
-        }
-        if proj_x < self.min_x {
-            self.min_x = proj_x;
-        }
-        if proj_y > self.max_y {
-            self.max_y = proj_y;
-        }
-        if proj_y < self.min_y {
-            self.min_y = proj_y;
-        }
-    }
-}
 // A line could not be decoded as an G-Code command
 // #[derive(Debug, Clone)]
 // struct GCodeError;
@@ -62,39 +65,46 @@ impl Svg {
 
 impl Display for Svg {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let width = self.max_x - self.min_x;
-        let height = self.max_y - self.min_y;
-        // An empty gcode file will not change min_x/y or max_x/y from
-        // its default of +/-INF respectively.
-        //
-        let parameters = if width.is_finite() && height.is_finite() {
-            format!(
-                "width=\"{width}\" height=\"{height}\" viewBox=\"{} {} {} {}\"",
-                self.min_x, self.min_y, width, height
-            )
-        } else {
-            // In this case silently fail by returning a empty SVG element, without a viewBox parameter.
-            String::new()
-        };
+        let parameters = svg_parameters(self.min_x, self.min_y, self.max_x, self.max_y);
         writeln!(
             f,
             "<svg xmlns=\"http://www.w3.org/2000/svg\" {parameters} >"
         )?;
-        write!(f, "  <path d=\"")?;
+        write!(f, "{PATH_HEADER}")?;
 
         for part in &self.parts {
             write!(f, "{part}")?;
         }
-        write!(
-            f,
-            r#""
-style="fill:none;stroke:green;stroke-width:0.05" />
- </svg>"#
-        )?;
+        write!(f, "{PATH_FOOTER}")?;
         Ok(())
     }
 }
 
+/// Opens the `<path>` element, the d string follows.
+pub(crate) const PATH_HEADER: &str = "  <path d=\"";
+
+/// Closes both the `<path>` and `<svg>` elements.
+pub(crate) const PATH_FOOTER: &str = r#""
+style="fill:none;stroke:green;stroke-width:0.05" />
+ </svg>"#;
+
+/// Returns the width, height and viewBox parameters of the `<svg>` element.
+pub(crate) fn svg_parameters(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> String {
+    let width = max_x - min_x;
+    let height = max_y - min_y;
+    // An empty gcode file will not change min_x/y or max_x/y from
+    // its default of +/-INF respectively.
+    //
+    if width.is_finite() && height.is_finite() {
+        format!(
+            "width=\"{width}\" height=\"{height}\" viewBox=\"{min_x} {min_y} {width} {height}\""
+        )
+    } else {
+        // In this case silently fail by returning a empty SVG element, without a viewBox parameter.
+        String::new()
+    }
+}
+
 /// Returns a SVG given a collection of G-Code commands.
 ///
 /// TODO: Want to iterate over something more flexible
@@ -103,259 +113,444 @@ impl FromIterator<String> for Svg {
     fn from_iter<I>(iter: I) -> Self
     where
         I: IntoIterator<Item = String>,
+    {
+        iter.into_iter()
+            .map(|line| {
+                let (_, command) = Command::parse_line(&line).expect("Command is not parsable");
+                command
+            })
+            .collect()
+    }
+}
+
+/// Returns a SVG given a collection of parsed G-Code commands.
+///
+/// See `decode_blocks()` for a way to generate the commands from a
+/// binary file.
+impl FromIterator<Command> for Svg {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Command>,
     {
         let mut svg = Self::default();
 
         // Invalid if the <path>'s d string does not start with a move.
         svg.parts.push("M0 0".to_string());
 
-        let part_id = Some(0);
+        let mut pen = Pen::default();
+        for command in iter {
+            pen.draw(command, &mut svg);
+        }
+
+        svg
+    }
+}
+
+/// Receives the path data generated by a `Pen`.
+///
+/// `Svg` buffers the data in memory, `SvgWriter` streams it.
+pub(crate) trait Canvas {
+    /// Record a projected point, used to compute the viewBox.
+    fn update_view_box(&mut self, proj_x: f64, proj_y: f64);
+    /// Append a fragment of the `<path>`'s d string.
+    fn push_part(&mut self, part: core::fmt::Arguments<'_>);
+    /// Called as each new layer starts.
+    ///
+    /// Returns true if a new path was started, which must begin with a move.
+    fn start_layer(&mut self, _layer: Layer) -> bool {
+        false
+    }
+    /// Directs the following parts into the group with the given label.
+    ///
+    /// Returns true if a different path was selected, which must begin with a move.
+    fn set_group(&mut self, _label: &str) -> bool {
+        false
+    }
+}
+
+impl Canvas for Svg {
+    fn update_view_box(&mut self, proj_x: f64, proj_y: f64) {
+        // Record min max x, y
+        if proj_x > self.max_x {
+            self.max_x = proj_x;
+        }
+        if proj_x < self.min_x {
+            self.min_x = proj_x;
+        }
+        if proj_y > self.max_y {
+            self.max_y = proj_y;
+        }
+        if proj_y < self.min_y {
+            self.min_y = proj_y;
+        }
+    }
+
+    fn push_part(&mut self, part: core::fmt::Arguments<'_>) {
+        self.parts.push(part.to_string());
+    }
+}
+
+/// The state of the print head.
+///
+/// As each command is replayed the head is moved and the
+/// projected path is drawn on a `Canvas`.
+#[derive(Debug, Default)]
+pub(crate) struct Pen {
+    is_extruding: bool,
+    // Positioning mode for all axes (A, B, C), (U, V, W),  (X, Y, Z).
+    position_mode: PositionMode,
+    // The plane of G2/G3 arcs.
+    plane: Plane,
+    // X and Y position of tool head (before projection).
+    current_x: f64,
+    current_y: f64,
+    current_z: f64,
+
+    origin_x: f64,
+    origin_y: f64,
+    origin_z: f64,
+
+    projection: Projection,
+
+    layers: LayerTracker,
+    filter: LayerFilter,
+    // The last projected point, drawn or not.
+    last_proj: (f64, f64),
+    // Parts have been hidden, or a new path started, since the last move.
+    needs_move: bool,
+
+    group_by: GroupBy,
+    show_travel: bool,
+    tessellation: Tessellation,
+    // The group of the canvas's current path.
+    group: Option<Key>,
+    // The M486 object being printed.
+    object: Option<i128>,
+    // Object names, from M486.
+    names: BTreeMap<i128, String>,
+    // Klipper's named objects, numbered as M486 objects.
+    klipper: KlipperObjects,
+    // Feature types, from ";TYPE:" comments, in order of appearance.
+    features: Vec<String>,
+    feature: Option<usize>,
+    tool: u8,
+    // mm/min
+    feedrate: f64,
+}
+
+impl Pen {
+    pub(crate) fn new(projection: Projection) -> Self {
+        Self {
+            projection,
+            last_proj: projection.project(0_f64, 0_f64, 0_f64),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) const fn set_filter(&mut self, filter: LayerFilter) {
+        self.filter = filter;
+    }
+
+    pub(crate) const fn set_group_by(&mut self, group_by: GroupBy) {
+        self.group_by = group_by;
+    }
+
+    pub(crate) const fn set_show_travel(&mut self, show_travel: bool) {
+        self.show_travel = show_travel;
+    }
+
+    pub(crate) const fn set_tessellation(&mut self, tessellation: Tessellation) {
+        self.tessellation = tessellation;
+    }
+
+    fn is_visible(&self) -> bool {
+        self.filter.contains(self.layers.current())
+    }
+
+    fn start_layer<C: Canvas>(&mut self, canvas: &mut C) {
+        if let Some(layer) = self.layers.current()
+            && canvas.start_layer(layer)
+        {
+            self.needs_move = true;
+            self.group = None;
+        }
+    }
+
+    // Before a line is drawn, selects the group it belongs to.
+    fn enter_group<C: Canvas>(&mut self, is_travel: bool, canvas: &mut C) {
+        if matches!(self.group_by, GroupBy::None) && !self.show_travel {
+            return;
+        }
+        let key = if is_travel {
+            Key::Travel
+        } else {
+            match self.group_by {
+                GroupBy::None => Key::Print,
+                GroupBy::Object => Key::Object(self.object),
+                GroupBy::Feature => Key::Feature(self.feature),
+                GroupBy::Tool => Key::Tool(self.tool),
+                GroupBy::Speed { bucket } => {
+                    let speed = self.feedrate / 60_f64;
+                    Key::Speed(((speed / bucket).floor() * bucket).to_bits())
+                }
+            }
+        };
+        if self.group != Some(key) {
+            self.group = Some(key);
+            let label = key.label(&self.features, &self.names, self.group_by);
+            if canvas.set_group(&label) {
+                self.needs_move = true;
+            }
+        }
+    }
+
+    // Records the feature type given by a ";TYPE:" comment.
+    fn set_feature(&mut self, feature: &str) {
+        let index = self
+            .features
+            .iter()
+            .position(|f| f == feature)
+            .unwrap_or_else(|| {
+                self.features.push(feature.to_string());
+                self.features.len() - 1
+            });
+        self.feature = Some(index);
+    }
+
+    // Before a line is drawn, moves to the point where the line starts
+    // if the preceding parts were not drawn.
+    fn resume<C: Canvas>(&mut self, canvas: &mut C) {
+        if self.needs_move {
+            self.needs_move = false;
+            let (proj_x, proj_y) = self.last_proj;
+            canvas.update_view_box(proj_x, proj_y);
+            canvas.push_part(format_args!("M{proj_x:.3} {proj_y:.3}"));
+        }
+    }
+
+    // Moves to the current position, drawing a line when asked to, or
+    // when the move is hidden, waiting for the next line drawn.
+    fn move_to<C: Canvas>(&mut self, is_drawn: bool, is_travel: bool, canvas: &mut C) {
+        let (proj_x, proj_y) = self.project(self.current_x, self.current_y);
+        if self.is_visible() {
+            if is_drawn {
+                self.enter_group(is_travel, canvas);
+                self.resume(canvas);
+                canvas.update_view_box(proj_x, proj_y);
+                canvas.push_part(format_args!("L{proj_x:.3} {proj_y:.3}"));
+            } else {
+                self.needs_move = false;
+                canvas.update_view_box(proj_x, proj_y);
+                canvas.push_part(format_args!("M{proj_x:.3} {proj_y:.3}"));
+            }
+        } else {
+            self.needs_move = true;
+        }
+        self.last_proj = (proj_x, proj_y);
+    }
+
+    // Draws a G2/G3 arc, in the selected plane, following a helix when
+    // the third axis changes.
+    fn draw_arc<C: Canvas>(&mut self, form: &ArcForm, clockwise: bool, canvas: &mut C) {
+        let (ArcForm::IJ(vals) | ArcForm::R(vals)) = form;
         let mut is_extruding = false;
-        // Positioning mode for all axes (A, B, C), (U, V, W),  (X, Y, Z).
-        let mut position_mode = PositionMode::default();
-        // X and Y position of tool head (before projection).
-        let mut current_x = 0_f64;
-        let mut current_y = 0_f64;
-        let mut current_z = 0_f64;
-
-        let mut origin_x = 0_f64;
-        let mut origin_y = 0_f64;
-        let mut origin_z = 0_f64;
-
-        for line in iter {
-            let (_, command) = Command::parse_line(&line).expect("Command is not parsable");
-
-            match command {
-                // Treat G0 and G1 command identically.
-                //
-                // A G0 is a non-printing move but E is present in files seen in the wild.
-                // (In the assets directory see the gears and benchy2 files.)
-                Command::G0(mut payload) | Command::G1(mut payload) => {
-                    // Candidate value of params X<number> Y<number>
-                    let mut x_param = f64::NAN;
-                    let mut y_param = f64::NAN;
-                    let mut z_param = f64::NAN;
-
-                    for param in payload.drain() {
-                        match param {
-                            PosVal::X(val) => x_param = val,
-                            PosVal::Y(val) => y_param = val,
-                            PosVal::Z(val) => z_param = val,
-                            // Negative values the extruder is "wiping"
-                            // or sucking filament back into the extruder.
-                            PosVal::E(val) => is_extruding = val > 0_f64,
-                            _ => {}
-                        }
-                    }
+        for val in vals {
+            match val {
+                ArcVal::E(e) => is_extruding = *e > 0_f64,
+                ArcVal::F(f) => self.feedrate = *f,
+                _ => {}
+            }
+        }
+        let start = [self.current_x, self.current_y, self.current_z];
+        let end = arc_end(start, form, self.position_mode);
+        if is_extruding && self.layers.extrude(end[2]) {
+            self.start_layer(canvas);
+        }
 
-                    if !x_param.is_nan() {
-                        current_x = match position_mode {
-                            PositionMode::Absolute => x_param,
-                            PositionMode::Relative => current_x + x_param,
-                        }
-                    }
+        let is_drawn = is_extruding || self.show_travel;
+        if is_drawn {
+            let [a, b, n] = self.plane.axes();
+            let params = compute_arc_to((start[a], start[b]), (end[a], end[b]), form, self.plane);
+            let sweep = params.sweep(clockwise);
+            for [u, v, t] in self.tessellation.points(&params, sweep) {
+                let mut point = [0_f64; 3];
+                point[a] = u;
+                point[b] = v;
+                point[n] = (end[n] - start[n]).mul_add(t, start[n]);
+                [self.current_x, self.current_y, self.current_z] = point;
+                self.move_to(true, !is_extruding, canvas);
+            }
+        }
+        // The last point lands on the end, free of rounding errors.
+        [self.current_x, self.current_y, self.current_z] = end;
+        if !is_drawn {
+            self.move_to(false, true, canvas);
+        }
+    }
 
-                    if !y_param.is_nan() {
-                        current_y = match position_mode {
-                            PositionMode::Absolute => y_param,
-                            PositionMode::Relative => current_y + y_param,
-                        }
-                    }
+    // Projects a point at the current height.
+    fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        self.projection.project(
+            self.origin_x + x,
+            self.origin_y + y,
+            self.origin_z + self.current_z,
+        )
+    }
 
-                    if !z_param.is_nan() {
-                        current_z = match position_mode {
-                            PositionMode::Absolute => z_param,
-                            PositionMode::Relative => current_z + z_param,
-                        };
+    // If the current position is at X=4 and G92 X7 is programmed,
+    //  the current position is redefined as X=7, effectively
+    // moving the origin of the coordinate system -3 units in X.""
+    fn set_position<C: Canvas>(&mut self, mut params: HashSet<PosVal>, canvas: &mut C) {
+        // The extrude rate is going to zero
+        // enter MoveMode ..ie not laying down filament.
+        for param in params.drain() {
+            match param {
+                PosVal::E(val) => {
+                    // Negative values the extruder is "wiping"
+                    // or sucking filament back into the extruder.
+                    self.is_extruding = val > 0_f64;
+                }
+                PosVal::X(val) => match self.position_mode {
+                    PositionMode::Absolute => {
+                        self.origin_x = self.current_x - val;
+                        self.current_x = val;
                     }
+                    PositionMode::Relative => {
+                        unimplemented!("Relative position mode origin adjust ");
+                    }
+                },
+                PosVal::Y(val) => match self.position_mode {
+                    PositionMode::Absolute => {
+                        self.origin_y = self.current_y - val;
+                        self.current_y = val;
+                    }
+                    PositionMode::Relative => {
+                        unimplemented!("Relative position mode origin adjust ");
+                    }
+                },
+                PosVal::Z(val) => match self.position_mode {
+                    PositionMode::Absolute => {
+                        self.origin_z = self.current_z - val;
+                        self.current_z = val;
+                    }
+                    PositionMode::Relative => {
+                        unimplemented!("Relative position mode origin adjust ");
+                    }
+                },
+                _ => { /* Silently drop. */ }
+            }
+        }
 
-                    let proj_x =
-                        f64::midpoint(origin_y, current_y) + f64::midpoint(origin_x, current_x);
-                    let proj_y = -(origin_z + current_z) - f64::midpoint(origin_y, current_y)
-                        + f64::midpoint(origin_x, current_x);
-                    svg.update_view_box(proj_x, proj_y);
+        // Set Position is by definition a move only.
+        let (proj_x, proj_y) = self.project(self.current_x, self.current_y);
+        if self.is_visible() {
+            self.needs_move = false;
+            canvas.update_view_box(proj_x, proj_y);
+            canvas.push_part(format_args!("M{proj_x} {proj_y}"));
+        } else {
+            self.needs_move = true;
+        }
+        self.last_proj = (proj_x, proj_y);
+    }
 
-                    if is_extruding && part_id.is_some() {
-                        svg.parts.push(format!("L{proj_x:.3} {proj_y:.3}"));
-                    } else {
-                        svg.parts.push(format!("M{proj_x:.3} {proj_y:.3}"));
-                    }
+    // Tracks the object being printed, and the names given to objects.
+    fn mark_object(&mut self, val: MultiPartVal) {
+        match val {
+            MultiPartVal::S(id, name) => {
+                self.object = Some(id);
+                if let Some(name) = name {
+                    self.names.insert(id, name);
                 }
-                Command::G2(arc_form) => {
-                    // Clockwise arc
-                    let ArcParams {
-                        center,
-                        radius,
-                        mut theta_start,
-                        theta_end,
-                    } = compute_arc(current_x, current_y, &arc_form);
-
-                    // Regarding the Ambiguity/Equivalence  of the angles 0 and 2PI
-                    // All values here are in the range 0<=theta<2PI
-                    // We are rotating clockwise
-                    // in this cased the start angle of 0 should be read as 2PI
-                    if theta_start == 0_f64 {
-                        theta_start = TAU;
-                    }
+            }
+            // Un-cancel also starts the object.
+            MultiPartVal::U(id) => self.object = Some(id),
+            // Names the current object.
+            MultiPartVal::A(name) => {
+                if let Some(id) = self.object {
+                    self.names.insert(id, name);
+                }
+            }
+            _ => {}
+        }
+    }
 
-                    let delta_theta = if theta_start < theta_end {
-                        // Adjust for zero crossing
-                        // say 115 -> 304 degrees
-                        // delta_theta = 115 + (360 - 304 ) = 170
-                        theta_start + (TAU - theta_end)
-                    } else {
-                        theta_start - theta_end
-                    };
-                    let total_arc_length = delta_theta * radius;
-                    // n_steps must be a number > 0
-                    let n_steps = (total_arc_length / MM_PER_ARC_SEGMENT).ceil();
-                    let theta_step = delta_theta / n_steps;
-
-                    // x,y are the position of the head in absolute units.
-                    let mut x = f64::NAN;
-                    let mut y = f64::NAN;
-                    // For loop: f64 has a problem with numerical accuracy
-                    // specifically the comparing limit.
-                    // rust idiomatically insists on indexed here
-                    for i in 0..=n_steps as u64 {
-                        let theta = (i as f64).mul_add(-theta_step, theta_start) % TAU;
-                        x = radius.mul_add(theta.cos(), center.0);
-                        y = radius.mul_add(theta.sin(), center.1);
-
-                        let proj_x = (origin_x + x + origin_y + y) / 2_f64;
-                        let proj_y = -(origin_z + current_z) - f64::midpoint(origin_y, y)
-                            + f64::midpoint(origin_x, x);
-                        svg.update_view_box(proj_x, proj_y);
-                        match position_mode {
-                            PositionMode::Absolute => {
-                                svg.parts.push(format!("L{proj_x:.3} {proj_y:.3}"));
-                            }
-                            PositionMode::Relative => {
-                                svg.parts.push(format!("l{proj_x:.3} {proj_y:.3}"));
-                            }
-                        }
+    /// Moves the head, drawing the projected path on the canvas.
+    pub(crate) fn draw<C: Canvas>(&mut self, command: Command, canvas: &mut C) {
+        match self.klipper.translate(command) {
+            // Treat G0 and G1 command identically.
+            //
+            // A G0 is a non-printing move but E is present in files seen in the wild.
+            // (In the assets directory see the gears and benchy2 files.)
+            Command::G0(mut payload) | Command::G1(mut payload) => {
+                // Candidate value of params X<number> Y<number>
+                let mut x_param = f64::NAN;
+                let mut y_param = f64::NAN;
+                let mut z_param = f64::NAN;
+
+                for param in payload.drain() {
+                    match param {
+                        PosVal::X(val) => x_param = val,
+                        PosVal::Y(val) => y_param = val,
+                        PosVal::Z(val) => z_param = val,
+                        // Negative values the extruder is "wiping"
+                        // or sucking filament back into the extruder.
+                        PosVal::E(val) => self.is_extruding = val > 0_f64,
+                        PosVal::F(val) => self.feedrate = val,
+                        _ => {}
                     }
-
-                    current_x = x;
-                    current_y = y;
                 }
-                Command::G3(arc_form) => {
-                    // Anti-Clockwise arc
-                    let ArcParams {
-                        center,
-                        radius,
-                        theta_start,
-                        mut theta_end,
-                    } = compute_arc(current_x, current_y, &arc_form);
-
-                    // Regarding the Ambiguity/Equivalence  of the angles 0 and 2PI
-                    // All values here are in the range 0<=theta<2PI
-                    // We are rotating anticlockwise
-                    // in this cased the final angle of 0 should be read as 2PI
-                    if theta_end == 0_f64 {
-                        theta_end = TAU;
+
+                if !x_param.is_nan() {
+                    self.current_x = match self.position_mode {
+                        PositionMode::Absolute => x_param,
+                        PositionMode::Relative => self.current_x + x_param,
                     }
+                }
 
-                    let delta_theta = if theta_start > theta_end {
-                        // Adjust for zero crossing
-                        // say 306 -> 115 degrees
-                        // delta_theta = (360 - 305 ) + 115 = 170
-                        TAU - theta_start + theta_end
-                    } else {
-                        theta_end - theta_start
-                    };
-                    let total_arc_length = delta_theta * radius;
-                    // n_steps must be a number > 0
-                    let n_steps = (total_arc_length / MM_PER_ARC_SEGMENT).ceil();
-                    let theta_step = delta_theta / n_steps;
-
-                    // For loop with f64 have a problem with numerical accuracy
-                    // specifically the comparing limit.
-                    // rust idiomatically insists on indexed here
-                    let mut x = f64::NAN;
-                    let mut y = f64::NAN;
-                    for i in 0..=n_steps as u64 {
-                        let theta = (i as f64).mul_add(theta_step, theta_start) % TAU;
-                        x = radius.mul_add(theta.cos(), center.0);
-                        y = radius.mul_add(theta.sin(), center.1);
-
-                        let proj_x = (origin_x + x + origin_y + y) / 2.;
-                        let proj_y = -(origin_z + current_z) - f64::midpoint(origin_y, y)
-                            + f64::midpoint(origin_x, x);
-                        svg.update_view_box(proj_x, proj_y);
-                        match position_mode {
-                            PositionMode::Absolute => {
-                                svg.parts.push(format!("L{proj_x:.3} {proj_y:.3}"));
-                            }
-                            PositionMode::Relative => {
-                                svg.parts.push(format!("l{proj_x:.3} {proj_y:.3}"));
-                            }
-                        }
+                if !y_param.is_nan() {
+                    self.current_y = match self.position_mode {
+                        PositionMode::Absolute => y_param,
+                        PositionMode::Relative => self.current_y + y_param,
                     }
+                }
 
-                    current_x = x;
-                    current_y = y;
+                if !z_param.is_nan() {
+                    self.current_z = match self.position_mode {
+                        PositionMode::Absolute => z_param,
+                        PositionMode::Relative => self.current_z + z_param,
+                    };
                 }
-                Command::G90 => position_mode = PositionMode::Absolute,
-                Command::G91 => position_mode = PositionMode::Relative,
-
-                // If the current position is at X=4 and G92 X7 is programmed,
-                //  the current position is redefined as X=7, effectively
-                // moving the origin of the coordinate system -3 units in X.""
-                Command::G92(mut params) => {
-                    // The extrude rate is going to zero
-                    // enter MoveMode ..ie not laying down filament.
-                    for param in params.drain() {
-                        match param {
-                            PosVal::E(val) => {
-                                // Negative values the extruder is "wiping"
-                                // or sucking filament back into the extruder.
-                                is_extruding = val > 0_f64;
-                            }
-                            PosVal::X(val) => match position_mode {
-                                PositionMode::Absolute => {
-                                    origin_x = current_x - val;
-                                    current_x = val;
-                                }
-                                PositionMode::Relative => {
-                                    unimplemented!("Relative position mode origin adjust ");
-                                }
-                            },
-                            PosVal::Y(val) => match position_mode {
-                                PositionMode::Absolute => {
-                                    origin_y = current_x - val;
-                                    current_y = val;
-                                }
-                                PositionMode::Relative => {
-                                    unimplemented!("Relative position mode origin adjust ");
-                                }
-                            },
-                            PosVal::Z(val) => match position_mode {
-                                PositionMode::Absolute => {
-                                    origin_z = current_z - val;
-                                    current_z = val;
-                                }
-                                PositionMode::Relative => {
-                                    unimplemented!("Relative position mode origin adjust ");
-                                }
-                            },
-                            _ => { /* Silently drop. */ }
-                        }
-                    }
 
-                    // Set Position is by definition a move only.
-                    let proj_x = (origin_x + current_x + origin_y + current_y) / 2.;
-                    let proj_y = -(origin_z + current_z) - f64::midpoint(origin_y, current_y)
-                        + f64::midpoint(origin_x, current_x);
-                    svg.update_view_box(proj_x, proj_y);
+                // Priming the nozzle, without moving, does not start a layer.
+                let is_moving = !x_param.is_nan() || !y_param.is_nan();
+                if self.is_extruding && is_moving && self.layers.extrude(self.current_z) {
+                    self.start_layer(canvas);
+                }
 
-                    svg.parts.push(format!("M{proj_x} {proj_y}"));
+                let is_drawn = self.is_extruding || (self.show_travel && is_moving);
+                self.move_to(is_drawn, !self.is_extruding, canvas);
+            }
+            Command::G2(arc_form) => self.draw_arc(&arc_form, true, canvas),
+            Command::G3(arc_form) => self.draw_arc(&arc_form, false, canvas),
+            Command::G17 => self.plane = Plane::XY,
+            Command::G18 => self.plane = Plane::ZX,
+            Command::G19 => self.plane = Plane::YZ,
+            Command::G90 => self.position_mode = PositionMode::Absolute,
+            Command::G91 => self.position_mode = PositionMode::Relative,
+
+            Command::G92(params) => self.set_position(params, canvas),
+            Command::Comment(text) => {
+                if let Some(feature) = text.strip_prefix("TYPE:") {
+                    self.set_feature(feature.trim());
+                }
+                if self.layers.comment(&text) {
+                    self.start_layer(canvas);
                 }
-                _ => {}
             }
+            Command::M486(val) => self.mark_object(val),
+            Command::T(tool) => self.tool = tool,
+            _ => {}
         }
-
-        svg
     }
 }
 
@@ -462,4 +657,23 @@ G1 Z0.350 F7800.000
             .collect::<Svg>();
         assert_debug_snapshot!(svg);
     }
+
+    #[test]
+    fn set_position() {
+        // G92 moves the origin of each axis by its own offset, so a move
+        // to the new zero stays where the head already is.
+        let svg = ["G1 X10 Y20", "G92 X0 Y0", "G1 X0 Y0 E1"]
+            .into_iter()
+            .map(std::string::ToString::to_string)
+            .collect::<Svg>();
+        assert!(svg.to_string().contains("M15 -5L15.000 -5.000"));
+    }
+
+    #[test]
+    fn endless_arc() {
+        // P has no upper bound, the turns drawn do.
+        let svg = core::iter::once("G2 X1 Y0 I1 P1000000000000 E1".to_string()).collect::<Svg>();
+        let segments = svg.to_string().matches('L').count();
+        assert!(segments > 1000 && segments <= 1 << 16, "{segments}");
+    }
 }
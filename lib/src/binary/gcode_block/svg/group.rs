@@ -0,0 +1,176 @@
+//! Splits the drawing into colour coded `<g>` elements.
+//!
+//! Each group is drawn in a colour taken from a fixed palette, in the
+//! order the groups first appear. Travel moves, when drawn, are always
+//! grey dashed lines.
+
+use std::collections::BTreeMap;
+
+/// The colour of travel moves.
+const TRAVEL_COLOUR: &str = "#999999";
+
+/// Colours assigned to groups in order of appearance.
+const PALETTE: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+/// How extrusions are split into groups.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GroupBy {
+    /// A single green path, no legend.
+    #[default]
+    None,
+    /// By the object being printed, as set by M486.
+    Object,
+    /// By the feature type given in the slicer's ";TYPE:" comments.
+    Feature,
+    /// By the selected tool (T0, T1 ...).
+    Tool,
+    /// By the speed of the move.
+    Speed {
+        /// The width of each speed range, in mm/s.
+        bucket: f64,
+    },
+}
+
+/// Identifies the group of a single part of the path.
+///
+/// Cheap to compare, the label is only generated when the group changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Key {
+    /// Everything, when not grouping.
+    Print,
+    /// Non extruding moves.
+    Travel,
+    /// A M486 object id, `None` outside of any object.
+    Object(Option<i128>),
+    /// An index into the list of feature names seen so far.
+    Feature(Option<usize>),
+    /// A tool number.
+    Tool(u8),
+    /// The lower bound of a speed range in mm/s, stored as bits.
+    Speed(u64),
+}
+
+impl Key {
+    /// Returns a label for the legend.
+    ///
+    /// Objects are labelled with their M486 name, when given.
+    pub(crate) fn label(
+        self,
+        features: &[String],
+        names: &BTreeMap<i128, String>,
+        group_by: GroupBy,
+    ) -> String {
+        match self {
+            Self::Print => "print".to_string(),
+            Self::Travel => "travel".to_string(),
+            // S-1 marks a purge tower, or other global feature.
+            Self::Object(Some(id)) if id >= 0 => names
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| format!("object {id}")),
+            Self::Object(_) => "non-object".to_string(),
+            Self::Feature(Some(index)) => features[index].clone(),
+            Self::Feature(None) => "unknown".to_string(),
+            Self::Tool(tool) => format!("T{tool}"),
+            Self::Speed(bits) => {
+                let lower = f64::from_bits(bits);
+                let bucket = match group_by {
+                    GroupBy::Speed { bucket } => bucket,
+                    _ => 0_f64,
+                };
+                format!("speed {lower}-{} mm/s", lower + bucket)
+            }
+        }
+    }
+}
+
+/// Returns the colour used by the n'th group.
+pub(crate) fn colour(label: &str, n: usize) -> &'static str {
+    if label == "travel" {
+        TRAVEL_COLOUR
+    } else {
+        PALETTE[n % PALETTE.len()]
+    }
+}
+
+/// Returns the style of the n'th group.
+pub(crate) fn style(label: &str, n: usize) -> String {
+    let colour = colour(label, n);
+    if label == "travel" {
+        format!("fill:none;stroke:{colour};stroke-width:0.05;stroke-dasharray:0.5 0.5")
+    } else {
+        format!("fill:none;stroke:{colour};stroke-width:0.05")
+    }
+}
+
+/// Converts a label into a valid id attribute.
+///
+/// "External perimeter" becomes "external-perimeter". An id must start
+/// with a letter, others are prefixed with "g-".
+pub(crate) fn id(label: &str) -> String {
+    let prefix = if label.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        ""
+    } else {
+        "g-"
+    };
+    prefix
+        .chars()
+        .chain(label.chars().map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        }))
+        .collect()
+}
+
+/// Escapes text for use in XML content.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels() {
+        let features = vec!["External perimeter".to_string()];
+        let names = BTreeMap::from([(3, "cube copy 3".to_string())]);
+        assert_eq!(
+            Key::Object(Some(2)).label(&features, &names, GroupBy::Object),
+            "object 2"
+        );
+        assert_eq!(
+            Key::Object(Some(-1)).label(&features, &names, GroupBy::Object),
+            "non-object"
+        );
+        assert_eq!(
+            Key::Feature(Some(0)).label(&features, &names, GroupBy::Feature),
+            "External perimeter"
+        );
+        assert_eq!(
+            Key::Speed(20_f64.to_bits()).label(
+                &features,
+                &names,
+                GroupBy::Speed { bucket: 10_f64 }
+            ),
+            "speed 20-30 mm/s"
+        );
+        assert_eq!(
+            Key::Object(Some(3)).label(&features, &names, GroupBy::Object),
+            "cube copy 3"
+        );
+        assert_eq!(id("External perimeter"), "external-perimeter");
+        assert_eq!(id("cube copy 3"), "cube-copy-3");
+        assert_eq!(id("speed 20-30 mm/s"), "speed-20-30-mm-s");
+        assert_eq!(id("3 cubes"), "g-3-cubes");
+    }
+}
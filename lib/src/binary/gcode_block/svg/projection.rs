@@ -0,0 +1,145 @@
+//! Maps the position of the print head onto the plane of the SVG.
+//!
+//! The SVG y axis points down the page, so every view negates
+//! the vertical axis.
+
+/// The direction the print is viewed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum View {
+    /// Plan view, looking down the Z axis onto the XY plane.
+    Top,
+    /// Looking along the Y axis onto the XZ plane.
+    Front,
+    /// Looking along the X axis onto the YZ plane.
+    Side,
+    /// A view from above the front right corner of the bed.
+    #[default]
+    Isometric,
+    /// An arbitrary view.
+    Custom {
+        /// Rotation of the print about the Z axis, in degrees.
+        rotate_z: f64,
+        /// The angle of the viewer above the bed, in degrees.
+        ///
+        /// 90 is equivalent to `View::Top`, 0 to `View::Front`.
+        elevation: f64,
+    },
+}
+
+/// A view and a scale factor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projection {
+    /// The direction the print is viewed from.
+    pub view: View,
+    /// Multiplies all projected coordinates.
+    pub scale: f64,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            view: View::default(),
+            scale: 1_f64,
+        }
+    }
+}
+
+impl Projection {
+    /// A unscaled projection.
+    #[must_use]
+    pub const fn new(view: View) -> Self {
+        Self { view, scale: 1_f64 }
+    }
+
+    /// Returns a copy of the projection with a new scale factor.
+    #[must_use]
+    pub const fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Returns the (x, y) SVG coordinates of a point in machine coordinates.
+    #[must_use]
+    pub fn project(&self, x: f64, y: f64, z: f64) -> (f64, f64) {
+        let (proj_x, proj_y) = match self.view {
+            View::Top => (x, -y),
+            View::Front => (x, -z),
+            View::Side => (y, -z),
+            View::Isometric => {
+                let half_x = x / 2_f64;
+                let half_y = y / 2_f64;
+                (half_y + half_x, -z - half_y + half_x)
+            }
+            View::Custom {
+                rotate_z,
+                elevation,
+            } => {
+                let (sin_r, cos_r) = rotate_z.to_radians().sin_cos();
+                let (sin_e, cos_e) = elevation.to_radians().sin_cos();
+                let rotated_x = x.mul_add(cos_r, -y * sin_r);
+                let rotated_y = x.mul_add(sin_r, y * cos_r);
+                (rotated_x, -rotated_y.mul_add(sin_e, z * cos_e))
+            }
+        };
+
+        (proj_x * self.scale, proj_y * self.scale)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round(p: (f64, f64)) -> (f64, f64) {
+        (
+            (p.0 * 1000.0).round() / 1000.0,
+            (p.1 * 1000.0).round() / 1000.0,
+        )
+    }
+
+    #[test]
+    fn planes() {
+        assert_eq!(
+            Projection::new(View::Top).project(1.0, 2.0, 3.0),
+            (1.0, -2.0)
+        );
+        assert_eq!(
+            Projection::new(View::Front).project(1.0, 2.0, 3.0),
+            (1.0, -3.0)
+        );
+        assert_eq!(
+            Projection::new(View::Side).project(1.0, 2.0, 3.0),
+            (2.0, -3.0)
+        );
+    }
+
+    #[test]
+    fn custom() {
+        // Elevation of 90 degrees, is a top down view.
+        let top = Projection::new(View::Custom {
+            rotate_z: 0.0,
+            elevation: 90.0,
+        });
+        assert_eq!(round(top.project(1.0, 2.0, 3.0)), (1.0, -2.0));
+
+        // Elevation of 0 degrees, is the front view.
+        let front = Projection::new(View::Custom {
+            rotate_z: 0.0,
+            elevation: 0.0,
+        });
+        assert_eq!(round(front.project(1.0, 2.0, 3.0)), (1.0, -3.0));
+
+        // Quarter turn about Z, the X axis now points up the page.
+        let rotated = Projection::new(View::Custom {
+            rotate_z: 90.0,
+            elevation: 90.0,
+        });
+        assert_eq!(round(rotated.project(1.0, 0.0, 0.0)), (0.0, -1.0));
+    }
+
+    #[test]
+    fn scale() {
+        let top = Projection::new(View::Top).with_scale(2.0);
+        assert_eq!(top.project(1.0, 2.0, 3.0), (2.0, -4.0));
+    }
+}
@@ -0,0 +1,710 @@
+//! A streaming alternative to `Svg`.
+//!
+//! `Svg` holds every fragment of the path as a separate `String`, and
+//! only writes them out in `Display`. For a large print the path is held
+//! in memory several times over.
+//!
+//! The `<svg>` element's viewBox is the only reason to buffer. Here the
+//! path data is formatted directly into a temporary buffer. With the
+//! `spool` feature the buffer spills over to a file on disk when it grows
+//! large. Once the final command is
+//! drawn the header is written, followed by a copy of the buffer.
+use std::io;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+#[cfg(feature = "spool")]
+use tempfile::SpooledTempFile;
+
+use crate::command::Command;
+use crate::layer::Layer;
+use crate::layer::LayerFilter;
+use crate::tessellation::Tessellation;
+
+use super::Canvas;
+use super::PATH_FOOTER;
+use super::PATH_HEADER;
+use super::Pen;
+use super::group;
+use super::group::GroupBy;
+use super::projection::Projection;
+use super::svg_parameters;
+
+/// The number of bytes of path data held in memory before spilling to disk.
+#[cfg(feature = "spool")]
+const SPOOL_LIMIT: usize = 1 << 20;
+
+/// Holds formatted path data until the header can be written.
+#[cfg(feature = "spool")]
+type Scratch = SpooledTempFile;
+/// Holds formatted path data until the header can be written.
+#[cfg(not(feature = "spool"))]
+type Scratch = io::Cursor<Vec<u8>>;
+
+/// Writes a SVG, given a stream of G-Code commands.
+///
+/// The output is identical to the `Display` of a `Svg`
+/// collected from the same commands.
+///
+/// ```rust
+/// use gcode_nom::binary::gcode_block::svg::writer::SvgWriter;
+/// use gcode_nom::command::Command;
+///
+/// let mut writer = SvgWriter::new(vec![]);
+/// for line in ["G1 X10 Y10 E1", "G1 X20 E2"] {
+///     let (_, command) = Command::parse_line(line).unwrap();
+///     writer.write_command(command).unwrap();
+/// }
+/// let svg = writer.finish().unwrap();
+/// assert!(svg.starts_with(b"<svg"));
+/// ```
+#[derive(Debug)]
+pub struct SvgWriter<W: Write> {
+    out: W,
+    pen: Pen,
+    path: PathBuffer,
+}
+
+impl<W: Write> SvgWriter<W> {
+    /// Creates a writer, the SVG is written to `out` when finished.
+    ///
+    /// Uses the default isometric projection.
+    pub fn new(out: W) -> Self {
+        Self::with_projection(out, Projection::default())
+    }
+
+    /// Creates a writer which views the print using the given projection.
+    pub fn with_projection(out: W, projection: Projection) -> Self {
+        let mut path = PathBuffer::default();
+        // Invalid if the <path>'s d string does not start with a move.
+        path.push_part(format_args!("M0 0"));
+        Self {
+            out,
+            pen: Pen::new(projection),
+            path,
+        }
+    }
+
+    /// Draws only the layers which pass the filter.
+    #[must_use]
+    pub const fn with_filter(mut self, filter: LayerFilter) -> Self {
+        self.pen.set_filter(filter);
+        self
+    }
+
+    /// Splits the drawing into colour coded groups, with a legend.
+    #[must_use]
+    pub const fn with_group_by(mut self, group_by: GroupBy) -> Self {
+        self.pen.set_group_by(group_by);
+        self
+    }
+
+    /// Draws travel moves as grey dashed lines.
+    #[must_use]
+    pub const fn with_travel(mut self, show_travel: bool) -> Self {
+        self.pen.set_show_travel(show_travel);
+        self
+    }
+
+    /// Sets how finely arcs are split into segments.
+    #[must_use]
+    pub const fn with_tessellation(mut self, tessellation: Tessellation) -> Self {
+        self.pen.set_tessellation(tessellation);
+        self
+    }
+
+    /// Draws a single command.
+    ///
+    /// # Errors
+    ///   When the temporary buffer cannot be written to.
+    pub fn write_command(&mut self, command: Command) -> io::Result<()> {
+        self.pen.draw(command, &mut self.path);
+        self.path.take_error()
+    }
+
+    /// Draws a series of commands.
+    ///
+    /// # Errors
+    ///   When the temporary buffer cannot be written to.
+    pub fn write_commands<I>(&mut self, commands: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = Command>,
+    {
+        for command in commands {
+            self.write_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the complete SVG, returning the underlying writer.
+    ///
+    /// # Errors
+    ///   When either the temporary buffer or the output cannot be accessed.
+    pub fn finish(self) -> io::Result<W> {
+        let Self { mut out, path, .. } = self;
+        path.write_document(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// Writes a separate SVG for each layer of the print.
+///
+/// `create` is called with each layer as it completes, and returns the
+/// output for that layer's SVG. Layers with nothing to draw are skipped,
+/// as is anything drawn before the first layer.
+///
+/// ```rust
+/// use gcode_nom::binary::gcode_block::svg::projection::Projection;
+/// use gcode_nom::binary::gcode_block::svg::writer::LayerSvgWriter;
+/// use gcode_nom::command::Command;
+///
+/// let mut layers = vec![];
+/// let mut writer = LayerSvgWriter::new(Projection::default(), |layer| {
+///     layers.push(layer.index);
+///     Ok(std::io::sink())
+/// });
+/// for line in ["G1 Z0.2", "G1 X10 E1", "G1 Z0.4", "G1 X0 E2"] {
+///     let (_, command) = Command::parse_line(line).unwrap();
+///     writer.write_command(command).unwrap();
+/// }
+/// assert_eq!(writer.finish().unwrap(), 2);
+/// assert_eq!(layers, vec![0, 1]);
+/// ```
+#[derive(Debug)]
+pub struct LayerSvgWriter<W, F>
+where
+    W: Write,
+    F: FnMut(Layer) -> io::Result<W>,
+{
+    pen: Pen,
+    pages: Pages<F>,
+}
+
+impl<W, F> LayerSvgWriter<W, F>
+where
+    W: Write,
+    F: FnMut(Layer) -> io::Result<W>,
+{
+    /// Creates a writer which views each layer using the given projection.
+    pub fn new(projection: Projection, create: F) -> Self {
+        Self {
+            pen: Pen::new(projection),
+            pages: Pages {
+                create,
+                current: None,
+                written: 0,
+                error: None,
+            },
+        }
+    }
+
+    /// Writes only the layers which pass the filter.
+    #[must_use]
+    pub const fn with_filter(mut self, filter: LayerFilter) -> Self {
+        self.pen.set_filter(filter);
+        self
+    }
+
+    /// Splits each drawing into colour coded groups, with a legend.
+    #[must_use]
+    pub const fn with_group_by(mut self, group_by: GroupBy) -> Self {
+        self.pen.set_group_by(group_by);
+        self
+    }
+
+    /// Draws travel moves as grey dashed lines.
+    #[must_use]
+    pub const fn with_travel(mut self, show_travel: bool) -> Self {
+        self.pen.set_show_travel(show_travel);
+        self
+    }
+
+    /// Sets how finely arcs are split into segments.
+    #[must_use]
+    pub const fn with_tessellation(mut self, tessellation: Tessellation) -> Self {
+        self.pen.set_tessellation(tessellation);
+        self
+    }
+
+    /// Draws a single command.
+    ///
+    /// # Errors
+    ///   When a temporary buffer or the output for a completed layer
+    /// cannot be written to.
+    pub fn write_command(&mut self, command: Command) -> io::Result<()> {
+        self.pen.draw(command, &mut self.pages);
+        self.pages.take_error()
+    }
+
+    /// Draws a series of commands.
+    ///
+    /// # Errors
+    ///   When a temporary buffer or the output for a completed layer
+    /// cannot be written to.
+    pub fn write_commands<I>(&mut self, commands: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = Command>,
+    {
+        for command in commands {
+            self.write_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the final layer, returning the number of SVGs written.
+    ///
+    /// # Errors
+    ///   When the output for the final layer cannot be written to.
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.pages.write_current()?;
+        Ok(self.pages.written)
+    }
+}
+
+// The layer being drawn, and the outputs for those already complete.
+struct Pages<F> {
+    create: F,
+    current: Option<(Layer, PathBuffer)>,
+    written: usize,
+    // The first write error, `Canvas` cannot report errors directly.
+    error: Option<io::Error>,
+}
+
+impl<F> core::fmt::Debug for Pages<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pages")
+            .field("current", &self.current)
+            .field("written", &self.written)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W, F> Pages<F>
+where
+    W: Write,
+    F: FnMut(Layer) -> io::Result<W>,
+{
+    fn take_error(&mut self) -> io::Result<()> {
+        if let Some((_, path)) = &mut self.current {
+            path.take_error()?;
+        }
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    fn write_current(&mut self) -> io::Result<()> {
+        if let Some((layer, path)) = self.current.take()
+            && path.min_x.is_finite()
+        {
+            let mut out = (self.create)(layer)?;
+            path.write_document(&mut out)?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<W, F> Canvas for Pages<F>
+where
+    W: Write,
+    F: FnMut(Layer) -> io::Result<W>,
+{
+    fn update_view_box(&mut self, proj_x: f64, proj_y: f64) {
+        if let Some((_, path)) = &mut self.current {
+            path.update_view_box(proj_x, proj_y);
+        }
+    }
+
+    fn push_part(&mut self, part: core::fmt::Arguments<'_>) {
+        if let Some((_, path)) = &mut self.current {
+            path.push_part(part);
+        }
+    }
+
+    fn start_layer(&mut self, layer: Layer) -> bool {
+        if let Err(e) = self.write_current()
+            && self.error.is_none()
+        {
+            self.error = Some(e);
+        }
+        let mut path = PathBuffer::default();
+        path.push_part(format_args!("M0 0"));
+        self.current = Some((layer, path));
+        true
+    }
+
+    fn set_group(&mut self, label: &str) -> bool {
+        self.current
+            .as_mut()
+            .is_some_and(|(_, path)| path.set_group(label))
+    }
+}
+
+/// Path data and the bounds of the projected points.
+///
+/// Holds a separate path for each group, the first path holds
+/// everything drawn outside of any group.
+#[derive(Debug)]
+struct PathBuffer {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+    paths: Vec<(Option<String>, BufWriter<Scratch>)>,
+    // Index into paths.
+    current: usize,
+    // The first write error, `Canvas` cannot report errors directly.
+    error: Option<io::Error>,
+}
+
+impl Default for PathBuffer {
+    fn default() -> Self {
+        Self {
+            min_x: f64::INFINITY,
+            max_x: -f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_y: -f64::INFINITY,
+            paths: vec![(None, scratch())],
+            current: 0,
+            error: None,
+        }
+    }
+}
+
+#[cfg(feature = "spool")]
+fn scratch() -> BufWriter<Scratch> {
+    BufWriter::new(SpooledTempFile::new(SPOOL_LIMIT))
+}
+
+#[cfg(not(feature = "spool"))]
+fn scratch() -> BufWriter<Scratch> {
+    BufWriter::new(io::Cursor::new(Vec::new()))
+}
+
+// Rewinds a temporary buffer, ready to be copied to the output.
+fn rewind(scratch: BufWriter<Scratch>) -> io::Result<Scratch> {
+    let mut scratch = scratch
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    scratch.seek(SeekFrom::Start(0))?;
+    Ok(scratch)
+}
+
+impl PathBuffer {
+    fn take_error(&mut self) -> io::Result<()> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    // Writes the `<svg>` element, followed by the buffered path.
+    fn write_document<W: Write>(self, out: &mut W) -> io::Result<()> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        if self.paths.len() > 1 {
+            return self.write_groups(out);
+        }
+        let Some((_, scratch)) = self.paths.into_iter().next() else {
+            unreachable!("The ungrouped path is always present");
+        };
+        let mut scratch = rewind(scratch)?;
+
+        let parameters = svg_parameters(self.min_x, self.min_y, self.max_x, self.max_y);
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" {parameters} >"
+        )?;
+        write!(out, "{PATH_HEADER}")?;
+        io::copy(&mut scratch, out)?;
+        write!(out, "{PATH_FOOTER}")?;
+        out.flush()
+    }
+
+    // Writes a `<g>` element for each group, followed by a legend to the
+    // right of the drawing.
+    //
+    // Parts drawn outside of any group are only moves, and are dropped.
+    fn write_groups<W: Write>(self, out: &mut W) -> io::Result<()> {
+        let groups = self
+            .paths
+            .into_iter()
+            .filter_map(|(label, scratch)| label.map(|label| (label, scratch)))
+            .collect::<Vec<_>>();
+
+        let width = self.max_x - self.min_x;
+        let height = self.max_y - self.min_y;
+        // The height of a row of the legend.
+        let row = width.max(height) / 30_f64;
+        let row = if row > 0_f64 { row } else { 1_f64 };
+        let longest = groups
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or_default();
+        let longest = f64::from(u32::try_from(longest).unwrap_or(u32::MAX));
+        let n_rows = f64::from(u32::try_from(groups.len()).unwrap_or(u32::MAX));
+        // Text width is estimated, as 0.6 of the font size per character.
+        let legend_x = self.max_x + row;
+        let legend_width = row * 0.6_f64.mul_add(longest, 2_f64);
+        let max_x = legend_x + legend_width;
+        let max_y = self.max_y.max(row.mul_add(n_rows + 1_f64, self.min_y));
+
+        let parameters = svg_parameters(self.min_x, self.min_y, max_x, max_y);
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" {parameters} >"
+        )?;
+
+        let mut legend = Vec::with_capacity(groups.len());
+        let mut colour_index = 0;
+        for (label, scratch) in groups {
+            let id = group::id(&label);
+            let style = group::style(&label, colour_index);
+            legend.push((group::colour(&label, colour_index), group::escape(&label)));
+            if label != "travel" {
+                colour_index += 1;
+            }
+
+            writeln!(out, "  <g id=\"{id}\" style=\"{style}\">")?;
+            write!(out, "    <path d=\"")?;
+            io::copy(&mut rewind(scratch)?, out)?;
+            writeln!(out, "\" />")?;
+            writeln!(out, "  </g>")?;
+        }
+
+        // A coloured square followed by the label, one per row.
+        let size = row * 0.8;
+        writeln!(
+            out,
+            "  <g id=\"legend\" style=\"font-family:sans-serif;font-size:{size}px\">"
+        )?;
+        let mut y = self.min_y;
+        for (colour, label) in legend {
+            let text_x = row.mul_add(1.2, legend_x);
+            let text_y = y + size;
+            writeln!(
+                out,
+                "    <rect x=\"{legend_x}\" y=\"{y}\" width=\"{size}\" height=\"{size}\" style=\"fill:{colour}\" />"
+            )?;
+            writeln!(
+                out,
+                "    <text x=\"{text_x}\" y=\"{text_y}\">{label}</text>"
+            )?;
+            y += row;
+        }
+        writeln!(out, "  </g>")?;
+        write!(out, " </svg>")?;
+        out.flush()
+    }
+}
+
+impl Canvas for PathBuffer {
+    fn update_view_box(&mut self, proj_x: f64, proj_y: f64) {
+        self.min_x = self.min_x.min(proj_x);
+        self.max_x = self.max_x.max(proj_x);
+        self.min_y = self.min_y.min(proj_y);
+        self.max_y = self.max_y.max(proj_y);
+    }
+
+    fn push_part(&mut self, part: core::fmt::Arguments<'_>) {
+        if self.error.is_none()
+            && let Err(e) = self.paths[self.current].1.write_fmt(part)
+        {
+            self.error = Some(e);
+        }
+    }
+
+    fn set_group(&mut self, label: &str) -> bool {
+        let index = self
+            .paths
+            .iter()
+            .position(|(l, _)| l.as_deref() == Some(label))
+            .unwrap_or_else(|| {
+                self.paths.push((Some(label.to_string()), scratch()));
+                self.paths.len() - 1
+            });
+        let is_changed = index != self.current;
+        self.current = index;
+        is_changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binary::gcode_block::svg::Svg;
+    use crate::binary::gcode_block::svg::projection::View;
+
+    fn commands(buffer: &str) -> impl Iterator<Item = Command> + '_ {
+        buffer
+            .lines()
+            .map(|line| Command::parse_line(line).unwrap().1)
+    }
+
+    #[test]
+    fn same_as_svg() {
+        let buffer = include_str!("../../../../../assets/both.gcode");
+
+        let mut writer = SvgWriter::new(vec![]);
+        writer.write_commands(commands(buffer)).unwrap();
+        let streamed = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        let svg = commands(buffer).collect::<Svg>();
+        assert_eq!(streamed, svg.to_string());
+    }
+
+    #[test]
+    fn top_view() {
+        // A square, viewed from above.
+        let buffer = "G1 X0 Y0\nG1 X10 Y0 E1\nG1 X10 Y10 E2\nG1 X0 Y10 E3\nG1 X0 Y0 E4";
+
+        let mut writer = SvgWriter::with_projection(vec![], Projection::new(View::Top));
+        writer.write_commands(commands(buffer)).unwrap();
+        let streamed = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert!(streamed.contains("viewBox=\"0 -10 10 10\""));
+        assert!(
+            streamed
+                .contains("M0.000 -0.000L10.000 -0.000L10.000 -10.000L0.000 -10.000L0.000 -0.000")
+        );
+    }
+
+    #[test]
+    fn tessellation() {
+        // A half circle, of radius 10.
+        let buffer = "G1 X0 Y0\nG3 X20 Y0 I10 J0 E1";
+        let draw = |tessellation| {
+            let mut writer = SvgWriter::with_projection(vec![], Projection::new(View::Top))
+                .with_tessellation(tessellation);
+            writer.write_commands(commands(buffer)).unwrap();
+            let streamed = String::from_utf8(writer.finish().unwrap()).unwrap();
+            streamed.matches('L').count()
+        };
+
+        let coarse = Tessellation {
+            chord_tolerance: 10_f64,
+            max_angle: None,
+        };
+        // A single segment to the end.
+        assert_eq!(draw(coarse), 1);
+        let stepped = Tessellation {
+            max_angle: Some(core::f64::consts::PI / 6_f64),
+            ..coarse
+        };
+        assert_eq!(draw(stepped), 6);
+        assert!(draw(Tessellation::default()) > 6);
+    }
+
+    #[test]
+    fn arcs() {
+        let quarters = Tessellation {
+            chord_tolerance: 10_f64,
+            max_angle: Some(core::f64::consts::FRAC_PI_2),
+        };
+        let draw = |buffer, view, show_travel| {
+            let mut writer = SvgWriter::with_projection(vec![], Projection::new(view))
+                .with_tessellation(quarters)
+                .with_travel(show_travel);
+            writer.write_commands(commands(buffer)).unwrap();
+            String::from_utf8(writer.finish().unwrap()).unwrap()
+        };
+
+        // A half turn of a helix, rising 2mm, seen from the front.
+        let helix = draw("G1 X10 Y0 E1\nG3 X-10 Y0 Z2 I-10 J0 E1", View::Front, false);
+        assert!(helix.contains("L10.000 -0.000L0.000 -1.000L-10.000 -2.000"));
+
+        // A travel arc is not drawn, the pen moves to its end.
+        let travel = "G1 X10 Y0 E1\nG3 X-10 Y0 I-10 J0\nG1 X-10 Y-10 E2";
+        let hidden = draw(travel, View::Top, false);
+        assert!(hidden.contains("L10.000 -0.000M-10.000 -0.000L-10.000 10.000"));
+        let shown = draw(travel, View::Top, true);
+        assert!(shown.contains("L0.000 -10.000L-10.000 -0.000"));
+
+        // In relative mode, the end and the center are offsets.
+        let relative = draw(
+            "G1 X10 Y0 E1\nG91\nG3 X-20 Y0 I-10 J0 E1\nG1 Y-10 E1",
+            View::Top,
+            false,
+        );
+        assert!(relative.contains("L10.000 -0.000L0.000 -10.000L-10.000 -0.000L-10.000 10.000"));
+
+        // A circle ends where it starts, P adds full turns.
+        let circle = draw("G1 X10 Y0 E1\nG2 X10 Y0 I-10 J0 E1", View::Top, false);
+        assert!(
+            circle
+                .contains("L10.000 -0.000L0.000 10.000L-10.000 0.000L-0.000 -10.000L10.000 -0.000")
+        );
+        let turns = draw("G1 X10 Y0 E1\nG2 X10 Y0 I-10 J0 E1 P2", View::Top, false);
+        assert_eq!(turns.matches('L').count(), 1 + 3 * 4);
+
+        // G18, a half circle in the ZX plane, seen from the front.
+        let upright = draw(
+            "G1 X10 Y0 E1\nG18\nG2 X-10 Z0 I-10 K0 E1",
+            View::Front,
+            false,
+        );
+        assert!(upright.contains("L10.000 -0.000L0.000 -10.000L-10.000 -0.000"));
+    }
+
+    #[test]
+    fn single_layer() {
+        // Two layers, the first line is hidden.
+        let buffer = "G1 Z0.2\nG1 X10 E1\nG1 Z0.4\nG1 X0 Y10 E2";
+
+        let mut writer = SvgWriter::with_projection(vec![], Projection::new(View::Top))
+            .with_filter(LayerFilter::Index(1));
+        writer.write_commands(commands(buffer)).unwrap();
+        let streamed = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        // The line starts with a move to the end of the hidden line.
+        assert!(streamed.contains("d=\"M0 0M10.000 -0.000L0.000 -10.000\""));
+        assert!(streamed.contains("viewBox=\"0 -10 10 10\""));
+    }
+
+    #[test]
+    fn groups() {
+        let buffer = "T0\nG1 X10 E1\nG1 E-1\nG1 X10 Y10\nT1\nG1 X0 Y10 E1";
+
+        let mut writer = SvgWriter::with_projection(vec![], Projection::new(View::Top))
+            .with_group_by(GroupBy::Tool)
+            .with_travel(true);
+        writer.write_commands(commands(buffer)).unwrap();
+        let streamed = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        // Each group's path starts where the previous group finished.
+        assert!(streamed.contains(
+            "<g id=\"t0\" style=\"fill:none;stroke:#1f77b4;stroke-width:0.05\">\n    <path d=\"M0.000 -0.000L10.000 -0.000M10.000 -0.000\" />"
+        ));
+        assert!(streamed.contains("<g id=\"travel\""));
+        assert!(streamed.contains("<path d=\"M10.000 -0.000L10.000 -10.000\" />"));
+        assert!(streamed.contains("<path d=\"M10.000 -10.000L0.000 -10.000\" />"));
+        assert!(streamed.contains(">T1</text>"));
+    }
+
+    #[test]
+    fn per_layer() {
+        let buffer = include_str!("../../../../../assets/both.gcode");
+
+        let mut svgs = vec![];
+        let mut writer = LayerSvgWriter::new(Projection::default(), |layer| {
+            svgs.push(layer);
+            Ok(vec![])
+        });
+        writer.write_commands(commands(buffer)).unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+        assert_eq!(
+            svgs,
+            vec![Layer { index: 0, z: 0.2 }, Layer { index: 1, z: 0.35 }]
+        );
+    }
+
+    #[test]
+    fn empty() {
+        // No viewBox, as per `Svg`.
+        let writer = SvgWriter::new(vec![]);
+        let streamed = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        let svg = core::iter::empty::<Command>().collect::<Svg>();
+        assert_eq!(streamed, svg.to_string());
+    }
+}
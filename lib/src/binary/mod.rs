@@ -213,6 +213,14 @@ impl Markdown for Bgcode<'_> {
     }
 }
 
+// Splits the text of a metadata block into "key=value" pairs.
+fn key_values(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
 /// Parses a binary gcode
 ///
 /// Fast version checksum is logged but not validated.
@@ -13,7 +13,9 @@ use super::block_header::BlockHeader;
 use super::block_header::block_header_parser;
 use super::default_params::Param;
 use super::default_params::param_parser;
+use super::inflate::DecompressError;
 use super::inflate::decompress_data_block;
+use super::key_values;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PrintMetadataBlock<'a> {
@@ -51,6 +53,20 @@ impl Display for PrintMetadataBlock<'_> {
 }
 
 impl PrintMetadataBlock<'_> {
+    /// Returns the "key=value" pairs of the block, in file order.
+    ///
+    /// eg. ("estimated printing time (normal mode)", "3h 16m 58s")
+    ///
+    /// # Errors
+    ///   When the data block cannot be decompressed.
+    pub fn entries(&self) -> Result<Vec<(String, String)>, DecompressError> {
+        match decompress_data_block(self.data, &self.param.encoding, &self.header) {
+            Ok((_remain, data)) => Ok(key_values(&String::from_utf8_lossy(&data))),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+            Err(nom::Err::Incomplete(_)) => Err(DecompressError::None),
+        }
+    }
+
     /// Write to formatter a markdown block.
     pub fn markdown<W>(&self, mut f: W) -> core::fmt::Result
     where
@@ -147,3 +163,23 @@ pub fn print_metadata_parser_with_checksum(
 
     Ok((remain, pm))
 }
+
+#[cfg(test)]
+mod test {
+    use crate::binary::bgcode_parser;
+
+    #[test]
+    fn entries() {
+        let buffer = include_bytes!("../../../../assets/mini_cube_b.bgcode");
+        let (_remain, bgcode) = bgcode_parser(buffer).unwrap();
+        let entries = bgcode.print_metadata.entries().unwrap();
+        assert_eq!(
+            entries[0],
+            ("filament used [mm]".to_string(), "986.61".to_string())
+        );
+        assert!(entries.contains(&(
+            "estimated printing time (normal mode)".to_string(),
+            "32m 6s".to_string()
+        )));
+    }
+}
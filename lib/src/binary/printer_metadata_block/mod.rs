@@ -5,7 +5,9 @@ use super::block_header::BlockHeader;
 use super::block_header::block_header_parser;
 use super::default_params::Param;
 use super::default_params::param_parser;
+use super::inflate::DecompressError;
 use super::inflate::decompress_data_block;
+use super::key_values;
 
 use nom::IResult;
 use nom::Parser;
@@ -47,6 +49,20 @@ impl Display for PrinterMetadataBlock<'_> {
 }
 
 impl PrinterMetadataBlock<'_> {
+    /// Returns the printer's metadata as "key=value" pairs, in file order.
+    ///
+    /// eg. `("printer_model", "MINI")`
+    ///
+    /// # Errors
+    ///   When the data block cannot be decompressed.
+    pub fn entries(&self) -> Result<Vec<(String, String)>, DecompressError> {
+        match decompress_data_block(self.data, &self.param.encoding, &self.header) {
+            Ok((_remain, data)) => Ok(key_values(&String::from_utf8_lossy(&data))),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+            Err(nom::Err::Incomplete(_)) => Err(DecompressError::None),
+        }
+    }
+
     /// Write to formatter a markdown block.
     pub fn markdown<W>(&self, mut f: W) -> core::fmt::Result
     where
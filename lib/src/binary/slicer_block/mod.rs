@@ -4,7 +4,9 @@ use core::fmt::Write;
 use super::BlockError;
 use super::block_header::BlockHeader;
 use super::block_header::block_header_parser;
+use super::inflate::DecompressError;
 use super::inflate::decompress_data_block;
+use super::key_values;
 
 use nom::IResult;
 use nom::Parser;
@@ -50,6 +52,20 @@ impl Display for SlicerBlock<'_> {
 }
 
 impl SlicerBlock<'_> {
+    /// Returns the slicer's settings as "key=value" pairs, in file order.
+    ///
+    /// eg. `("filament_diameter", "1.75")`
+    ///
+    /// # Errors
+    ///   When the data block cannot be decompressed.
+    pub fn entries(&self) -> Result<Vec<(String, String)>, DecompressError> {
+        match decompress_data_block(self.data, &self.param.encoding, &self.header) {
+            Ok((_remain, data)) => Ok(key_values(&String::from_utf8_lossy(&data))),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+            Err(nom::Err::Incomplete(_)) => Err(DecompressError::None),
+        }
+    }
+
     /// Write to formatter a markdown block.
     pub fn markdown<W>(&self, f: &mut W) -> core::fmt::Result
     where
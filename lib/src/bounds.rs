@@ -0,0 +1,497 @@
+//! Checks that a print fits within the printable volume of the machine.
+//!
+//! The printable volume is the bed shape, a polygon, extruded up to the
+//! maximum print height. Both are found in the slicer's settings, in the
+//! comments at the end of a .gcode file or in the metadata of a .bgcode
+//! file.
+//!
+//! ```text
+//! ; bed_shape = 0x0,250x0,250x210,0x210
+//! ; max_print_height = 210
+//! ```
+//!
+//! When only the printer model is known, the volume of a few common
+//! printers is used instead.
+
+use crate::Plane;
+use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::command::Command;
+use crate::objects::BoundingBox;
+use crate::objects::arc_extremes;
+use crate::params::head::PosVal;
+
+/// The printable volume of a machine.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrintVolume {
+    /// The corners of the bed, in mm. Empty when not known.
+    pub bed: Vec<[f64; 2]>,
+    /// The maximum print height, in mm.
+    pub height: Option<f64>,
+}
+
+impl PrintVolume {
+    /// Reads a slicer setting, unknown keys are ignored.
+    ///
+    /// "`printer_model`" only fills in values which are not yet known, so
+    /// the slicer's own settings take precedence.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "bed_shape" => {
+                if let Some(bed) = parse_bed_shape(value) {
+                    self.bed = bed;
+                }
+            }
+            "max_print_height" => {
+                if let Ok(height) = value.parse() {
+                    self.height = Some(height);
+                }
+            }
+            "printer_model" => {
+                if let Some(model) = Self::model(value) {
+                    if self.bed.is_empty() {
+                        self.bed = model.bed;
+                    }
+                    self.height = self.height.or(model.height);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The printable volume of a known printer model.
+    #[must_use]
+    pub fn model(name: &str) -> Option<Self> {
+        let (width, depth, height) = match name {
+            "MINI" | "MINIIS" => (180_f64, 180_f64, 180_f64),
+            "MK2.5" | "MK2.5S" | "MK3" | "MK3S" => (250_f64, 210_f64, 210_f64),
+            "MK3.5" | "MK3.9" | "MK4" | "MK4S" | "MK4IS" => (250_f64, 210_f64, 220_f64),
+            "COREONE" => (250_f64, 220_f64, 270_f64),
+            "XL" | "XLIS" => (360_f64, 360_f64, 360_f64),
+            _ => return None,
+        };
+        Some(Self {
+            bed: vec![
+                [0_f64, 0_f64],
+                [width, 0_f64],
+                [width, depth],
+                [0_f64, depth],
+            ],
+            height: Some(height),
+        })
+    }
+
+    /// Returns true if a point lies within the volume, or within `margin` mm
+    /// of it.
+    ///
+    /// Axes which are not known are not checked.
+    #[must_use]
+    pub fn contains(&self, p: [f64; 3], margin: f64) -> bool {
+        if p[2] < -margin || self.height.is_some_and(|height| p[2] > height + margin) {
+            return false;
+        }
+        self.bed.is_empty() || self.on_bed([p[0], p[1]], margin)
+    }
+
+    fn on_bed(&self, p: [f64; 2], margin: f64) -> bool {
+        let edges = || {
+            self.bed
+                .iter()
+                .zip(self.bed.iter().cycle().skip(1))
+                .map(|(a, b)| (*a, *b))
+        };
+        // Counts the edges crossed by a ray heading in +X.
+        let crossings = edges()
+            .filter(|(a, b)| {
+                (a[1] > p[1]) != (b[1] > p[1])
+                    && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+            })
+            .count();
+        crossings % 2 == 1 || edges().any(|(a, b)| distance(p, a, b) <= margin)
+    }
+}
+
+/// Parses the slicer's "0x0,250x0,250x210,0x210" list of corners.
+#[must_use]
+pub fn parse_bed_shape(value: &str) -> Option<Vec<[f64; 2]>> {
+    value
+        .split(',')
+        .map(|corner| {
+            let (x, y) = corner.trim().split_once('x')?;
+            Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|bed| bed.len() >= 3)
+}
+
+// The distance from a point to the line segment a-b.
+fn distance(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let ap = [p[0] - a[0], p[1] - a[1]];
+    let length2 = ab[0].mul_add(ab[0], ab[1] * ab[1]);
+    let t = if length2 > 0_f64 {
+        (ap[0].mul_add(ab[0], ap[1] * ab[1]) / length2).clamp(0_f64, 1_f64)
+    } else {
+        0_f64
+    };
+    (t.mul_add(-ab[0], ap[0])).hypot(t.mul_add(-ab[1], ap[1]))
+}
+
+/// A move which leaves the printable volume.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Violation {
+    /// The line number of the move, counting from 1.
+    pub line: usize,
+    /// The first point of the move found outside of the volume.
+    pub position: [f64; 3],
+    /// True for an extrusion, false for a travel move.
+    pub extruding: bool,
+}
+
+/// The extent of a print, and the moves which leave the printable volume.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bounds {
+    /// Encloses the extrusions.
+    pub extrusion: Option<BoundingBox>,
+    /// Encloses the end points of the travel moves.
+    pub travel: Option<BoundingBox>,
+    /// Moves outside of the printable volume, in file order.
+    pub violations: Vec<Violation>,
+}
+
+/// Follows the head as commands are replayed, checking each move.
+///
+/// ```rust
+/// use gcode_nom::bounds::BoundsChecker;
+/// use gcode_nom::bounds::PrintVolume;
+/// use gcode_nom::command::Command;
+///
+/// let mut checker = BoundsChecker::new(PrintVolume::model("MINI").unwrap(), 0.0);
+/// for (i, line) in ["G1 X10 Y10 Z0.2", "G1 X190 E5"].iter().enumerate() {
+///     checker.push(i + 1, &Command::parse_line(line).unwrap().1);
+/// }
+/// let bounds = checker.finish();
+/// assert_eq!(bounds.violations[0].line, 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BoundsChecker {
+    volume: PrintVolume,
+    margin: f64,
+    // The position of the head, in the coordinates of the file.
+    position: [f64; 3],
+    // Set by G92, added to position to give machine coordinates.
+    origin: [f64; 3],
+    position_mode: PositionMode,
+    // The position of the E axis, as given by the file.
+    e: f64,
+    // Set by M83, or G91.
+    relative_e: bool,
+    // The plane of G2/G3 arcs.
+    plane: Plane,
+    bounds: Bounds,
+}
+
+impl BoundsChecker {
+    /// Checks moves against a volume, allowing `margin` mm beyond its edges.
+    #[must_use]
+    pub fn new(volume: PrintVolume, margin: f64) -> Self {
+        Self {
+            volume,
+            margin,
+            ..Self::default()
+        }
+    }
+
+    /// Replays a single command, found on the given line.
+    pub fn push(&mut self, line: usize, command: &Command) {
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                let start = self.machine_position();
+                let mut moved = false;
+                let mut extruding = false;
+                for param in params {
+                    match param {
+                        PosVal::X(val) => moved |= self.set_axis(0, *val),
+                        PosVal::Y(val) => moved |= self.set_axis(1, *val),
+                        PosVal::Z(val) => moved |= self.set_axis(2, *val),
+                        PosVal::E(val) => extruding = self.extrude(*val),
+                        _ => {}
+                    }
+                }
+                if moved {
+                    let end = self.machine_position();
+                    self.visit(line, start, &[end], extruding);
+                }
+            }
+            Command::G2(form) => self.arc(line, form, true),
+            Command::G3(form) => self.arc(line, form, false),
+            Command::G17 => self.plane = Plane::XY,
+            Command::G18 => self.plane = Plane::ZX,
+            Command::G19 => self.plane = Plane::YZ,
+            Command::G90 => {
+                self.position_mode = PositionMode::Absolute;
+                self.relative_e = false;
+            }
+            Command::G91 => {
+                self.position_mode = PositionMode::Relative;
+                self.relative_e = true;
+            }
+            Command::MDrop(82) => self.relative_e = false,
+            Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                for param in params {
+                    let (axis, val) = match param {
+                        PosVal::X(val) => (0, val),
+                        PosVal::Y(val) => (1, val),
+                        PosVal::Z(val) => (2, val),
+                        PosVal::E(val) => {
+                            self.e = *val;
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    self.origin[axis] += self.position[axis] - val;
+                    self.position[axis] = *val;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the extent of the print, and the moves out of bounds.
+    #[must_use]
+    pub fn finish(self) -> Bounds {
+        self.bounds
+    }
+
+    // Returns true if the axis moves.
+    fn set_axis(&mut self, axis: usize, val: f64) -> bool {
+        let previous = self.position[axis];
+        self.position[axis] = match self.position_mode {
+            PositionMode::Absolute => val,
+            PositionMode::Relative => self.position[axis] + val,
+        };
+        (self.position[axis] - previous).abs() > f64::EPSILON
+    }
+
+    // Returns true if filament is pushed out.
+    fn extrude(&mut self, e: f64) -> bool {
+        let delta = if self.relative_e { e } else { e - self.e };
+        self.e = if self.relative_e { self.e + e } else { e };
+        delta > 0_f64
+    }
+
+    fn machine_position(&self) -> [f64; 3] {
+        [
+            self.origin[0] + self.position[0],
+            self.origin[1] + self.position[1],
+            self.origin[2] + self.position[2],
+        ]
+    }
+
+    fn arc(&mut self, line: usize, form: &ArcForm, clockwise: bool) {
+        let start = self.machine_position();
+        let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+        let mut extruding = false;
+        for param in params {
+            match param {
+                ArcVal::X(val) => _ = self.set_axis(0, *val),
+                ArcVal::Y(val) => _ = self.set_axis(1, *val),
+                ArcVal::Z(val) => _ = self.set_axis(2, *val),
+                ArcVal::E(val) => extruding = self.extrude(*val),
+                _ => {}
+            }
+        }
+        let end = self.machine_position();
+        // The R form only visits its end points.
+        let mut points = arc_extremes(start, end, form, self.plane, clockwise);
+        points.push(end);
+        self.visit(line, start, &points, extruding);
+    }
+
+    // Records the points of a move, and checks them against the volume.
+    fn visit(&mut self, line: usize, start: [f64; 3], points: &[[f64; 3]], extruding: bool) {
+        let bbox = if extruding {
+            &mut self.bounds.extrusion
+        } else {
+            &mut self.bounds.travel
+        };
+        if extruding {
+            bbox.get_or_insert(BoundingBox::point(start)).extend(start);
+        }
+        for p in points {
+            bbox.get_or_insert(BoundingBox::point(*p)).extend(*p);
+        }
+
+        if let Some(position) = points
+            .iter()
+            .find(|p| !self.volume.contains(**p, self.margin))
+        {
+            self.bounds.violations.push(Violation {
+                line,
+                position: *position,
+                extruding,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(volume: PrintVolume, lines: &[&str]) -> Bounds {
+        let mut checker = BoundsChecker::new(volume, 0_f64);
+        for (i, line) in lines.iter().enumerate() {
+            checker.push(i + 1, &Command::parse_line(line).unwrap().1);
+        }
+        checker.finish()
+    }
+
+    #[test]
+    fn volume() {
+        let mut volume = PrintVolume::default();
+        volume.set("printer_model", "MK3S");
+        volume.set("bed_shape", "0x0,100x0,100x100,0x100");
+        // The model does not replace the slicer's settings.
+        volume.set("printer_model", "XL");
+        assert_eq!(
+            volume.bed,
+            parse_bed_shape("0x0,100x0,100x100,0x100").unwrap()
+        );
+        assert_eq!(volume.height, Some(210_f64));
+
+        assert!(volume.contains([100_f64, 50_f64, 210_f64], 0_f64));
+        assert!(!volume.contains([100.5, 50_f64, 10_f64], 0_f64));
+        assert!(volume.contains([100.5, 50_f64, 10_f64], 1_f64));
+        assert!(!volume.contains([50_f64, 50_f64, 211_f64], 0_f64));
+        assert!(!volume.contains([50_f64, 50_f64, -0.5], 0_f64));
+
+        // A triangular bed.
+        let triangle = PrintVolume {
+            bed: parse_bed_shape("0x0, 100x0, 0x100").unwrap(),
+            height: None,
+        };
+        assert!(triangle.contains([40_f64, 40_f64, 500_f64], 0_f64));
+        assert!(!triangle.contains([60_f64, 60_f64, 0_f64], 0_f64));
+
+        assert_eq!(parse_bed_shape("0x0,100x0"), None);
+        assert_eq!(parse_bed_shape("0x0,100,100x100"), None);
+    }
+
+    #[test]
+    fn planes() {
+        // A half circle in the ZX plane, dipping below the bed.
+        let below = check(
+            PrintVolume::model("MINI").unwrap(),
+            &["M83", "G1 X10 Y10 Z2", "G18", "G2 X20 Z2 I5 K0 E1"],
+        );
+        assert_eq!(
+            below.violations,
+            vec![Violation {
+                line: 4,
+                position: [15_f64, 10_f64, -3_f64],
+                extruding: true
+            }]
+        );
+
+        // A half circle in the YZ plane, rising above the maximum height.
+        let above = check(
+            PrintVolume::model("MINI").unwrap(),
+            &["M83", "G1 X10 Y10 Z178", "G19", "G2 Y20 Z178 J5 K0 E1"],
+        );
+        assert_eq!(
+            above.violations,
+            vec![Violation {
+                line: 4,
+                position: [10_f64, 15_f64, 183_f64],
+                extruding: true
+            }]
+        );
+    }
+
+    #[test]
+    fn moves() {
+        let bounds = check(
+            PrintVolume::model("MINI").unwrap(),
+            &[
+                "M83",
+                "G1 X10 Y2 Z0.2",
+                "G1 X20 E1",
+                // The arc bulges past Y = 0, its end points do not.
+                "G3 X30 Y2 I5 J0 E1",
+                // Travel beyond the bed, in relative mode.
+                "G91",
+                "G1 X160",
+                "G90",
+                "G1 X20",
+                // Machine X is 200.
+                "G92 X0",
+                "M83",
+                "G1 X180 E2",
+            ],
+        );
+        assert_eq!(
+            bounds.violations,
+            vec![
+                Violation {
+                    line: 4,
+                    position: [25_f64, -3_f64, 0.2],
+                    extruding: true
+                },
+                Violation {
+                    line: 6,
+                    position: [190_f64, 2_f64, 0.2],
+                    extruding: false
+                },
+                Violation {
+                    line: 11,
+                    position: [200_f64, 2_f64, 0.2],
+                    extruding: true
+                },
+            ]
+        );
+        let extrusion = bounds.extrusion.unwrap();
+        let expected = [[10_f64, -3_f64, 0.2], [200_f64, 2_f64, 0.2]];
+        for (actual, expected) in [extrusion.min, extrusion.max].iter().zip(expected) {
+            for axis in 0..3 {
+                assert!((actual[axis] - expected[axis]).abs() < 1e-9, "{actual:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn slicer_file() {
+        let input = include_str!("../../assets/II 2x4_0.15mm_PLA_MK3S_1h17m.gcode");
+        let commands = input
+            .lines()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<Vec<_>>();
+        let mut volume = PrintVolume::default();
+        for command in &commands {
+            if let Command::Comment(text) = command
+                && let Some((key, value)) = text.split_once('=')
+            {
+                volume.set(key.trim(), value.trim());
+            }
+        }
+        assert_eq!(volume, PrintVolume::model("MK3S").unwrap());
+
+        let mut checker = BoundsChecker::new(volume, 0_f64);
+        for (i, command) in commands.iter().enumerate() {
+            checker.push(i + 1, command);
+        }
+        let bounds = checker.finish();
+        // Only the purge line, which starts outside of the print area.
+        assert!(!bounds.violations.is_empty());
+        assert!(
+            bounds
+                .violations
+                .iter()
+                .all(|violation| violation.position[1] < 0_f64),
+            "{:?}",
+            bounds.violations
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! Removes objects from a multipart print.
+//!
+//! The extrusions of a cancelled object become travel moves, the head
+//! still follows the path of the object but no filament is laid down.
+//! Retractions, and other moves which only drive the extruder, are kept so
+//! the filament is in the expected state when the next object starts.
+//!
+//! With absolute extrusion (M82) the E axis of the file runs ahead of the
+//! extruder once moves are skipped, so a `G92 E` is inserted before the
+//! next move which uses the E axis.
+//!
+//! The feedrate of a converted move is dropped, so the head travels at
+//! the speed it arrived at rather than the speed of the extrusion. A `G1 F`
+//! restores the feedrate of the file before the next move which is kept.
+//!
+//! Lines are rewritten as text, comments and formatting are preserved.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::binary::gcode_block::rewrite::rewrite_blocks;
+use crate::command::Command;
+use crate::objects::ObjectTable;
+use crate::params::head::PosVal;
+
+/// Turns the extrusions of selected objects into travel moves.
+///
+/// Objects are selected by M486 id, or by name. Klipper's objects are
+/// numbered in order of appearance, see [`crate::objects::KlipperObjects`].
+///
+/// ```rust
+/// use gcode_nom::cancel::Canceller;
+///
+/// let mut canceller = Canceller::default();
+/// canceller.ids.insert(1);
+///
+/// let mut out = vec![];
+/// for line in ["M486 S1", "G1 X10 Y10 E1.5 ; perimeter", "M486 S0", "G1 X20 E2"] {
+///     canceller.write_line(line, &mut out).unwrap();
+/// }
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "M486 S1\nG1 X10 Y10 ; perimeter\nM486 S0\nG92 E1.5\nG1 X20 E2\n"
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Canceller {
+    /// The M486 ids of the objects to cancel.
+    pub ids: BTreeSet<i128>,
+    /// The names of the objects to cancel, as given by M486 A or Klipper.
+    pub names: BTreeSet<String>,
+    table: ObjectTable,
+    // Set by M83, or G91.
+    relative_e: bool,
+    // The position of the E axis, as given by the file.
+    e: f64,
+    // Extrusion was skipped, the extruder no longer agrees with the file.
+    skipped: bool,
+    // The feedrate, as given by the file.
+    feedrate: Option<f64>,
+    // A feedrate was dropped, the printer no longer agrees with the file.
+    slowed: bool,
+}
+
+impl Canceller {
+    /// Returns true if the object being printed is cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.table.current().is_some_and(|id| {
+            self.ids.contains(&id)
+                || self
+                    .table
+                    .get(id)
+                    .and_then(|object| object.name.as_ref())
+                    .is_some_and(|name| self.names.contains(name))
+        })
+    }
+
+    /// Writes a single line of G-code, rewritten if it belongs to a
+    /// cancelled object.
+    ///
+    /// # Errors
+    ///   When a write fails.
+    pub fn write_line<W>(&mut self, line: &str, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let Ok((_, command)) = Command::parse_line(line) else {
+            return writeln!(out, "{line}");
+        };
+        self.table.push(&command);
+
+        match &command {
+            Command::G90 | Command::MDrop(82) => self.relative_e = false,
+            Command::G91 | Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                if let Some(e) = params.iter().find_map(|param| match param {
+                    PosVal::E(e) => Some(*e),
+                    _ => None,
+                }) {
+                    self.e = e;
+                    self.skipped = false;
+                }
+            }
+            _ => {}
+        }
+
+        let feedrate = feedrate(&command);
+        if feedrate.is_some() {
+            self.feedrate = feedrate;
+        }
+
+        if let Some((e, moves)) = extrusion(&command) {
+            let start = self.e;
+            let delta = if self.relative_e {
+                self.e += e;
+                e
+            } else {
+                self.e = e;
+                e - start
+            };
+
+            if moves && delta > 0_f64 && self.is_cancelled() {
+                self.skipped = true;
+                self.slowed |= feedrate.is_some();
+                return writeln!(out, "{}", strip_params(line, &['E', 'F']));
+            }
+            if self.skipped && !self.relative_e {
+                writeln!(out, "G92 E{start}")?;
+                self.skipped = false;
+            }
+        }
+
+        if self.slowed && is_move(&command) && !self.is_cancelled() {
+            if feedrate.is_none()
+                && let Some(f) = self.feedrate
+            {
+                writeln!(out, "G1 F{f}")?;
+            }
+            self.slowed = false;
+        }
+        writeln!(out, "{line}")
+    }
+
+    /// Rewrites a binary G-code file.
+    ///
+    /// Blocks without a cancelled object are copied unchanged.
+    ///
+    /// # Errors
+    ///   When the file cannot be parsed, or a block decompressed.
+    pub fn rewrite_bgcode(&mut self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        rewrite_blocks(input, |text| {
+            let mut out = vec![];
+            for line in text.lines() {
+                self.write_line(line, &mut out)?;
+            }
+            let out = String::from_utf8_lossy(&out).into_owned();
+            Ok((!out.lines().eq(text.lines())).then_some(out))
+        })
+    }
+}
+
+// Returns the E parameter of a move, and true if the head moves in XY.
+fn extrusion(command: &Command) -> Option<(f64, bool)> {
+    match command {
+        Command::G0(params) | Command::G1(params) => {
+            let e = params.iter().find_map(|param| match param {
+                PosVal::E(e) => Some(*e),
+                _ => None,
+            })?;
+            let moves = params
+                .iter()
+                .any(|param| matches!(param, PosVal::X(_) | PosVal::Y(_)));
+            Some((e, moves))
+        }
+        Command::G2(ArcForm::IJ(params) | ArcForm::R(params))
+        | Command::G3(ArcForm::IJ(params) | ArcForm::R(params)) => {
+            let e = params.iter().find_map(|param| match param {
+                ArcVal::E(e) => Some(*e),
+                _ => None,
+            })?;
+            Some((e, true))
+        }
+        _ => None,
+    }
+}
+
+// Returns the F parameter of a move.
+fn feedrate(command: &Command) -> Option<f64> {
+    match command {
+        Command::G0(params) | Command::G1(params) => params.iter().find_map(|param| match param {
+            PosVal::F(f) => Some(*f),
+            _ => None,
+        }),
+        Command::G2(ArcForm::IJ(params) | ArcForm::R(params))
+        | Command::G3(ArcForm::IJ(params) | ArcForm::R(params)) => {
+            params.iter().find_map(|param| match param {
+                ArcVal::F(f) => Some(*f),
+                _ => None,
+            })
+        }
+        _ => None,
+    }
+}
+
+// Returns true for G0, G1, G2 and G3.
+const fn is_move(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::G0(_) | Command::G1(_) | Command::G2(_) | Command::G3(_)
+    )
+}
+
+// Removes the given parameters, and the space before each, from a move.
+//
+// Handles MeatPack's compact form "G1X10E1.5" as well as "G1 X10 E1.5".
+fn strip_params(line: &str, letters: &[char]) -> String {
+    let (code, comment) = line.split_at(line.find(';').unwrap_or(line.len()));
+    let mut stripped = String::with_capacity(line.len());
+    let mut skipping = false;
+    for (i, c) in code.char_indices() {
+        if i > 0 && letters.contains(&c) {
+            skipping = true;
+            stripped.truncate(stripped.trim_end().len());
+        } else if !(skipping && (c.is_ascii_digit() || matches!(c, '.' | '-' | '+'))) {
+            skipping = false;
+            stripped.push(c);
+        }
+    }
+    stripped.push_str(comment);
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binary::bgcode_parser_with_checksum;
+    use crate::binary::gcode_block::decode::decode_text;
+    use crate::binary::gcode_block::extractor::extract_gcode;
+
+    fn cancel(canceller: &mut Canceller, input: &str) -> String {
+        let mut out = vec![];
+        for line in input.lines() {
+            canceller.write_line(line, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn strip() {
+        assert_eq!(strip_params("G1 X1 E2.5 F300", &['E']), "G1 X1 F300");
+        assert_eq!(strip_params("G1 X1 E.5 ; Edge", &['E']), "G1 X1 ; Edge");
+        assert_eq!(strip_params("G1X1Y2E0.04", &['E']), "G1X1Y2");
+        assert_eq!(strip_params("G2 X1 Y2 I3 J4 E-1", &['E']), "G2 X1 Y2 I3 J4");
+        assert_eq!(strip_params("G1 X1 E2.5 F300", &['E', 'F']), "G1 X1");
+        assert_eq!(strip_params("G1X1F1200.5E0.04", &['E', 'F']), "G1X1");
+    }
+
+    #[test]
+    fn relative() {
+        let mut canceller = Canceller::default();
+        canceller.ids.insert(0);
+        let input = "M83
+M486 S0
+G1 E0.8
+G1 X10 Y10 E1.2
+G1 X10 Y20 E-0.1 ; wipe
+G1 E-0.7
+M486 S-1
+M486 S1
+G1 E0.8
+G1 X20 Y20 E1.2
+";
+        let expected = "M83
+M486 S0
+G1 E0.8
+G1 X10 Y10
+G1 X10 Y20 E-0.1 ; wipe
+G1 E-0.7
+M486 S-1
+M486 S1
+G1 E0.8
+G1 X20 Y20 E1.2
+";
+        assert_eq!(cancel(&mut canceller, input), expected);
+    }
+
+    #[test]
+    fn absolute() {
+        let mut canceller = Canceller::default();
+        canceller.names.insert("cube".to_string());
+        let input = "M82
+G92 E0
+EXCLUDE_OBJECT_START NAME=cube
+G1 X10 Y10 E1
+G3 X10 Y20 I0 J5 E2
+G1 E1.2
+EXCLUDE_OBJECT_END NAME=cube
+G1 E2
+G1 X20 Y20 E3
+";
+        // The retraction inside the object must not undo the skipped moves.
+        let expected = "M82
+G92 E0
+EXCLUDE_OBJECT_START NAME=cube
+G1 X10 Y10
+G3 X10 Y20 I0 J5
+G92 E2
+G1 E1.2
+EXCLUDE_OBJECT_END NAME=cube
+G1 E2
+G1 X20 Y20 E3
+";
+        assert_eq!(cancel(&mut canceller, input), expected);
+    }
+
+    #[test]
+    fn multipart_file() {
+        let input = include_str!("../../assets/both.gcode");
+        let mut canceller = Canceller::default();
+        canceller
+            .names
+            .insert("SpiralHourglassInner.stl".to_string());
+        let output = cancel(&mut canceller, input);
+
+        // The file uses relative extrusion (M83).
+        let mut table = ObjectTable::default();
+        for line in output.lines() {
+            let command = Command::parse_line(line).unwrap().1;
+            table.push(&command);
+            if table.current() == Some(0)
+                && let Some((e, moves)) = extrusion(&command)
+            {
+                assert!(!moves || e <= 0_f64, "{line}");
+            }
+        }
+
+        // The other object is untouched.
+        let before = input
+            .lines()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<ObjectTable>();
+        assert_eq!(table.get(1), before.get(1));
+    }
+
+    #[test]
+    fn travel_speed() {
+        let mut canceller = Canceller::default();
+        canceller.ids.insert(0);
+        let input = "M83
+G1 X10 Y10 F6000
+M486 S0
+G1 X20 Y10 E0.5 F1200
+G1 X20 Y20 E0.5
+M486 S-1
+G1 X30 Y20 E0.5
+";
+        // The cancelled moves travel at 6000, the next extrusion at 1200.
+        let expected = "M83
+G1 X10 Y10 F6000
+M486 S0
+G1 X20 Y10
+G1 X20 Y20
+M486 S-1
+G1 F1200
+G1 X30 Y20 E0.5
+";
+        assert_eq!(cancel(&mut canceller, input), expected);
+    }
+
+    #[test]
+    fn bgcode() {
+        let input = include_bytes!("../../assets/both parts.bgcode");
+        let (_, blocks) = extract_gcode(input).unwrap();
+        let before = decode_text(&blocks).unwrap();
+
+        let mut canceller = Canceller::default();
+        canceller.ids.insert(0);
+        let output = canceller.rewrite_bgcode(input).unwrap();
+        assert!(bgcode_parser_with_checksum(&output).is_ok());
+        let (_, blocks) = extract_gcode(&output).unwrap();
+        let after = decode_text(&blocks).unwrap();
+        assert_ne!(after, before);
+
+        let mut canceller = Canceller::default();
+        canceller.ids.insert(0);
+        assert_eq!(after, cancel(&mut canceller, &before));
+    }
+}
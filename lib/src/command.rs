@@ -8,8 +8,11 @@ use nom::character::complete::digit1;
 use nom::character::complete::line_ending;
 use nom::character::complete::not_line_ending;
 use nom::character::complete::space0;
+use nom::combinator::eof;
 use nom::combinator::map;
+use nom::combinator::map_opt;
 use nom::combinator::map_res;
+use nom::combinator::not;
 use nom::multi::many;
 use nom::sequence::preceded;
 use nom::sequence::terminated;
@@ -23,6 +26,8 @@ use crate::arc::parse_arc_e;
 use crate::arc::parse_arc_f;
 use crate::arc::parse_arc_i;
 use crate::arc::parse_arc_j;
+use crate::arc::parse_arc_k;
+use crate::arc::parse_arc_p;
 use crate::arc::parse_arc_s;
 use crate::arc::parse_arc_u;
 use crate::arc::parse_arc_v;
@@ -45,7 +50,13 @@ use crate::params::head::parse_x;
 use crate::params::head::parse_y;
 use crate::params::head::parse_z;
 
+use crate::params::exclude;
+use crate::params::exclude::ObjectDefinition;
+use crate::params::exclude::parse_exclude_vals;
+use crate::params::limits::LimitVal;
+use crate::params::limits::parse_limit_vals;
 use crate::params::mp::MultiPartVal;
+use crate::params::mp::parse_mp_a;
 use crate::params::mp::parse_mp_c;
 use crate::params::mp::parse_mp_p;
 use crate::params::mp::parse_mp_s;
@@ -75,6 +86,12 @@ pub enum Command {
     /// TODO Must implement.
     // G5 - Bézier Cubic Spline
 
+    /// G17 – Arcs in the XY plane, the default.
+    G17,
+    /// G18 – Arcs in the ZX plane.
+    G18,
+    /// G19 – Arcs in the YZ plane.
+    G19,
     /// Change unit to imperial
     G20,
     /// Change units to metric
@@ -105,6 +122,22 @@ pub enum Command {
     ///
     /// TODO:  F and S are not permitted here.
     G92(HashSet<PosVal>),
+    /// Set the maximum acceleration of each axis, in mm/sec^2
+    ///
+    /// eg. "M201 X1000 Y1000 Z200 E5000"
+    M201(Vec<LimitVal>),
+    /// Set the maximum feedrate of each axis, in mm/sec
+    ///
+    /// eg. "M203 X200 Y200 Z12 E120"
+    M203(Vec<LimitVal>),
+    /// Set the starting acceleration of moves, in mm/sec^2
+    ///
+    /// eg. "M204 P1250 R1250 T1250" for printing, retract and travel moves.
+    M204(Vec<LimitVal>),
+    /// Set the jerk limits, or junction deviation, and minimum feedrates.
+    ///
+    /// eg. "M205 X8.00 Y8.00 Z0.40 E4.50"
+    M205(Vec<LimitVal>),
     /// Multipart: Cancel, Un-cancel parts listed by index
     ///
     /// M486 T12               ; Total of 12 objects (otherwise the firmware must count)
@@ -118,6 +151,26 @@ pub enum Command {
     ///
     /// source <https://docs.duet3d.com/User_manual/Reference/Gcodes>
     M486(MultiPartVal),
+    /// Klipper: declares an object, and its outline.
+    ///
+    /// `EXCLUDE_OBJECT_DEFINE NAME=cube_id_0_copy_0 CENTER=110,110 POLYGON=[[105,105],[115,105],[115,115]]`
+    ///
+    /// source <https://www.klipper3d.org/G-Codes.html#exclude_object>
+    ExcludeObjectDefine(ObjectDefinition),
+    /// Klipper: the named object is starting.
+    ///
+    /// `EXCLUDE_OBJECT_START NAME=cube_id_0_copy_0`
+    ExcludeObjectStart(String),
+    /// Klipper: the named object is complete.
+    ///
+    /// `EXCLUDE_OBJECT_END NAME=cube_id_0_copy_0`
+    ExcludeObjectEnd(String),
+    /// Klipper: cancel the named object.
+    ///
+    /// `EXCLUDE_OBJECT NAME=cube_id_0_copy_0`
+    ExcludeObject(String),
+    /// Select tool, eg. "T1" selects the second extruder.
+    T(u8),
     /// Drop G - no further action.
     GDrop(u16),
     /// Drop M - no further action.
@@ -141,13 +194,17 @@ impl Command {
             parse_g2,
             parse_g3,
             // TODO add G5 - Bézier Cubic Spline
+            parse_plane,
             map(tag("G20"), |_| Self::G20),
             map(tag("G21"), |_| Self::G21),
             map(tag("G90"), |_| Self::G90),
             map(tag("G91"), |_| Self::G91),
             parse_g92,
             parse_comment,
+            parse_limits,
             parse_486,
+            parse_exclude_object,
+            parse_tool,
             // Dropping "bed leveling", "dock sled", "Retract", "Stepper motor", "Mechanical Gantry Calibration"
             map(g_drop, Self::GDrop),
             map(m_drop, Self::MDrop),
@@ -157,6 +214,48 @@ impl Command {
     }
 }
 
+// G17, G18 and G19 select the plane of G2/G3 arcs.
+fn parse_plane(i: &str) -> IResult<&str, Command> {
+    alt((
+        map(tag("G17"), |_| Command::G17),
+        map(tag("G18"), |_| Command::G18),
+        map(tag("G19"), |_| Command::G19),
+    ))
+    .parse(i)
+}
+
+// Klipper's EXCLUDE_OBJECT commands.
+//
+// Commands without a NAME, such as "EXCLUDE_OBJECT RESET=1", are left as a `Nop`.
+fn parse_exclude_object(i: &str) -> IResult<&str, Command> {
+    alt((
+        map_opt(
+            preceded(tag("EXCLUDE_OBJECT_DEFINE"), parse_exclude_vals),
+            |vals| exclude::definition(vals).map(Command::ExcludeObjectDefine),
+        ),
+        map_opt(
+            preceded(tag("EXCLUDE_OBJECT_START"), parse_exclude_vals),
+            |vals| exclude::name(vals).map(Command::ExcludeObjectStart),
+        ),
+        map_opt(
+            preceded(tag("EXCLUDE_OBJECT_END"), parse_exclude_vals),
+            |vals| exclude::name(vals).map(Command::ExcludeObjectEnd),
+        ),
+        map_opt(
+            preceded(tag("EXCLUDE_OBJECT"), parse_exclude_vals),
+            |vals| exclude::name(vals).map(Command::ExcludeObject),
+        ),
+    ))
+    .parse(i)
+}
+
+// Tool selection "T<n>".
+//
+// Other forms, such as the MMU's "Tx" and "Tc", are left as a `Nop`.
+fn parse_tool(i: &str) -> IResult<&str, Command> {
+    map(preceded(tag("T"), nom::character::complete::u8), Command::T).parse(i)
+}
+
 /// G commands that require no further action
 ///
 /// # Errors
@@ -172,7 +271,8 @@ fn parse_comment(i: &str) -> IResult<&str, Command> {
         (space0, tag(";")),
         terminated(
             map(not_line_ending, |v: &str| Command::Comment(v.to_string())),
-            line_ending,
+            // Lines split by `str::lines()` have no line ending.
+            alt((line_ending, eof)),
         ),
     )
     .parse(i)
@@ -239,8 +339,8 @@ fn parse_g2(i: &str) -> IResult<&str, Command> {
             let mut has_r = false;
             for val in &hs {
                 match val {
-                    ArcVal::I(_) | ArcVal::J(_) => {
-                        // If I or J is present, then we have a "IJ" form.
+                    ArcVal::I(_) | ArcVal::J(_) | ArcVal::K(_) => {
+                        // If I, J or K is present, then we have a "IJ" form.
                         has_ij = true;
                     }
                     ArcVal::R(_) => {
@@ -288,8 +388,8 @@ fn parse_g3(i: &str) -> IResult<&str, Command> {
             let mut has_r = false;
             for val in &hs {
                 match val {
-                    ArcVal::I(_) | ArcVal::J(_) => {
-                        // If I or J is present, then we have a "IJ" form.
+                    ArcVal::I(_) | ArcVal::J(_) | ArcVal::K(_) => {
+                        // If I, J or K is present, then we have a "IJ" form.
                         has_ij = true;
                     }
                     ArcVal::R(_) => {
@@ -332,6 +432,31 @@ fn parse_g92(i: &str) -> IResult<&str, Command> {
     .parse(i)
 }
 
+// M201/M203/M204/M205 Motion limits.
+//
+// The parameters are optional, so guard against longer codes such as "M2040".
+fn parse_limits(i: &str) -> IResult<&str, Command> {
+    alt((
+        map(
+            preceded((tag("M201"), not(digit1)), parse_limit_vals),
+            Command::M201,
+        ),
+        map(
+            preceded((tag("M203"), not(digit1)), parse_limit_vals),
+            Command::M203,
+        ),
+        map(
+            preceded((tag("M204"), not(digit1)), parse_limit_vals),
+            Command::M204,
+        ),
+        map(
+            preceded((tag("M205"), not(digit1)), parse_limit_vals),
+            Command::M205,
+        ),
+    ))
+    .parse(i)
+}
+
 /// M486 Start/Cancel objects
 ///
 /// This command supports multipart rendering.
@@ -386,7 +511,10 @@ fn pos_val(i: &str) -> IResult<&str, PosVal> {
 /// # Errors
 ///   When match fails.
 fn multipart_val(i: &str) -> IResult<&str, MultiPartVal> {
-    alt((parse_mp_c, parse_mp_p, parse_mp_s, parse_mp_t, parse_mp_u)).parse(i)
+    alt((
+        parse_mp_a, parse_mp_c, parse_mp_p, parse_mp_s, parse_mp_t, parse_mp_u,
+    ))
+    .parse(i)
 }
 
 ///
@@ -401,6 +529,8 @@ fn arc_val(i: &str) -> IResult<&str, ArcVal> {
         parse_arc_f,
         parse_arc_i,
         parse_arc_j,
+        parse_arc_k,
+        parse_arc_p,
         parse_arc_s,
         parse_arc_u,
         parse_arc_v,
@@ -458,6 +588,11 @@ mod test {
                     Command::Comment(" generated by Slic3r 1.2.9 on 2015-10-01 at 20:51:53".into()),
                 )),
             ),
+            (
+                // As returned by `str::lines()`, without a line ending.
+                ";LAYER_CHANGE",
+                Ok(("", Command::Comment("LAYER_CHANGE".into()))),
+            ),
         ];
 
         for (line, expected) in text_commands {
@@ -466,6 +601,17 @@ mod test {
         }
     }
 
+    #[test]
+    fn tool() {
+        assert_eq!(Command::parse_line("T1"), Ok(("", Command::T(1))));
+        assert_eq!(
+            Command::parse_line("T0 ; select tool"),
+            Ok((" ; select tool", Command::T(0)))
+        );
+        // MMU specific forms are not a tool selection.
+        assert_eq!(Command::parse_line("Tx"), Ok(("Tx", Command::Nop)));
+    }
+
     #[test]
     fn g0() {
         let text_commands = [
@@ -639,6 +785,22 @@ mod test {
                     Command::G2(ArcForm::IJ([ArcVal::I(20_f64), ArcVal::J(20_f64)].into())),
                 )),
             ),
+            (
+                "G2 X10 Y0 I-10 J0 P2; two more turns",
+                Ok((
+                    "; two more turns",
+                    Command::G2(ArcForm::IJ(
+                        [
+                            ArcVal::X(10_f64),
+                            ArcVal::Y(0_f64),
+                            ArcVal::I(-10_f64),
+                            ArcVal::J(0_f64),
+                            ArcVal::P(2_f64),
+                        ]
+                        .into(),
+                    )),
+                )),
+            ),
             (
                 // Leading zero check
                 "G02X100J20",
@@ -716,6 +878,35 @@ mod test {
         }
     }
 
+    // G17, G18 and G19 select the plane, arcs in the ZX and YZ planes use K.
+    #[test]
+    fn planes() {
+        let text_commands = [
+            ("G17", Ok(("", Command::G17))),
+            ("G18 ; ZX", Ok((" ; ZX", Command::G18))),
+            ("G19", Ok(("", Command::G19))),
+            (
+                "G2 X0 Z10 I-10 K0",
+                Ok((
+                    "",
+                    Command::G2(ArcForm::IJ(
+                        [
+                            ArcVal::X(0_f64),
+                            ArcVal::Z(10_f64),
+                            ArcVal::I(-10_f64),
+                            ArcVal::K(0_f64),
+                        ]
+                        .into(),
+                    )),
+                )),
+            ),
+        ];
+        for (line, expected) in text_commands {
+            let actual = Command::parse_line(line);
+            assert_eq!(actual, expected, "line: {line}");
+        }
+    }
+
     // G486 Multipart support.
     //
     // Start, Un-cancel,
@@ -729,14 +920,20 @@ mod test {
                     Command::M486(MultiPartVal::C),
                 )),
             ),
-            // This is broken...
-            // (
-            //     "M486 S3 A\"cube copy 3\" xx",
-            //     Ok((
-            //         "",
-            //         Command::M486(MultiPartVal::S(3, Some("cube copy 3".to_string()))),
-            //     )),
-            // ),
+            (
+                "M486 S3 A\"cube copy 3\" ; Indicate that the 4th object is starting now and name it",
+                Ok((
+                    "; Indicate that the 4th object is starting now and name it",
+                    Command::M486(MultiPartVal::S(3, Some("cube copy 3".to_string()))),
+                )),
+            ),
+            (
+                "M486 ASpiralHourglass.stl",
+                Ok((
+                    "",
+                    Command::M486(MultiPartVal::A("SpiralHourglass.stl".to_string())),
+                )),
+            ),
             (
                 "M486 S3; Indicate that the 4th object is starting now",
                 Ok((
@@ -783,6 +980,87 @@ mod test {
         }
     }
 
+    #[test]
+    fn limits() {
+        let text_commands = [
+            (
+                "M201 X1000 Y1000 Z200 E5000 ; sets maximum accelerations, mm/sec^2",
+                Ok((
+                    " ; sets maximum accelerations, mm/sec^2",
+                    Command::M201(vec![
+                        LimitVal::X(1000.0),
+                        LimitVal::Y(1000.0),
+                        LimitVal::Z(200.0),
+                        LimitVal::E(5000.0),
+                    ]),
+                )),
+            ),
+            (
+                "M203 X200 Y200",
+                Ok((
+                    "",
+                    Command::M203(vec![LimitVal::X(200.0), LimitVal::Y(200.0)]),
+                )),
+            ),
+            (
+                "M204 S800",
+                Ok(("", Command::M204(vec![LimitVal::S(800.0)]))),
+            ),
+            (
+                "M205 S0 T0",
+                Ok(("", Command::M205(vec![LimitVal::S(0.0), LimitVal::T(0.0)]))),
+            ),
+            // Not a limit.
+            ("M2040", Ok(("", Command::MDrop(2040)))),
+        ];
+
+        for (line, expected) in text_commands {
+            let actual = Command::parse_line(line);
+            assert_eq!(actual, expected, "line: {line}");
+        }
+    }
+
+    #[test]
+    fn exclude_object() {
+        let text_commands = [
+            (
+                "EXCLUDE_OBJECT_DEFINE NAME=cube_id_0 CENTER=110,110 POLYGON=[[105,105],[115,115]]",
+                Ok((
+                    "",
+                    Command::ExcludeObjectDefine(ObjectDefinition {
+                        name: "cube_id_0".to_string(),
+                        center: Some((110.0, 110.0)),
+                        polygon: vec![(105.0, 105.0), (115.0, 115.0)],
+                    }),
+                )),
+            ),
+            (
+                "EXCLUDE_OBJECT_START NAME=cube_id_0",
+                Ok(("", Command::ExcludeObjectStart("cube_id_0".to_string()))),
+            ),
+            (
+                "EXCLUDE_OBJECT_END NAME=cube_id_0 ; done",
+                Ok((
+                    " ; done",
+                    Command::ExcludeObjectEnd("cube_id_0".to_string()),
+                )),
+            ),
+            (
+                "EXCLUDE_OBJECT NAME=cube_id_0",
+                Ok(("", Command::ExcludeObject("cube_id_0".to_string()))),
+            ),
+            (
+                "EXCLUDE_OBJECT RESET=1",
+                Ok(("EXCLUDE_OBJECT RESET=1", Command::Nop)),
+            ),
+        ];
+
+        for (line, expected) in text_commands {
+            let actual = Command::parse_line(line);
+            assert_eq!(actual, expected, "line: {line}");
+        }
+    }
+
     #[test]
     const fn parse_g_drop() {}
 }
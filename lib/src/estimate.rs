@@ -0,0 +1,744 @@
+//! Estimates the print time, by replaying the moves through a model of the
+//! firmware's motion planner.
+//!
+//! Each move accelerates to its nominal speed, cruises, then decelerates
+//! (a trapezoidal velocity profile). The speed through each corner is
+//! limited by the jerk settings, or by junction deviation when M205 J is
+//! given. Like the firmware, the planner looks ahead over a bounded number
+//! of moves.
+//!
+//! Time spent heating, homing or waiting is not included.
+
+use std::collections::VecDeque;
+
+use crate::Plane;
+use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::command::Command;
+use crate::compute_arc_radius;
+use crate::compute_arc_to;
+use crate::layer::Layer;
+use crate::layer::LayerTracker;
+use crate::params::head::PosVal;
+use crate::params::limits::LimitVal;
+
+/// The number of moves the planner looks ahead.
+const PLANNER_BLOCKS: usize = 64;
+
+/// Moves shorter than this take no time.
+const MIN_LENGTH: f64 = 1e-9;
+
+/// The firmware splits arcs into segments of this length, in mm.
+const MM_PER_ARC_SEGMENT: f64 = 1_f64;
+
+/// The most segments an arc is split into.
+const MAX_ARC_SEGMENTS: f64 = 65536_f64;
+
+/// The key of the slicer's own estimate, in comments and print metadata.
+///
+/// `; estimated printing time (normal mode) = 1h 16m 44s`
+pub const SLICER_ESTIMATE_KEY: &str = "estimated printing time (normal mode)";
+
+/// The motion limits of the printer.
+///
+/// Arrays are indexed by axis X, Y, Z, E.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Set by M201, in mm/sec^2.
+    pub max_acceleration: [f64; 4],
+    /// Set by M203, in mm/sec.
+    pub max_feedrate: [f64; 4],
+    /// Acceleration of printing moves, set by M204 P (or S), in mm/sec^2.
+    pub acceleration: f64,
+    /// Acceleration of moves which only drive the extruder, set by M204 R.
+    pub retract_acceleration: f64,
+    /// Acceleration of non extruding moves, set by M204 T (or S).
+    pub travel_acceleration: f64,
+    /// The largest instantaneous change of speed, set by M205, in mm/sec.
+    pub jerk: [f64; 4],
+    /// Set by M205 J, in mm. When given, replaces the jerk limits of
+    /// corners.
+    pub junction_deviation: Option<f64>,
+    /// The slowest printing move, set by M205 S, in mm/sec.
+    pub min_feedrate: f64,
+    /// The slowest travel move, set by M205 T, in mm/sec.
+    pub min_travel_feedrate: f64,
+}
+
+/// The Marlin defaults of a Prusa MK3.
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_acceleration: [1000_f64, 1000_f64, 200_f64, 5000_f64],
+            max_feedrate: [200_f64, 200_f64, 12_f64, 120_f64],
+            acceleration: 1250_f64,
+            retract_acceleration: 1250_f64,
+            travel_acceleration: 1250_f64,
+            jerk: [8_f64, 8_f64, 0.4_f64, 4.5_f64],
+            junction_deviation: None,
+            min_feedrate: 0_f64,
+            min_travel_feedrate: 0_f64,
+        }
+    }
+}
+
+impl Limits {
+    /// Applies M201, M203, M204 and M205, other commands are ignored.
+    pub fn apply(&mut self, command: &Command) {
+        match command {
+            Command::M201(vals) => set_axes(&mut self.max_acceleration, vals),
+            Command::M203(vals) => set_axes(&mut self.max_feedrate, vals),
+            Command::M204(vals) => {
+                for val in vals {
+                    match val {
+                        LimitVal::P(val) => self.acceleration = *val,
+                        LimitVal::R(val) => self.retract_acceleration = *val,
+                        LimitVal::S(val) => {
+                            self.acceleration = *val;
+                            self.travel_acceleration = *val;
+                        }
+                        LimitVal::T(val) => self.travel_acceleration = *val,
+                        _ => {}
+                    }
+                }
+            }
+            Command::M205(vals) => {
+                set_axes(&mut self.jerk, vals);
+                for val in vals {
+                    match val {
+                        LimitVal::J(val) => {
+                            self.junction_deviation = (*val > 0_f64).then_some(*val);
+                        }
+                        LimitVal::S(val) => self.min_feedrate = *val,
+                        LimitVal::T(val) => self.min_travel_feedrate = *val,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_axes(axes: &mut [f64; 4], vals: &[LimitVal]) {
+    for val in vals {
+        match val {
+            LimitVal::X(val) => axes[0] = *val,
+            LimitVal::Y(val) => axes[1] = *val,
+            LimitVal::Z(val) => axes[2] = *val,
+            LimitVal::E(val) => axes[3] = *val,
+            _ => {}
+        }
+    }
+}
+
+/// The time spent printing a single layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerTime {
+    /// The layer.
+    pub layer: Layer,
+    /// In seconds.
+    pub time: f64,
+}
+
+/// The result of replaying a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// The time to print the file, in seconds.
+    pub total: f64,
+    /// The time spent in each layer, indexed by layer.
+    ///
+    /// Moves made before the first layer are only counted in the total.
+    pub layers: Vec<LayerTime>,
+    /// The slicer's own estimate, in seconds, if found.
+    pub slicer: Option<f64>,
+}
+
+/// Estimates the print time of a command stream.
+///
+/// ```rust
+/// use gcode_nom::command::Command;
+/// use gcode_nom::estimate::Estimate;
+///
+/// let estimate = ["G1 F6000", "G1 X100"]
+///     .iter()
+///     .map(|line| Command::parse_line(line).unwrap().1)
+///     .collect::<Estimate>();
+///
+/// // Slower than 100mm at 100mm/s, as the head must accelerate.
+/// assert!(estimate.total > 1.0 && estimate.total < 1.2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Estimator {
+    /// The limits used by moves which follow.
+    pub limits: Limits,
+    // Moves which may still be slowed down by the moves that follow.
+    queue: VecDeque<Block>,
+    // The direction and nominal speed of the last move.
+    previous: Option<([f64; 4], f64)>,
+    // X, Y, Z and E.
+    position: [f64; 4],
+    // In mm/sec.
+    feedrate: f64,
+    position_mode: PositionMode,
+    relative_e: bool,
+    // The plane of G2/G3 arcs.
+    plane: Plane,
+    tracker: LayerTracker,
+    estimate: Estimate,
+}
+
+impl Default for Estimator {
+    fn default() -> Self {
+        Self {
+            limits: Limits::default(),
+            queue: VecDeque::with_capacity(PLANNER_BLOCKS + 1),
+            previous: None,
+            position: [0_f64; 4],
+            // 1500 mm/min
+            feedrate: 25_f64,
+            position_mode: PositionMode::Absolute,
+            relative_e: false,
+            plane: Plane::default(),
+            tracker: LayerTracker::default(),
+            estimate: Estimate::default(),
+        }
+    }
+}
+
+// A move held by the planner.
+#[derive(Clone, Copy, Debug)]
+struct Block {
+    length: f64,
+    // The cruising speed.
+    nominal: f64,
+    acceleration: f64,
+    // The fastest the move can start, limited by the corner before it.
+    max_entry: f64,
+    entry: f64,
+    // The speed at which the move can start or stop, without a ramp.
+    safe: f64,
+    layer: Option<Layer>,
+}
+
+impl Block {
+    // The time taken, given the speed at the start and end of the move.
+    fn time(&self, entry: f64, exit: f64) -> f64 {
+        let a = self.acceleration;
+        let nominal = self.nominal.powi(2);
+        let accelerate = entry.mul_add(-entry, nominal) / (2_f64 * a);
+        let decelerate = exit.mul_add(-exit, nominal) / (2_f64 * a);
+        if accelerate + decelerate <= self.length {
+            let cruise = self.length - accelerate - decelerate;
+            (self.nominal - entry) / a + (self.nominal - exit) / a + cruise / self.nominal
+        } else {
+            // Never reaches the nominal speed.
+            let peak = a
+                .mul_add(self.length, f64::midpoint(entry.powi(2), exit.powi(2)))
+                .sqrt()
+                .max(entry)
+                .max(exit);
+            (peak - entry) / a + (peak - exit) / a
+        }
+    }
+
+    // The fastest speed at the end of the move, given the speed at its start.
+    fn reachable(&self, entry: f64) -> f64 {
+        2_f64
+            .mul_add(self.acceleration * self.length, entry.powi(2))
+            .sqrt()
+    }
+}
+
+impl Estimator {
+    /// Replays a single command.
+    pub fn push(&mut self, command: &Command) {
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                let mut target = self.position;
+                for param in params {
+                    match param {
+                        PosVal::X(val) => target[0] = self.axis(0, *val),
+                        PosVal::Y(val) => target[1] = self.axis(1, *val),
+                        PosVal::Z(val) => target[2] = self.axis(2, *val),
+                        PosVal::E(val) => target[3] = self.axis(3, *val),
+                        PosVal::F(val) => self.feedrate = *val / 60_f64,
+                        _ => {}
+                    }
+                }
+                self.line(target);
+            }
+            Command::G2(form) => self.arc(form, true),
+            Command::G3(form) => self.arc(form, false),
+            Command::G17 => self.plane = Plane::XY,
+            Command::G18 => self.plane = Plane::ZX,
+            Command::G19 => self.plane = Plane::YZ,
+            Command::G90 => {
+                self.position_mode = PositionMode::Absolute;
+                self.relative_e = false;
+            }
+            Command::G91 => {
+                self.position_mode = PositionMode::Relative;
+                self.relative_e = true;
+            }
+            Command::MDrop(82) => self.relative_e = false,
+            Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.position[0] = *val,
+                        PosVal::Y(val) => self.position[1] = *val,
+                        PosVal::Z(val) => self.position[2] = *val,
+                        PosVal::E(val) => self.position[3] = *val,
+                        _ => {}
+                    }
+                }
+            }
+            Command::M201(_) | Command::M203(_) | Command::M204(_) | Command::M205(_) => {
+                self.limits.apply(command);
+            }
+            Command::Comment(text) => {
+                if self.tracker.comment(text) {
+                    self.start_layer();
+                }
+                if let Some((key, value)) = text.split_once('=')
+                    && key.trim() == SLICER_ESTIMATE_KEY
+                {
+                    self.estimate.slicer = parse_duration(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Completes the moves still held by the planner, and returns the
+    /// estimate.
+    #[must_use]
+    pub fn finish(mut self) -> Estimate {
+        self.plan();
+        while !self.queue.is_empty() {
+            self.retire();
+        }
+        self.estimate
+    }
+
+    // Returns the new position of an axis.
+    fn axis(&self, axis: usize, val: f64) -> f64 {
+        let relative = if axis == 3 {
+            self.relative_e
+        } else {
+            self.position_mode == PositionMode::Relative
+        };
+        if relative {
+            self.position[axis] + val
+        } else {
+            val
+        }
+    }
+
+    // Splits an arc in the selected plane into short lines, as the firmware
+    // does. The third axis, and E, change evenly along the arc.
+    //
+    // The R form is treated as a straight line.
+    fn arc(&mut self, form: &ArcForm, clockwise: bool) {
+        let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+        let start = self.position;
+        let mut target = start;
+        for param in params {
+            match param {
+                ArcVal::X(val) => target[0] = self.axis(0, *val),
+                ArcVal::Y(val) => target[1] = self.axis(1, *val),
+                ArcVal::Z(val) => target[2] = self.axis(2, *val),
+                ArcVal::E(val) => target[3] = self.axis(3, *val),
+                ArcVal::F(val) => self.feedrate = *val / 60_f64,
+                _ => {}
+            }
+        }
+        let [a, b, n] = self.plane.axes();
+        let (from, to) = ((start[a], start[b]), (target[a], target[b]));
+        let arc = match form {
+            ArcForm::IJ(_) => compute_arc_to(from, to, form, self.plane),
+            ArcForm::R(_) => {
+                let Some(arc) = compute_arc_radius(from, to, form, clockwise) else {
+                    self.line(target);
+                    return;
+                };
+                arc
+            }
+        };
+        let sweep = arc.sweep(clockwise);
+        let (ca, cb) = arc.center;
+        // Arcs are split into segments, as the firmware does.
+        let steps = (sweep.abs() * arc.radius / MM_PER_ARC_SEGMENT)
+            .ceil()
+            .clamp(1_f64, MAX_ARC_SEGMENTS);
+        for step in (1_u32..).map(f64::from).take_while(|step| *step < steps) {
+            let t = step / steps;
+            let (sin, cos) = sweep.mul_add(t, arc.theta_start).sin_cos();
+            let mut point = target;
+            point[a] = arc.radius.mul_add(cos, ca);
+            point[b] = arc.radius.mul_add(sin, cb);
+            point[n] = (target[n] - start[n]).mul_add(t, start[n]);
+            point[3] = (target[3] - start[3]).mul_add(t, start[3]);
+            self.line(point);
+        }
+        self.line(target);
+    }
+
+    // Adds a straight move to the planner.
+    fn line(&mut self, target: [f64; 4]) {
+        let delta = [
+            target[0] - self.position[0],
+            target[1] - self.position[1],
+            target[2] - self.position[2],
+            target[3] - self.position[3],
+        ];
+        self.position = target;
+
+        let xyz = delta[0].hypot(delta[1]).hypot(delta[2]);
+        let length = if xyz > MIN_LENGTH {
+            xyz
+        } else {
+            delta[3].abs()
+        };
+        if length < MIN_LENGTH {
+            return;
+        }
+        let unit = delta.map(|d| d / length);
+        let extruding = delta[3] != 0_f64;
+        if extruding && delta[3] > 0_f64 && xyz > MIN_LENGTH && self.tracker.extrude(target[2]) {
+            self.start_layer();
+        }
+
+        let limits = &self.limits;
+        let mut nominal = self.feedrate.max(if extruding {
+            limits.min_feedrate
+        } else {
+            limits.min_travel_feedrate
+        });
+        let mut acceleration = if xyz <= MIN_LENGTH {
+            limits.retract_acceleration
+        } else if extruding {
+            limits.acceleration
+        } else {
+            limits.travel_acceleration
+        };
+        let mut safe = f64::INFINITY;
+        for (axis, u) in unit.iter().enumerate() {
+            let u = u.abs();
+            if u > 0_f64 {
+                nominal = nominal.min(limits.max_feedrate[axis] / u);
+                acceleration = acceleration.min(limits.max_acceleration[axis] / u);
+                safe = safe.min(limits.jerk[axis] / u);
+            }
+        }
+        let safe = if limits.junction_deviation.is_some() {
+            0_f64
+        } else {
+            safe.min(nominal)
+        };
+
+        let junction = match self.previous {
+            None => safe,
+            Some((previous, previous_nominal)) => {
+                let speed = nominal.min(previous_nominal);
+                limits.junction_deviation.map_or_else(
+                    || jerk_speed(&previous, &unit, &limits.jerk, speed),
+                    |deviation| {
+                        deviation_speed(&previous, &unit, acceleration, deviation).min(speed)
+                    },
+                )
+            }
+        };
+        self.previous = Some((unit, nominal));
+
+        self.queue.push_back(Block {
+            length,
+            nominal,
+            acceleration,
+            max_entry: junction.min(nominal),
+            entry: 0_f64,
+            safe,
+            layer: self.tracker.current(),
+        });
+        if self.queue.len() > PLANNER_BLOCKS {
+            self.plan();
+            self.retire();
+        }
+    }
+
+    // Sets the entry speeds of the queued moves, assuming the head stops
+    // after the last one.
+    fn plan(&mut self) {
+        // Backwards, each move must be able to slow down for the next.
+        let mut exit = self.queue.back().map_or(0_f64, |block| block.safe);
+        for block in self.queue.iter_mut().rev() {
+            block.entry = block.max_entry.min(block.reachable(exit));
+            exit = block.entry;
+        }
+        // Forwards, each move must be able to reach the speed of the next.
+        let mut previous: Option<Block> = None;
+        for block in &mut self.queue {
+            if let Some(previous) = previous {
+                block.entry = block.entry.min(previous.reachable(previous.entry));
+            }
+            previous = Some(*block);
+        }
+    }
+
+    // Removes the oldest move from the planner, and adds its time.
+    fn retire(&mut self) {
+        let Some(block) = self.queue.pop_front() else {
+            return;
+        };
+        let exit = match self.queue.front_mut() {
+            Some(next) => {
+                // The speed is now fixed.
+                next.max_entry = next.entry;
+                next.entry
+            }
+            None => block.safe.min(block.reachable(block.entry)),
+        };
+        let time = block.time(block.entry, exit);
+        self.estimate.total += time;
+        if let Some(layer) = block.layer
+            && let Some(entry) = self.estimate.layers.get_mut(layer.index)
+        {
+            entry.time += time;
+        }
+    }
+
+    // Lists each layer as it starts, including layers without moves.
+    fn start_layer(&mut self) {
+        if let Some(layer) = self.tracker.current() {
+            self.estimate.layers.push(LayerTime { layer, time: 0_f64 });
+        }
+    }
+}
+
+// The fastest speed through a corner, keeping the change in speed of
+// each axis within its jerk limit.
+fn jerk_speed(previous: &[f64; 4], unit: &[f64; 4], jerk: &[f64; 4], speed: f64) -> f64 {
+    let mut factor = 1_f64;
+    for axis in 0..4 {
+        let jump = speed * (unit[axis] - previous[axis]).abs();
+        if jump > jerk[axis] {
+            factor = factor.min(jerk[axis] / jump);
+        }
+    }
+    speed * factor
+}
+
+// The fastest speed through a corner, following a circular path which
+// deviates from the corner by `deviation`.
+fn deviation_speed(previous: &[f64; 4], unit: &[f64; 4], acceleration: f64, deviation: f64) -> f64 {
+    let norm = |v: &[f64; 4]| v[0].hypot(v[1]).hypot(v[2]).hypot(v[3]);
+    let dot =
+        previous.iter().zip(unit).map(|(a, b)| a * b).sum::<f64>() / (norm(previous) * norm(unit));
+    let cos_theta = (-dot).max(-0.999_999);
+    if cos_theta > 0.999_999 {
+        // Reversing direction.
+        return 0_f64;
+    }
+    let sin_half = (0.5_f64 * (1_f64 - cos_theta)).sqrt();
+    (acceleration * deviation * sin_half / (1_f64 - sin_half)).sqrt()
+}
+
+/// Parses a duration, as written by the slicer.
+///
+/// ```rust
+/// use gcode_nom::estimate::parse_duration;
+///
+/// assert_eq!(parse_duration("1h 16m 44s"), Some(4604.0));
+/// assert_eq!(parse_duration("1d 0h 0m 1s"), Some(86401.0));
+/// ```
+#[must_use]
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let mut seconds = 0_f64;
+    for part in text.split_whitespace() {
+        let unit = match part.chars().last()? {
+            'd' => 86400_f64,
+            'h' => 3600_f64,
+            'm' => 60_f64,
+            's' => 1_f64,
+            _ => return None,
+        };
+        seconds += part[..part.len() - 1].parse::<f64>().ok()? * unit;
+    }
+    Some(seconds)
+}
+
+impl FromIterator<Command> for Estimate {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Command>,
+    {
+        let mut estimator = Estimator::default();
+        for command in iter {
+            estimator.push(&command);
+        }
+        estimator.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn estimate(lines: &[&str]) -> Estimate {
+        lines
+            .iter()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn trapezoid() {
+        // 100mm at 100mm/s, accelerating at 1000mm/s^2 from the jerk
+        // limit of 8mm/s.
+        let total = estimate(&["G1 F6000", "G1 X100"]).total;
+        let ramp = (100_f64 - 8_f64) / 1000_f64;
+        let ramp_length = (10_000_f64 - 64_f64) / 2000_f64;
+        let cruise = 2_f64.mul_add(-ramp_length, 100_f64) / 100_f64;
+        let expected = 2_f64.mul_add(ramp, cruise);
+        assert!((total - expected).abs() < 1e-9, "{total} {expected}");
+    }
+
+    #[test]
+    fn triangle() {
+        // Too short to reach the nominal speed.
+        let total = estimate(&["M205 X0 Y0", "G1 F6000", "G1 X1"]).total;
+        // 0.5mm accelerating from rest at 1000mm/s^2, then decelerating.
+        let expected = 2_f64 * (2_f64 * 0.5_f64 / 1000_f64).sqrt();
+        assert!((total - expected).abs() < 1e-9, "{total} {expected}");
+    }
+
+    #[test]
+    fn limits() {
+        // The feedrate is limited by M203, the acceleration by M204.
+        let slow = estimate(&["M203 X10", "G1 F6000", "G1 X100"]).total;
+        assert!(slow > 10_f64);
+        let fast = estimate(&["M204 T10000", "M201 X10000", "G1 F6000", "G1 X100"]).total;
+        let normal = estimate(&["G1 F6000", "G1 X100"]).total;
+        assert!(fast < normal);
+    }
+
+    #[test]
+    fn corners() {
+        // A straight line split in two is no slower.
+        let straight = estimate(&["G1 F6000", "G1 X50", "G1 X100"]).total;
+        let single = estimate(&["G1 F6000", "G1 X100"]).total;
+        assert!((straight - single).abs() < 1e-9);
+
+        // A sharp corner is slower, more so with a small junction deviation.
+        let jerk = estimate(&["G1 F6000", "G1 X50", "G1 Y50"]).total;
+        let deviation = estimate(&["M205 J0.01", "G1 F6000", "G1 X50", "G1 Y50"]).total;
+        assert!(jerk > single);
+        assert!(deviation > jerk);
+    }
+
+    #[test]
+    fn arcs() {
+        // A quarter circle, radius 10mm.
+        let arc = estimate(&[
+            "M204 S100000",
+            "M201 X100000 Y100000",
+            "G1 X10 F600",
+            "G3 X0 Y10 I-10 J0",
+        ]);
+        // 10mm then ~15.7mm at 10mm/s.
+        let expected = 1_f64 + core::f64::consts::FRAC_PI_2;
+        assert!((arc.total - expected).abs() < 0.01, "{}", arc.total);
+
+        // A full circle, ending where it starts, then with an extra turn.
+        for (circle, turns) in [
+            ("G2 X10 Y0 I-10 J0", 1_f64),
+            ("G2 X10 Y0 I-10 J0 P1", 2_f64),
+        ] {
+            let arc = estimate(&[
+                "M204 S100000",
+                "M201 X100000 Y100000",
+                "G1 X10 F600",
+                circle,
+            ]);
+            let expected = turns.mul_add(core::f64::consts::TAU, 1_f64);
+            assert!((arc.total - expected).abs() < 0.01, "{}", arc.total);
+        }
+
+        // A quarter circle in the ZX plane, centred on K and I. Measured
+        // from Z towards X, the shorter way is clockwise.
+        let arc = estimate(&[
+            "M204 S100000",
+            "M201 X100000 Y100000 Z100000",
+            "G1 X10 F600",
+            "G18",
+            "G2 X0 Z10 I-10 K0",
+        ]);
+        let expected = 1_f64 + core::f64::consts::FRAC_PI_2;
+        assert!((arc.total - expected).abs() < 0.01, "{}", arc.total);
+
+        // The same quarter circle given by its radius, and the long way round.
+        for (r, turns) in [(10_f64, 0.25_f64), (-10_f64, 0.75)] {
+            let form = [ArcVal::X(0_f64), ArcVal::Y(10_f64), ArcVal::R(r)];
+            let arc = ["M204 S100000", "M201 X100000 Y100000", "G1 X10 F600"]
+                .iter()
+                .map(|line| Command::parse_line(line).unwrap().1)
+                .chain([Command::G3(ArcForm::R(form.into()))])
+                .collect::<Estimate>();
+            let expected = turns.mul_add(core::f64::consts::TAU, 1_f64);
+            assert!((arc.total - expected).abs() < 0.01, "{}", arc.total);
+        }
+    }
+
+    #[test]
+    fn layers() {
+        let estimate = estimate(&[
+            "; estimated printing time (normal mode) = 1m 2s",
+            "G1 F600",
+            ";LAYER_CHANGE",
+            ";Z:0.2",
+            "G1 Z0.2",
+            "G1 X10 E1",
+            ";LAYER_CHANGE",
+            ";Z:0.4",
+            "G1 Z0.4",
+            "G1 X0 E2",
+            ";LAYER_CHANGE",
+            ";Z:0.6",
+            ";LAYER_CHANGE",
+            ";Z:0.8",
+            "G1 Z0.8",
+            "G1 X10 E3",
+        ]);
+        assert_eq!(estimate.slicer, Some(62_f64));
+        assert_eq!(estimate.layers.len(), 4);
+        assert_eq!(estimate.layers[1].layer, Layer { index: 1, z: 0.4 });
+        // A layer without moves is listed, and takes no time.
+        assert_eq!(estimate.layers[2].layer, Layer { index: 2, z: 0.6 });
+        assert!(estimate.layers[2].time.abs() < 1e-12);
+        assert_eq!(estimate.layers[3].layer, Layer { index: 3, z: 0.8 });
+        let sum = estimate.layers.iter().map(|layer| layer.time).sum::<f64>();
+        assert!((sum - estimate.total).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slicer() {
+        use crate::binary::bgcode_parser;
+        use crate::binary::gcode_block::decode::decode_blocks;
+
+        // Agrees with PrusaSlicer's estimate of 32m 6s.
+        let buffer = include_bytes!("../../assets/mini_cube_b.bgcode");
+        let (_remain, bgcode) = bgcode_parser(buffer).unwrap();
+        let estimate = decode_blocks(&bgcode.gcode)
+            .unwrap()
+            .into_iter()
+            .collect::<Estimate>();
+        let slicer = 1926_f64;
+        assert!(
+            (estimate.total - slicer).abs() / slicer < 0.02,
+            "{}",
+            estimate.total
+        );
+    }
+}
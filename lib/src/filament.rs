@@ -0,0 +1,353 @@
+//! Sums the filament used by a print, per tool and per object.
+//!
+//! The length of filament is taken from the E axis. Retractions are not
+//! counted as filament used; the filament pushed back out after a
+//! retraction is not counted a second time.
+//!
+//! Lengths are converted to volume, mass and cost using the slicer's
+//! settings, which are found in the comments at the end of a .gcode file,
+//! or in the metadata of a .bgcode file.
+//!
+//! ```text
+//! ; filament_diameter = 1.75
+//! ; filament_density = 1.24
+//! ; filament_cost = 25.4
+//! ```
+
+use core::f64::consts::PI;
+use std::collections::BTreeMap;
+
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::command::Command;
+use crate::objects::ObjectTable;
+use crate::params::head::PosVal;
+
+/// Used when the diameter is not known, in mm.
+const DEFAULT_DIAMETER: f64 = 1.75;
+
+/// Used when the density is not known, in g/cm^3 (PLA).
+const DEFAULT_DENSITY: f64 = 1.24;
+
+/// The properties of the filament, one value per tool.
+///
+/// When there are fewer values than tools, the last value is used.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filament {
+    /// In mm.
+    pub diameter: Vec<f64>,
+    /// In g/cm^3.
+    pub density: Vec<f64>,
+    /// Per kg.
+    pub cost: Vec<f64>,
+}
+
+impl Filament {
+    /// Reads a slicer setting, unknown keys are ignored.
+    ///
+    /// Values are comma separated lists, one value per tool.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let values = || {
+            value
+                .split(',')
+                .filter_map(|v| v.trim().parse::<f64>().ok())
+                .collect::<Vec<_>>()
+        };
+        match key {
+            "filament_diameter" => self.diameter = values(),
+            "filament_density" => self.density = values(),
+            "filament_cost" => self.cost = values(),
+            _ => {}
+        }
+    }
+
+    /// The diameter of the filament used by a tool, in mm.
+    #[must_use]
+    pub fn diameter(&self, tool: u8) -> f64 {
+        per_tool(&self.diameter, tool).unwrap_or(DEFAULT_DIAMETER)
+    }
+
+    /// The density of the filament used by a tool, in g/cm^3.
+    #[must_use]
+    pub fn density(&self, tool: u8) -> f64 {
+        per_tool(&self.density, tool).unwrap_or(DEFAULT_DENSITY)
+    }
+
+    /// The volume of a length of filament, in mm^3.
+    #[must_use]
+    pub fn volume(&self, tool: u8, length: f64) -> f64 {
+        let radius = self.diameter(tool) / 2_f64;
+        PI * radius * radius * length
+    }
+
+    /// The mass of a length of filament, in g.
+    #[must_use]
+    pub fn mass(&self, tool: u8, length: f64) -> f64 {
+        self.volume(tool, length) / 1000_f64 * self.density(tool)
+    }
+
+    /// The cost of a length of filament, `None` if the price is not known.
+    #[must_use]
+    pub fn cost(&self, tool: u8, length: f64) -> Option<f64> {
+        per_tool(&self.cost, tool).map(|cost| self.mass(tool, length) / 1000_f64 * cost)
+    }
+}
+
+fn per_tool(values: &[f64], tool: u8) -> Option<f64> {
+    values
+        .get(usize::from(tool))
+        .or_else(|| values.last())
+        .copied()
+}
+
+/// The slicer's own totals, for comparison.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SlicerTotals {
+    /// "filament used [mm]", summed over tools.
+    pub length: Option<f64>,
+    /// "filament used [g]", summed over tools.
+    pub mass: Option<f64>,
+    /// "filament cost", summed over tools.
+    pub cost: Option<f64>,
+}
+
+impl SlicerTotals {
+    /// Reads a slicer total, unknown keys are ignored.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let sum = || {
+            value
+                .split(',')
+                .map(|v| v.trim().parse::<f64>().ok())
+                .sum::<Option<f64>>()
+        };
+        match key {
+            "filament used [mm]" => self.length = sum(),
+            "filament used [g]" => self.mass = sum(),
+            "filament cost" => self.cost = sum(),
+            _ => {}
+        }
+    }
+}
+
+/// The filament used by a print.
+///
+/// ```rust
+/// use gcode_nom::command::Command;
+/// use gcode_nom::filament::Usage;
+///
+/// let usage = ["M83", "M486 S0", "G1 X10 E5", "G1 E-1", "M486 S1", "G1 E1", "G1 X0 E3"]
+///     .iter()
+///     .map(|line| Command::parse_line(line).unwrap().1)
+///     .collect::<Usage>();
+///
+/// assert_eq!(usage.total(), 8.0);
+/// assert_eq!(usage.lengths[&(Some(1), 0)], 3.0);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    /// Length of filament used in mm, by object and tool.
+    ///
+    /// The object is `None` before the first M486.
+    pub lengths: BTreeMap<(Option<i128>, u8), f64>,
+    /// The objects of the print, and their names.
+    pub objects: ObjectTable,
+    /// The properties of the filament.
+    pub filament: Filament,
+    /// The slicer's own totals.
+    pub slicer: SlicerTotals,
+}
+
+impl Usage {
+    /// Reads a "key = value" pair, from a comment or from metadata.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.filament.set(key, value);
+        self.slicer.set(key, value);
+    }
+
+    /// The total length of filament used, in mm.
+    #[must_use]
+    pub fn total(&self) -> f64 {
+        self.lengths.values().sum()
+    }
+
+    /// The length of filament used by each tool, in mm.
+    #[must_use]
+    pub fn by_tool(&self) -> BTreeMap<u8, f64> {
+        let mut tools = BTreeMap::new();
+        for ((_, tool), length) in &self.lengths {
+            *tools.entry(*tool).or_default() += length;
+        }
+        tools
+    }
+
+    /// The total mass of filament used, in g.
+    #[must_use]
+    pub fn mass(&self) -> f64 {
+        self.lengths
+            .iter()
+            .map(|((_, tool), length)| self.filament.mass(*tool, *length))
+            .sum()
+    }
+}
+
+/// Follows the E axis as commands are replayed.
+#[derive(Clone, Debug, Default)]
+pub struct UsageCounter {
+    usage: Usage,
+    tool: u8,
+    // The position of the E axis, as given by the file.
+    e: f64,
+    // Set by M83, or G91.
+    relative_e: bool,
+    // Filament pulled back, and not yet pushed out again.
+    retracted: f64,
+}
+
+impl UsageCounter {
+    /// Replays a single command.
+    pub fn push(&mut self, command: &Command) {
+        self.usage.objects.push(command);
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                if let Some(e) = params.iter().find_map(|param| match param {
+                    PosVal::E(e) => Some(*e),
+                    _ => None,
+                }) {
+                    self.extrude(e);
+                }
+            }
+            Command::G2(ArcForm::IJ(params) | ArcForm::R(params))
+            | Command::G3(ArcForm::IJ(params) | ArcForm::R(params)) => {
+                if let Some(e) = params.iter().find_map(|param| match param {
+                    ArcVal::E(e) => Some(*e),
+                    _ => None,
+                }) {
+                    self.extrude(e);
+                }
+            }
+            Command::G90 | Command::MDrop(82) => self.relative_e = false,
+            Command::G91 | Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                for param in params {
+                    if let PosVal::E(e) = param {
+                        self.e = *e;
+                    }
+                }
+            }
+            Command::T(tool) => self.tool = *tool,
+            Command::Comment(text) => {
+                if let Some((key, value)) = text.split_once('=') {
+                    self.usage.set(key.trim(), value.trim());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the filament used.
+    #[must_use]
+    pub fn finish(self) -> Usage {
+        self.usage
+    }
+
+    fn extrude(&mut self, e: f64) {
+        let delta = if self.relative_e { e } else { e - self.e };
+        self.e = if self.relative_e { self.e + e } else { e };
+
+        if delta < 0_f64 {
+            self.retracted -= delta;
+            return;
+        }
+        // Pushing a retraction back out uses no more filament.
+        let used = (delta - self.retracted).max(0_f64);
+        self.retracted = (self.retracted - delta).max(0_f64);
+        if used > 0_f64 {
+            *self
+                .usage
+                .lengths
+                .entry((self.usage.objects.current(), self.tool))
+                .or_default() += used;
+        }
+    }
+}
+
+impl FromIterator<Command> for Usage {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Command>,
+    {
+        let mut counter = UsageCounter::default();
+        for command in iter {
+            counter.push(&command);
+        }
+        counter.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn usage(lines: &[&str]) -> Usage {
+        lines
+            .iter()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn retractions() {
+        // Absolute extrusion, with a retraction and a reset.
+        let usage = usage(&[
+            "M82",
+            "G92 E0",
+            "G1 X10 E4",
+            "G1 E3.2",
+            "G1 E4",
+            "G1 X20 E6",
+            "G92 E0",
+            "G1 X30 E1",
+        ]);
+        assert!((usage.total() - 7_f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tools() {
+        let usage = usage(&[
+            "M83",
+            "T0",
+            "G1 X10 E2",
+            "T1",
+            "G1 X20 E3",
+            "; filament_diameter = 1.75,2.85",
+            "; filament_density = 1.24,1.27",
+            "; filament_cost = 25,30",
+            "; filament used [mm] = 2.00, 3.00",
+        ]);
+        assert_eq!(usage.by_tool(), BTreeMap::from([(0, 2_f64), (1, 3_f64)]));
+        assert_eq!(usage.filament.diameter, vec![1.75, 2.85]);
+        // Tools without their own value use the last value.
+        assert!((usage.filament.density(3) - 1.27).abs() < 1e-9);
+        assert_eq!(usage.slicer.length, Some(5_f64));
+
+        // 1.75mm diameter, 1000mm long is 2.405cm^3, or 2.983g.
+        let mass = usage.filament.mass(0, 1000_f64);
+        assert!((mass - 2.982_549_5).abs() < 1e-6, "{mass}");
+        let cost = usage.filament.cost(0, 1000_f64).unwrap();
+        assert!((cost - mass * 0.025).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slicer() {
+        // Agrees with PrusaSlicer's "filament used [mm] = 2944.04".
+        let input = include_str!("../../assets/II 2x4_0.15mm_PLA_MK3S_1h17m.gcode");
+        let usage = input
+            .lines()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<Usage>();
+        let slicer = usage.slicer.length.unwrap();
+        assert!((usage.total() - slicer).abs() < 1_f64, "{}", usage.total());
+        let mass = usage.slicer.mass.unwrap();
+        assert!((usage.mass() - mass).abs() < 0.01, "{}", usage.mass());
+    }
+}
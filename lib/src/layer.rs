@@ -0,0 +1,575 @@
+//! Detects the layers of a print.
+//!
+//! Slicers such as PrusaSlicer mark the start of each layer with a pair of
+//! comments :-
+//!
+//! ```text
+//! ;LAYER_CHANGE
+//! ;Z:0.2
+//! ```
+//!
+//! Cura marks each layer with `;LAYER:n` instead, and its height is taken
+//! from the next extrusion. PrusaSlicer follows `;Z:` with the thickness of
+//! the layer, as `;HEIGHT:0.2`.
+//!
+//! Files without these markers are split into layers whenever the head
+//! extrudes at a new height. Travel moves are ignored, so a Z-hop does not
+//! start a new layer.
+
+use core::f64::consts::TAU;
+use core::ops::Range;
+use std::collections::BTreeSet;
+
+use crate::Plane;
+use crate::PositionMode;
+use crate::annotation::Annotation;
+use crate::annotation::classify;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::arc_turns;
+use crate::command::Command;
+use crate::compute_arc_to;
+use crate::objects::ObjectTable;
+use crate::params::head::PosVal;
+
+/// Heights closer than this are considered to be the same layer.
+const Z_TOLERANCE: f64 = 1e-6;
+
+/// A single layer of the print.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layer {
+    /// Layers are numbered from 0.
+    pub index: usize,
+    /// The height of the layer.
+    pub z: f64,
+}
+
+/// Selects which layers are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LayerFilter {
+    /// Every command, including those before the first layer.
+    #[default]
+    All,
+    /// A single layer, by index.
+    Index(usize),
+    /// Layers with a height between min and max (inclusive).
+    ZRange {
+        /// Lowest layer height.
+        min: f64,
+        /// Highest layer height.
+        max: f64,
+    },
+}
+
+impl LayerFilter {
+    /// Returns true if the layer passes the filter.
+    ///
+    /// `None` represents commands before the first layer.
+    #[must_use]
+    pub fn contains(&self, layer: Option<Layer>) -> bool {
+        match (self, layer) {
+            (Self::All, _) => true,
+            (Self::Index(index), Some(layer)) => layer.index == *index,
+            (Self::ZRange { min, max }, Some(layer)) => {
+                *min - Z_TOLERANCE <= layer.z && layer.z <= *max + Z_TOLERANCE
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+/// Follows the layer changes as commands are replayed.
+///
+/// ```rust
+/// use gcode_nom::layer::LayerTracker;
+///
+/// let mut tracker = LayerTracker::default();
+/// assert!(tracker.extrude(0.2));
+/// // Still on the first layer.
+/// assert!(!tracker.extrude(0.2));
+/// assert!(tracker.extrude(0.4));
+/// assert_eq!(tracker.current().unwrap().index, 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LayerTracker {
+    current: Option<Layer>,
+    // Once a marker comment is seen, only markers start new layers.
+    has_markers: bool,
+    // A ;LAYER_CHANGE has been seen, the height is not yet known.
+    pending: bool,
+}
+
+impl LayerTracker {
+    /// The layer being printed, `None` before the first layer.
+    #[must_use]
+    pub const fn current(&self) -> Option<Layer> {
+        self.current
+    }
+
+    /// Inspects the text of a comment (without the leading ';').
+    ///
+    /// Returns true if a new layer starts.
+    pub fn comment(&mut self, text: &str) -> bool {
+        match classify(text) {
+            Some(Annotation::LayerChange | Annotation::Layer(_)) => {
+                self.has_markers = true;
+                self.pending = true;
+                false
+            }
+            Some(Annotation::Z(z)) if self.pending => {
+                self.pending = false;
+                self.start(z)
+            }
+            _ => false,
+        }
+    }
+
+    /// Records an extruding move which ends at height `z`.
+    ///
+    /// Returns true if a new layer starts.
+    pub fn extrude(&mut self, z: f64) -> bool {
+        if self.has_markers {
+            // A marker without a height, the layer starts here.
+            if self.pending {
+                self.pending = false;
+                return self.start(z);
+            }
+            false
+        } else {
+            self.start(z)
+        }
+    }
+
+    // Moves to a new layer, unless already at that height.
+    //
+    // When a file has markers, extrusions made before the first marker
+    // (a purge line, say) are at the height of the first layer, and are
+    // merged into it.
+    fn start(&mut self, z: f64) -> bool {
+        match self.current {
+            Some(layer) if (layer.z - z).abs() < Z_TOLERANCE => false,
+            Some(Layer { index, .. }) => {
+                self.current = Some(Layer {
+                    index: index + 1,
+                    z,
+                });
+                true
+            }
+            None => {
+                self.current = Some(Layer { index: 0, z });
+                true
+            }
+        }
+    }
+}
+
+/// A layer, and what happens while it is printed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerStats {
+    /// The index and height of the layer.
+    pub layer: Layer,
+    /// The thickness of the layer, from `;HEIGHT:` or the step from the
+    /// layer below.
+    pub height: f64,
+    /// The indices of the layer's commands.
+    ///
+    /// A layer starts at its marker comment, or, without markers, at its
+    /// first extrusion.
+    pub commands: Range<usize>,
+    /// The length of the extruding moves, in mm.
+    pub extrusion: f64,
+    /// The length of the travel moves, in mm.
+    pub travel: f64,
+    /// The objects extruded on this layer, by M486 id.
+    pub objects: BTreeSet<i128>,
+}
+
+/// Splits a print into layers, as commands are replayed.
+///
+/// ```rust
+/// use gcode_nom::command::Command;
+/// use gcode_nom::layer::LayerSegmenter;
+///
+/// let mut segmenter = LayerSegmenter::default();
+/// for line in [";LAYER:0", "G0 Z0.3", "G1 X10 E1", ";LAYER:1", "G0 Z0.5", "G1 X0 E2"] {
+///     segmenter.push(&Command::parse_line(line).unwrap().1);
+/// }
+/// let layers = segmenter.finish();
+/// assert_eq!(layers.len(), 2);
+/// assert_eq!(layers[1].commands, 3..6);
+/// assert_eq!(layers[1].extrusion, 10.0);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LayerSegmenter {
+    tracker: LayerTracker,
+    objects: ObjectTable,
+    layers: Vec<LayerStats>,
+    // The index of the next command.
+    index: usize,
+    // A marker comment, where the next layer starts, once its height is known.
+    marker: Option<usize>,
+    // Travel since the marker, belonging to the next layer.
+    marker_travel: f64,
+    // The layer's ;HEIGHT: has been seen.
+    has_height: bool,
+    // The position of the head, in the coordinates of the file.
+    position: [f64; 3],
+    // The position of the E axis, as given by the file.
+    e: f64,
+    position_mode: PositionMode,
+    // Set by M83, or G91.
+    relative_e: bool,
+    // The plane of G2/G3 arcs.
+    plane: Plane,
+}
+
+impl LayerSegmenter {
+    /// Replays a single command.
+    pub fn push(&mut self, command: &Command) {
+        self.objects.push(command);
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                let start = self.position;
+                let mut extruding = false;
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.set_axis(0, *val),
+                        PosVal::Y(val) => self.set_axis(1, *val),
+                        PosVal::Z(val) => self.set_axis(2, *val),
+                        PosVal::E(val) => extruding = self.extrude(*val),
+                        _ => {}
+                    }
+                }
+                let length = distance(start, self.position);
+                self.add_move(length, extruding);
+            }
+            Command::G2(form) => self.arc(form, true),
+            Command::G3(form) => self.arc(form, false),
+            Command::G17 => self.plane = Plane::XY,
+            Command::G18 => self.plane = Plane::ZX,
+            Command::G19 => self.plane = Plane::YZ,
+            Command::G90 => {
+                self.position_mode = PositionMode::Absolute;
+                self.relative_e = false;
+            }
+            Command::G91 => {
+                self.position_mode = PositionMode::Relative;
+                self.relative_e = true;
+            }
+            Command::MDrop(82) => self.relative_e = false,
+            Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.position[0] = *val,
+                        PosVal::Y(val) => self.position[1] = *val,
+                        PosVal::Z(val) => self.position[2] = *val,
+                        PosVal::E(val) => self.e = *val,
+                        _ => {}
+                    }
+                }
+            }
+            Command::Comment(text) => {
+                let annotation = classify(text);
+                if matches!(
+                    annotation,
+                    Some(Annotation::LayerChange | Annotation::Layer(_))
+                ) {
+                    self.end_marker();
+                    self.marker = Some(self.index);
+                }
+                if self.tracker.comment(text) {
+                    self.start_layer();
+                } else if !self.tracker.pending {
+                    // The marker repeats the height of the current layer.
+                    self.end_marker();
+                }
+                if !self.has_height
+                    && let Some(Annotation::Height(height)) = annotation
+                    && let Some(last) = self.layers.last_mut()
+                {
+                    self.has_height = true;
+                    last.height = height;
+                }
+            }
+            _ => {}
+        }
+        self.index += 1;
+        if let Some(last) = self.layers.last_mut() {
+            last.commands.end = self.index;
+        }
+    }
+
+    /// Returns the layers, in the order printed.
+    #[must_use]
+    pub fn finish(self) -> Vec<LayerStats> {
+        self.layers
+    }
+
+    fn set_axis(&mut self, axis: usize, val: f64) {
+        self.position[axis] = match self.position_mode {
+            PositionMode::Absolute => val,
+            PositionMode::Relative => self.position[axis] + val,
+        };
+    }
+
+    // Returns true if filament is pushed out.
+    fn extrude(&mut self, e: f64) -> bool {
+        let delta = if self.relative_e { e } else { e - self.e };
+        self.e = if self.relative_e { self.e + e } else { e };
+        delta > 0_f64
+    }
+
+    fn arc(&mut self, form: &ArcForm, clockwise: bool) {
+        let start = self.position;
+        let mut radius = None::<f64>;
+        let mut extruding = false;
+        let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+        for param in params {
+            match param {
+                ArcVal::X(val) => self.set_axis(0, *val),
+                ArcVal::Y(val) => self.set_axis(1, *val),
+                ArcVal::Z(val) => self.set_axis(2, *val),
+                ArcVal::E(val) => extruding = self.extrude(*val),
+                ArcVal::R(val) => radius = Some(*val),
+                _ => {}
+            }
+        }
+        let end = self.position;
+        // Measured in the plane of the arc.
+        let [a, b, _] = self.plane.axes();
+        let length = match (form, radius) {
+            (ArcForm::IJ(_), _) => {
+                let arc = compute_arc_to((start[a], start[b]), (end[a], end[b]), form, self.plane);
+                arc.radius * arc.sweep(clockwise).abs()
+            }
+            (ArcForm::R(_), Some(r)) => {
+                let chord = (end[a] - start[a]).hypot(end[b] - start[b]);
+                let angle = 2_f64 * (chord / (2_f64 * r.abs())).min(1_f64).asin();
+                // A negative radius takes the long way round.
+                let angle = if r < 0_f64 { TAU - angle } else { angle };
+                r.abs() * arc_turns(form).mul_add(TAU, angle)
+            }
+            (ArcForm::R(_), None) => distance(start, end),
+        };
+        self.add_move(length, extruding);
+    }
+
+    fn add_move(&mut self, length: f64, extruding: bool) {
+        if extruding {
+            if self.tracker.extrude(self.position[2]) {
+                self.start_layer();
+            } else if !self.tracker.pending {
+                self.end_marker();
+            }
+        }
+        if !extruding && self.marker.is_some() {
+            self.marker_travel += length;
+            return;
+        }
+        let current = self.objects.current();
+        if let Some(last) = self.layers.last_mut() {
+            if extruding {
+                last.extrusion += length;
+                if let Some(id) = current.filter(|id| *id >= 0) {
+                    last.objects.insert(id);
+                }
+            } else {
+                last.travel += length;
+            }
+        }
+    }
+
+    // Hands the travel since a marker back to the current layer.
+    fn end_marker(&mut self) {
+        if self.marker.take().is_some()
+            && let Some(last) = self.layers.last_mut()
+        {
+            last.travel += self.marker_travel;
+        }
+        self.marker_travel = 0_f64;
+    }
+
+    // The tracker has moved to a new layer.
+    fn start_layer(&mut self) {
+        let Some(layer) = self.tracker.current() else {
+            return;
+        };
+        let start = self.marker.take().unwrap_or(self.index);
+        let height = self
+            .layers
+            .last()
+            .map_or(layer.z, |previous| layer.z - previous.layer.z);
+        if let Some(previous) = self.layers.last_mut() {
+            previous.commands.end = start;
+        }
+        self.layers.push(LayerStats {
+            layer,
+            height,
+            commands: start..start,
+            extrusion: 0_f64,
+            travel: core::mem::take(&mut self.marker_travel),
+            objects: BTreeSet::new(),
+        });
+        self.has_height = false;
+    }
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (b[0] - a[0]).hypot(b[1] - a[1]).hypot(b[2] - a[2])
+}
+
+/// Splits a sequence of commands into layers.
+pub fn segment<'a, I>(commands: I) -> Vec<LayerStats>
+where
+    I: IntoIterator<Item = &'a Command>,
+{
+    let mut segmenter = LayerSegmenter::default();
+    for command in commands {
+        segmenter.push(command);
+    }
+    segmenter.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn markers() {
+        let mut tracker = LayerTracker::default();
+        // A purge line before the first marker.
+        assert!(tracker.extrude(0.2));
+        assert!(!tracker.comment("LAYER_CHANGE"));
+        assert!(!tracker.comment("Z:0.2"));
+        assert_eq!(tracker.current(), Some(Layer { index: 0, z: 0.2 }));
+
+        assert!(!tracker.comment("LAYER_CHANGE"));
+        assert!(tracker.comment("Z:0.4"));
+        assert_eq!(tracker.current(), Some(Layer { index: 1, z: 0.4 }));
+
+        // Ignored, only markers start layers from now on.
+        assert!(!tracker.extrude(0.6));
+        assert!(!tracker.comment("Z:0.6"));
+        assert_eq!(tracker.current(), Some(Layer { index: 1, z: 0.4 }));
+    }
+
+    #[test]
+    fn cura() {
+        let mut tracker = LayerTracker::default();
+        assert!(!tracker.comment("LAYER_COUNT:20"));
+        assert!(!tracker.comment("LAYER:0"));
+        assert!(tracker.extrude(0.3));
+        assert!(!tracker.comment("LAYER:1"));
+        // A Z-hop, travel does not reach the tracker.
+        assert!(tracker.extrude(0.5));
+        assert_eq!(tracker.current(), Some(Layer { index: 1, z: 0.5 }));
+    }
+
+    fn segment_lines(lines: &[&str]) -> Vec<LayerStats> {
+        let commands = lines
+            .iter()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<Vec<_>>();
+        segment(&commands)
+    }
+
+    #[test]
+    fn segments() {
+        let layers = segment_lines(&[
+            "M83",
+            ";LAYER_CHANGE",
+            ";Z:0.2",
+            ";HEIGHT:0.2",
+            "M486 S0",
+            "G1 X10 Y0 Z0.2 E1",
+            "M486 S1",
+            "G1 X10 Y10 E1",
+            "M486 S-1",
+            "G1 X20 Y10 E1",
+            ";LAYER_CHANGE",
+            ";Z:0.35",
+            ";HEIGHT:0.15",
+            "G1 X20 Y20 Z0.35",
+            ";HEIGHT:0.3",
+            "G3 X40 Y20 I10 J0 E1",
+            "M107",
+        ]);
+        assert_eq!(layers.len(), 2);
+
+        assert_eq!(layers[0].layer, Layer { index: 0, z: 0.2 });
+        assert_eq!(layers[0].commands, 1..10);
+        assert_eq!(layers[0].objects, BTreeSet::from([0, 1]));
+        assert!((layers[0].height - 0.2).abs() < 1e-9);
+        assert!((layers[0].travel - 0_f64).abs() < 1e-9);
+
+        // Only the first ;HEIGHT: is the thickness of the layer.
+        assert!((layers[1].height - 0.15).abs() < 1e-9);
+        assert_eq!(layers[1].commands, 10..17);
+        assert!(layers[1].objects.is_empty());
+        // The travel up to the next layer.
+        assert!((layers[1].travel - 10_f64.hypot(0.15)).abs() < 1e-9);
+        // A half circle, of radius 10.
+        let half = 10_f64 * core::f64::consts::PI;
+        assert!((layers[1].extrusion - half).abs() < 1e-9);
+    }
+
+    #[test]
+    fn heuristic() {
+        // No markers, so layers start with the first extrusion at a new height.
+        let layers = segment_lines(&[
+            "G90",
+            "M82",
+            "G1 Z0.3",
+            "G1 X10 E1",
+            // A Z-hop.
+            "G1 Z1",
+            "G1 X0 Z0.3",
+            "G1 Z0.6",
+            "G1 X10 E0.5",
+            "G1 X20 E2",
+        ]);
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].commands, 3..8);
+        assert!((layers[0].height - 0.3).abs() < 1e-9);
+        // The retraction is not an extrusion.
+        let travel = 0.7 + 10_f64.hypot(0.7) + 0.3 + 10_f64;
+        assert!((layers[0].travel - travel).abs() < 1e-9);
+        assert_eq!(layers[1].commands, 8..9);
+        assert!((layers[1].height - 0.3).abs() < 1e-9);
+        assert!((layers[1].extrusion - 10_f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slicer_file() {
+        let input = include_str!("../../assets/II 2x4_0.15mm_PLA_MK3S_1h17m.gcode");
+        let commands = input
+            .lines()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<Vec<_>>();
+        let layers = segment(&commands);
+        let markers = input
+            .lines()
+            .filter(|line| *line == ";LAYER_CHANGE")
+            .count();
+        assert_eq!(layers.len(), markers);
+        // The layers follow one another, without gaps.
+        for pair in layers.windows(2) {
+            assert_eq!(pair[0].commands.end, pair[1].commands.start);
+            assert!((pair[0].layer.z + pair[1].height - pair[1].layer.z).abs() < 1e-4);
+        }
+        assert_eq!(layers.last().unwrap().commands.end, commands.len());
+        assert!(layers.iter().all(|layer| layer.objects.len() <= 2));
+    }
+
+    #[test]
+    fn filter() {
+        let layer = Some(Layer { index: 3, z: 0.8 });
+        assert!(LayerFilter::All.contains(None));
+        assert!(!LayerFilter::Index(3).contains(None));
+        assert!(LayerFilter::Index(3).contains(layer));
+        assert!(!LayerFilter::Index(2).contains(layer));
+        assert!(LayerFilter::ZRange { min: 0.8, max: 1.0 }.contains(layer));
+        assert!(!LayerFilter::ZRange { min: 0.2, max: 0.6 }.contains(layer));
+    }
+}
@@ -3,27 +3,56 @@
 #![allow(clippy::many_single_char_names)]
 
 use core::f64;
+use core::f64::consts::TAU;
 
 use crate::arc::ArcVal;
 use crate::arc::Form as ArcForm;
 
-/// G2/G3 Arc commands.
-/// Used in step size calculations
-pub static MM_PER_ARC_SEGMENT: f64 = 1_f64;
-
+/// Typed slicer annotations, from comments.
+pub mod annotation;
+/// Compresses G1 moves into G2/G3 arcs.
+pub mod arc_fit;
 /// Streaming for binary gcode files
 pub mod binary;
+/// Checks that a print fits the machine.
+pub mod bounds;
+/// Removes objects from a multipart print.
+pub mod cancel;
 /// Parsing rules for gcode commands
 pub mod command;
 mod double;
+/// Estimates the print time.
+pub mod estimate;
+/// Sums the filament used by a print.
+pub mod filament;
+/// Detects the layers of a print.
+pub mod layer;
+/// The objects of a multipart print.
+pub mod objects;
 /// Parsing rules for G0/G1 commands
 pub mod params;
+/// Inserts a filament change, or a pause, at a layer.
+pub mod pause;
+/// Resumes a failed print, from a given line.
+pub mod resume;
+/// Splits arcs into segments, for rendering.
+pub mod tessellation;
+/// Moves, rotates, scales and mirrors a print.
+pub mod transform;
 
 /// Parsing rules for G2/G3 arc commands
 pub mod arc;
 
+/// G2/G3 Arc commands.
+/// Used in step size calculations
+///
+/// The firmware splits arcs into segments of this length, as modelled by
+/// the print time estimator.
+#[deprecated(note = "renderers split arcs by chord tolerance, see `tessellation::Tessellation`")]
+pub static MM_PER_ARC_SEGMENT: f64 = 1_f64;
+
 /// Absolute or Relative positioning
-#[derive(Default, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
 pub enum PositionMode {
     /// As per spec `Positionmode::Absolute` is the default
     /// <https://marlinfw.org/docs/gcode/G090.html>
@@ -33,12 +62,50 @@ pub enum PositionMode {
     Relative,
 }
 
+/// The plane in which G2/G3 arcs are drawn, selected by G17, G18 or G19.
+///
+/// <https://marlinfw.org/docs/gcode/G017-G019.html>
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+pub enum Plane {
+    /// G17, the center is offset by I and J.
+    #[default]
+    XY,
+    /// G18, the center is offset by K and I.
+    ZX,
+    /// G19, the center is offset by J and K.
+    YZ,
+}
+
+impl Plane {
+    /// The indices of the plane's two axes, then of the axis normal to it.
+    ///
+    /// Seen from the normal axis, the first axis turns anticlockwise
+    /// into the second, so G2 and G3 keep their direction in every plane.
+    ///
+    /// ```rust
+    /// use gcode_nom::Plane;
+    ///
+    /// // G18: Z then X, along Y.
+    /// assert_eq!(Plane::ZX.axes(), [2, 0, 1]);
+    /// ```
+    #[must_use]
+    pub const fn axes(self) -> [usize; 3] {
+        match self {
+            Self::XY => [0, 1, 2],
+            Self::ZX => [2, 0, 1],
+            Self::YZ => [1, 2, 0],
+        }
+    }
+}
+
 /// Returns values used to render an ARC
 ///
 /// input state is current position and the raw param values
 /// extracted from command.
 ///
+/// Built by [`compute_arc`] or [`compute_arc_to`], fields may be added.
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct ArcParams {
     /// The center of the arc
     pub center: (f64, f64),
@@ -48,6 +115,51 @@ pub struct ArcParams {
     pub theta_start: f64,
     /// The end angle of the arc in radians
     pub theta_end: f64,
+    /// Full turns added to the arc, from `P<count>`.
+    pub turns: f64,
+}
+
+impl ArcParams {
+    /// The angle swept by the arc, in radians.
+    ///
+    /// Clockwise arcs sweep a negative angle. An arc which ends where it
+    /// starts is a full circle, and each of the `turns` adds another.
+    #[must_use]
+    pub fn sweep(&self, clockwise: bool) -> f64 {
+        let mut sweep = if clockwise {
+            (self.theta_start - self.theta_end).rem_euclid(TAU)
+        } else {
+            (self.theta_end - self.theta_start).rem_euclid(TAU)
+        };
+        if sweep < f64::EPSILON {
+            sweep = TAU;
+        }
+        sweep = self.turns.mul_add(TAU, sweep);
+        if clockwise { -sweep } else { sweep }
+    }
+}
+
+/// Returns the position at the end of an arc.
+///
+/// Axes missing from the command keep their current value, and in
+/// relative mode the values given are offsets from the current position.
+#[must_use]
+pub fn arc_end(current: [f64; 3], form: &ArcForm, position_mode: PositionMode) -> [f64; 3] {
+    let (ArcForm::IJ(arc_values) | ArcForm::R(arc_values)) = form;
+    let mut end = current;
+    for val in arc_values {
+        let (axis, val) = match val {
+            ArcVal::X(val) => (0, *val),
+            ArcVal::Y(val) => (1, *val),
+            ArcVal::Z(val) => (2, *val),
+            _ => continue,
+        };
+        end[axis] = match position_mode {
+            PositionMode::Absolute => val,
+            PositionMode::Relative => current[axis] + val,
+        };
+    }
+    end
 }
 
 #[must_use] // // pub fn compute_arc(&payload) ->  ( origin, radius, theta_start, theta_end)
@@ -55,12 +167,28 @@ pub struct ArcParams {
 ///
 /// `ArcParams` contains the values in a form which can be rendered to a OBJ/SVG file.
 pub fn compute_arc(current_x: f64, current_y: f64, form: &ArcForm) -> ArcParams {
-    // Unspecified relative offsets default to zero.
-    let mut i: f64 = 0_f64;
-    let mut j: f64 = 0_f64;
+    let [x, y, _] = arc_end([current_x, current_y, 0_f64], form, PositionMode::Absolute);
+    compute_arc_to((current_x, current_y), (x, y), form, Plane::XY)
+}
 
-    let mut x: f64 = f64::NAN;
-    let mut y: f64 = f64::NAN;
+#[must_use]
+/// Computes the parameters of an arc, given its start and end points.
+///
+/// Unlike [`compute_arc`] the end point is not read from the arc form, so
+/// the caller may resolve relative moves first. The points, and the
+/// parameters returned, are measured along the axes of the plane.
+pub fn compute_arc_to(
+    start: (f64, f64),
+    end: (f64, f64),
+    form: &ArcForm,
+    plane: Plane,
+) -> ArcParams {
+    let (current_x, current_y) = start;
+    let (x, y) = end;
+
+    // Unspecified relative offsets default to zero.
+    let i: f64;
+    let j: f64;
 
     let radius: f64;
     let center: (f64, f64);
@@ -70,19 +198,18 @@ pub fn compute_arc(current_x: f64, current_y: f64, form: &ArcForm) -> ArcParams
     match form {
         ArcForm::IJ(arc_values) => {
             // I and J form
+            let mut offset = [0_f64; 3];
             for val in arc_values {
                 match val {
-                    ArcVal::X(val) => x = *val,
-                    ArcVal::Y(val) => y = *val,
-                    ArcVal::I(val) => i = *val,
-                    ArcVal::J(val) => j = *val,
+                    ArcVal::I(val) => offset[0] = *val,
+                    ArcVal::J(val) => offset[1] = *val,
+                    ArcVal::K(val) => offset[2] = *val,
                     _ => {}
                 }
             }
-
-            // x and y MUST be extracted from command
-            debug_assert!(x.is_finite());
-            debug_assert!(y.is_finite());
+            let [first, second, _] = plane.axes();
+            i = offset[first];
+            j = offset[second];
 
             radius = i.hypot(j);
             center = (current_x + i, current_y + j);
@@ -111,17 +238,10 @@ pub fn compute_arc(current_x: f64, current_y: f64, form: &ArcForm) -> ArcParams
             // R form
 
             for val in arc_values {
-                match val {
-                    ArcVal::X(val) => x = *val,
-                    ArcVal::Y(val) => y = *val,
-                    ArcVal::R(val) => radius = *val,
-                    _ => {
-                        // Ignored params
-                    }
+                if let ArcVal::R(val) = val {
+                    radius = *val;
                 }
             }
-            debug_assert!(x.is_finite());
-            debug_assert!(y.is_finite());
             // r Must be specified from command.
             debug_assert!(radius.is_finite());
             // radius = r;
@@ -138,9 +258,71 @@ pub fn compute_arc(current_x: f64, current_y: f64, form: &ArcForm) -> ArcParams
         radius,
         theta_start,
         theta_end,
+        turns: arc_turns(form),
     }
 }
 
+/// Computes the parameters of an R form arc, given its start and end
+/// points.
+///
+/// Of the two circles of radius R through both points, a positive R takes
+/// the shorter way round and a negative R the longer. Returns `None` when
+/// R is missing, or the points are the same, as the circle is unknown.
+pub(crate) fn compute_arc_radius(
+    start: (f64, f64),
+    end: (f64, f64),
+    form: &ArcForm,
+    clockwise: bool,
+) -> Option<ArcParams> {
+    let (ArcForm::IJ(arc_values) | ArcForm::R(arc_values)) = form;
+    let r = arc_values.iter().find_map(|val| match val {
+        ArcVal::R(val) => Some(*val),
+        _ => None,
+    })?;
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let chord = dx.hypot(dy);
+    if chord == 0_f64 || !r.is_finite() {
+        return None;
+    }
+    // The distance from the middle of the chord to the center, to its left
+    // for a counter clockwise arc which takes the shorter way round.
+    let radius = r.abs().max(chord / 2_f64);
+    let mut offset = radius.mul_add(radius, -(chord * chord / 4_f64)).sqrt() / chord;
+    if clockwise != (r < 0_f64) {
+        offset = -offset;
+    }
+    let center = (
+        offset.mul_add(-dy, start.0 + dx / 2_f64),
+        offset.mul_add(dx, start.1 + dy / 2_f64),
+    );
+    let theta = |(x, y): (f64, f64)| (y - center.1).atan2(x - center.0).rem_euclid(TAU);
+    Some(ArcParams {
+        center,
+        radius,
+        theta_start: theta(start),
+        theta_end: theta(end),
+        turns: arc_turns(form),
+    })
+}
+
+/// The most full turns `P<count>` may add to an arc.
+const MAX_ARC_TURNS: f64 = 1000_f64;
+
+/// The full turns added to an arc by `P<count>`.
+///
+/// P is a count, fractions and negative values are ignored. Larger counts
+/// are clamped to [`MAX_ARC_TURNS`].
+pub(crate) fn arc_turns(form: &ArcForm) -> f64 {
+    let (ArcForm::IJ(arc_values) | ArcForm::R(arc_values)) = form;
+    arc_values
+        .iter()
+        .find_map(|val| match val {
+            ArcVal::P(count) => Some(count.floor().clamp(0_f64, MAX_ARC_TURNS)),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
 // This illustrates a counter clockwise arc, starting at [9, 6]. It can be generated either by G3 X2 Y7 I-4 J-3 or G3 X2 Y7 R5
 //
 // As show in this (image)[<../images/G3fog.png>]
@@ -202,6 +384,92 @@ mod tests {
         assert_eq!(round_to_two_decimals(arc.theta_end.to_degrees()), 270_f64);
     }
 
+    #[test]
+    fn planes() {
+        // The same quarter circle, centered on the origin, in each plane.
+        let form = ArcForm::IJ([ArcVal::I(-10.0), ArcVal::J(-10.0), ArcVal::K(-10.0)].into());
+        for plane in [Plane::XY, Plane::ZX, Plane::YZ] {
+            let arc = compute_arc_to((10.0, 0.0), (0.0, 10.0), &form, plane);
+            let (a, b) = arc.center;
+            assert!(a.abs() < 1e-12 && (b + 10.0).abs() < 1e-12, "{plane:?}");
+        }
+        let form = ArcForm::IJ([ArcVal::J(-10.0), ArcVal::K(5.0)].into());
+        // G18, the offset is K then I.
+        assert_eq!(
+            compute_arc_to((0.0, 0.0), (10.0, 0.0), &form, Plane::ZX).center,
+            (5.0, 0.0)
+        );
+        // G19, the offset is J then K.
+        assert_eq!(
+            compute_arc_to((0.0, 0.0), (10.0, 0.0), &form, Plane::YZ).center,
+            (-10.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn full_circle() {
+        let circle = |vals: &[ArcVal]| {
+            let mut form = vec![ArcVal::X(10.0), ArcVal::Y(0.0), ArcVal::I(-10.0)];
+            form.extend_from_slice(vals);
+            compute_arc(10.0, 0.0, &ArcForm::IJ(form.into_iter().collect()))
+        };
+        // The end point is the start point.
+        let arc = circle(&[]);
+        assert!((arc.sweep(false) - TAU).abs() < 1e-12);
+        assert!((arc.sweep(true) + TAU).abs() < 1e-12);
+        // Two more turns.
+        let arc = circle(&[ArcVal::P(2.0)]);
+        assert!(3.0_f64.mul_add(-TAU, arc.sweep(false)).abs() < 1e-12);
+        // An absurd count is clamped.
+        let arc = circle(&[ArcVal::P(1e12)]);
+        assert!(
+            (MAX_ARC_TURNS + 1_f64)
+                .mul_add(-TAU, arc.sweep(false))
+                .abs()
+                < 1e-9
+        );
+
+        // A half circle, with a turn added.
+        let arc = compute_arc(
+            10.0,
+            0.0,
+            &ArcForm::IJ(
+                [
+                    ArcVal::X(-10.0),
+                    ArcVal::Y(0.0),
+                    ArcVal::I(-10.0),
+                    ArcVal::P(1.0),
+                ]
+                .into(),
+            ),
+        );
+        assert!(1.5_f64.mul_add(TAU, arc.sweep(true)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn r_form() {
+        let form = |r| ArcForm::R([ArcVal::X(2.0), ArcVal::Y(7.0), ArcVal::R(r)].into());
+        let close =
+            |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9;
+
+        // G3 X2 Y7 R5, from [9, 6], as illustrated above.
+        let arc = compute_arc_radius((9.0, 6.0), (2.0, 7.0), &form(5.0), false).unwrap();
+        assert!(close(arc.center, (5.0, 3.0)));
+        assert!((arc.radius - 5.0).abs() < 1e-12);
+        assert!(arc.sweep(false) > 0_f64 && arc.sweep(false) < f64::consts::PI);
+
+        // Clockwise, or with a negative radius, the other circle is used.
+        let arc = compute_arc_radius((9.0, 6.0), (2.0, 7.0), &form(5.0), true).unwrap();
+        assert!(close(arc.center, (6.0, 10.0)));
+        assert!(arc.sweep(true) > -f64::consts::PI);
+        let arc = compute_arc_radius((9.0, 6.0), (2.0, 7.0), &form(-5.0), false).unwrap();
+        assert!(close(arc.center, (6.0, 10.0)));
+        assert!(arc.sweep(false) > f64::consts::PI);
+
+        // Without a chord, the circle is unknown.
+        assert!(compute_arc_radius((2.0, 7.0), (2.0, 7.0), &form(5.0), false).is_none());
+    }
+
     #[ignore]
     #[test]
     // ignored? - Complex algorithm to be implemented involving solving a par of simultaneous equations
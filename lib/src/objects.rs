@@ -0,0 +1,476 @@
+//! The objects of a multipart print.
+//!
+//! M486 assigns each extrusion to an object, by id. Objects can be named,
+//! cancelled and un-cancelled as the print progresses.
+//!
+//! ```text
+//! M486 S3 A"cube copy 3" ; the 4th object is starting now, named
+//! M486 P3                ; cancel the 4th object
+//! M486 S-1               ; a non-object, purge tower, or other global feature
+//! ```
+//!
+//! Klipper names its objects instead, with `EXCLUDE_OBJECT_DEFINE`,
+//! `EXCLUDE_OBJECT_START` and `EXCLUDE_OBJECT_END`. [`KlipperObjects`]
+//! numbers them in order of appearance, so both fit the same model.
+
+use core::f64::consts::FRAC_PI_2;
+use core::f64::consts::TAU;
+use std::collections::BTreeMap;
+
+use crate::Plane;
+use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::command::Command;
+use crate::compute_arc_to;
+use crate::params::head::PosVal;
+use crate::params::mp::MultiPartVal;
+
+/// An axis aligned box, enclosing the extrusions of an object.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    /// The lowest X, Y and Z.
+    pub min: [f64; 3],
+    /// The highest X, Y and Z.
+    pub max: [f64; 3],
+}
+
+impl BoundingBox {
+    /// A box enclosing a single point.
+    #[must_use]
+    pub const fn point(p: [f64; 3]) -> Self {
+        Self { min: p, max: p }
+    }
+
+    /// Grows the box to enclose a point.
+    pub const fn extend(&mut self, p: [f64; 3]) {
+        let mut i = 0;
+        while i < 3 {
+            self.min[i] = self.min[i].min(p[i]);
+            self.max[i] = self.max[i].max(p[i]);
+            i += 1;
+        }
+    }
+}
+
+/// Assigns M486 style ids to Klipper's named objects.
+///
+/// ```rust
+/// use gcode_nom::command::Command;
+/// use gcode_nom::objects::KlipperObjects;
+/// use gcode_nom::params::mp::MultiPartVal;
+///
+/// let mut klipper = KlipperObjects::default();
+/// let start = Command::ExcludeObjectStart("cube".to_string());
+/// assert_eq!(
+///     klipper.translate(start),
+///     Command::M486(MultiPartVal::S(0, Some("cube".to_string())))
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KlipperObjects {
+    // Indexed by id.
+    names: Vec<String>,
+}
+
+impl KlipperObjects {
+    /// Returns the id of a named object, assigning the next free id to a
+    /// new name.
+    ///
+    /// # Panics
+    ///   When there are more than `i128::MAX` objects.
+    pub fn id(&mut self, name: &str) -> i128 {
+        let index = self
+            .names
+            .iter()
+            .position(|n| n == name)
+            .unwrap_or_else(|| {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            });
+        i128::try_from(index).expect("fewer than i128::MAX objects")
+    }
+
+    /// Translates Klipper's object commands into their M486 equivalent.
+    ///
+    /// `EXCLUDE_OBJECT_START` starts the object, `EXCLUDE_OBJECT_END`
+    /// returns to the non-object (S-1), and `EXCLUDE_OBJECT` cancels the
+    /// object. `EXCLUDE_OBJECT_DEFINE` reserves an id, and is returned
+    /// unchanged, as are all other commands.
+    pub fn translate(&mut self, command: Command) -> Command {
+        match command {
+            Command::ExcludeObjectDefine(definition) => {
+                self.id(&definition.name);
+                Command::ExcludeObjectDefine(definition)
+            }
+            Command::ExcludeObjectStart(name) => {
+                Command::M486(MultiPartVal::S(self.id(&name), Some(name)))
+            }
+            Command::ExcludeObjectEnd(_) => Command::M486(MultiPartVal::S(-1, None)),
+            Command::ExcludeObject(name) => Command::M486(MultiPartVal::P(self.id(&name))),
+            command => command,
+        }
+    }
+}
+
+/// An entry in the object table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Object {
+    /// The M486 id, negative for a non-object such as a purge tower.
+    pub id: i128,
+    /// The name given by M486 A, if any.
+    pub name: Option<String>,
+    /// True if the object was cancelled, and not un-cancelled.
+    pub cancelled: bool,
+    /// Encloses the extrusions of the object, `None` if nothing was printed.
+    pub bbox: Option<BoundingBox>,
+}
+
+impl Object {
+    const fn new(id: i128) -> Self {
+        Self {
+            id,
+            name: None,
+            cancelled: false,
+            bbox: None,
+        }
+    }
+}
+
+/// The objects declared by a command stream, ordered by id.
+///
+/// ```rust
+/// use gcode_nom::command::Command;
+/// use gcode_nom::objects::ObjectTable;
+///
+/// let table = [
+///     "M486 S0 A\"cube\"",
+///     "G1 X10 Y10 Z0.2 E1",
+///     "M486 S1",
+///     "M486 P1",
+/// ]
+/// .iter()
+/// .map(|line| Command::parse_line(line).unwrap().1)
+/// .collect::<ObjectTable>();
+///
+/// let cube = table.get(0).unwrap();
+/// assert_eq!(cube.name.as_deref(), Some("cube"));
+/// assert!(table.get(1).unwrap().cancelled);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjectTable {
+    objects: BTreeMap<i128, Object>,
+    // The object being printed.
+    current: Option<i128>,
+    // The position of the head, in the coordinates of the file.
+    position: [f64; 3],
+    // Set by G92, added to position to give machine coordinates.
+    origin: [f64; 3],
+    position_mode: PositionMode,
+    // The plane of G2/G3 arcs.
+    plane: Plane,
+    klipper: KlipperObjects,
+}
+
+impl ObjectTable {
+    /// Returns the object with this id.
+    #[must_use]
+    pub fn get(&self, id: i128) -> Option<&Object> {
+        self.objects.get(&id)
+    }
+
+    /// Iterates over the objects, ordered by id.
+    pub fn iter(&self) -> impl Iterator<Item = &Object> {
+        self.objects.values()
+    }
+
+    /// The number of objects.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns true if no objects are declared.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// The id of the object being printed.
+    #[must_use]
+    pub const fn current(&self) -> Option<i128> {
+        self.current
+    }
+
+    /// Updates the table with a single command.
+    pub fn push(&mut self, command: &Command) {
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                let start = self.machine_position();
+                let mut extruding = false;
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.set_axis(0, *val),
+                        PosVal::Y(val) => self.set_axis(1, *val),
+                        PosVal::Z(val) => self.set_axis(2, *val),
+                        PosVal::E(val) => extruding = *val > 0_f64,
+                        _ => {}
+                    }
+                }
+                if extruding {
+                    let end = self.machine_position();
+                    self.extend(start);
+                    self.extend(end);
+                }
+            }
+            Command::G2(form) => self.arc(form, true),
+            Command::G3(form) => self.arc(form, false),
+            Command::G17 => self.plane = Plane::XY,
+            Command::G18 => self.plane = Plane::ZX,
+            Command::G19 => self.plane = Plane::YZ,
+            Command::G90 => self.position_mode = PositionMode::Absolute,
+            Command::G91 => self.position_mode = PositionMode::Relative,
+            Command::G92(params) => {
+                for param in params {
+                    let (axis, val) = match param {
+                        PosVal::X(val) => (0, val),
+                        PosVal::Y(val) => (1, val),
+                        PosVal::Z(val) => (2, val),
+                        _ => continue,
+                    };
+                    self.origin[axis] += self.position[axis] - val;
+                    self.position[axis] = *val;
+                }
+            }
+            Command::M486(val) => self.multipart(val),
+            Command::ExcludeObjectDefine(definition) => {
+                let id = self.klipper.id(&definition.name);
+                self.entry(id).name = Some(definition.name.clone());
+            }
+            Command::ExcludeObjectStart(_)
+            | Command::ExcludeObjectEnd(_)
+            | Command::ExcludeObject(_) => {
+                if let Command::M486(val) = self.klipper.translate(command.clone()) {
+                    self.multipart(&val);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn multipart(&mut self, val: &MultiPartVal) {
+        match val {
+            MultiPartVal::A(name) => {
+                if let Some(id) = self.current {
+                    self.entry(id).name = Some(name.clone());
+                }
+            }
+            MultiPartVal::C => {
+                if let Some(id) = self.current {
+                    self.entry(id).cancelled = true;
+                }
+            }
+            MultiPartVal::P(id) => self.entry(*id).cancelled = true,
+            MultiPartVal::S(id, name) => {
+                self.current = Some(*id);
+                let object = self.entry(*id);
+                if name.is_some() {
+                    object.name.clone_from(name);
+                }
+            }
+            MultiPartVal::T(_) => {}
+            MultiPartVal::U(id) => self.entry(*id).cancelled = false,
+        }
+    }
+
+    fn entry(&mut self, id: i128) -> &mut Object {
+        self.objects.entry(id).or_insert_with(|| Object::new(id))
+    }
+
+    fn set_axis(&mut self, axis: usize, val: f64) {
+        self.position[axis] = match self.position_mode {
+            PositionMode::Absolute => val,
+            PositionMode::Relative => self.position[axis] + val,
+        };
+    }
+
+    fn machine_position(&self) -> [f64; 3] {
+        [
+            self.origin[0] + self.position[0],
+            self.origin[1] + self.position[1],
+            self.origin[2] + self.position[2],
+        ]
+    }
+
+    // Adds a point to the bounding box of the current object.
+    fn extend(&mut self, p: [f64; 3]) {
+        if let Some(id) = self.current {
+            let object = self.entry(id);
+            match &mut object.bbox {
+                Some(bbox) => bbox.extend(p),
+                None => object.bbox = Some(BoundingBox::point(p)),
+            }
+        }
+    }
+
+    // Follows an arc, adding its extreme points when extruding.
+    fn arc(&mut self, form: &ArcForm, clockwise: bool) {
+        let start = self.machine_position();
+        let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+        let mut extruding = false;
+        for param in params {
+            match param {
+                ArcVal::X(val) => self.set_axis(0, *val),
+                ArcVal::Y(val) => self.set_axis(1, *val),
+                ArcVal::Z(val) => self.set_axis(2, *val),
+                ArcVal::E(val) => extruding = *val > 0_f64,
+                _ => {}
+            }
+        }
+        if !extruding {
+            return;
+        }
+        let end = self.machine_position();
+        self.extend(start);
+        self.extend(end);
+        for p in arc_extremes(start, end, form, self.plane, clockwise) {
+            self.extend(p);
+        }
+    }
+}
+
+/// The points of an arc which lie furthest along each axis of its plane.
+///
+/// Only the points which the arc passes through are returned, with the
+/// third axis at its value at the end point. The R form returns no points.
+pub(crate) fn arc_extremes(
+    start: [f64; 3],
+    end: [f64; 3],
+    form: &ArcForm,
+    plane: Plane,
+    clockwise: bool,
+) -> Vec<[f64; 3]> {
+    if matches!(form, ArcForm::R(_)) {
+        return vec![];
+    }
+    let [a, b, _] = plane.axes();
+    let params = compute_arc_to((start[a], start[b]), (end[a], end[b]), form, plane);
+    let sweep = params.sweep(clockwise).abs();
+    let (ca, cb) = params.center;
+    (0..4)
+        .map(|quadrant| f64::from(quadrant) * FRAC_PI_2)
+        .filter(|theta| {
+            let along = if clockwise {
+                (params.theta_start - theta).rem_euclid(TAU)
+            } else {
+                (theta - params.theta_start).rem_euclid(TAU)
+            };
+            along <= sweep
+        })
+        .map(|theta| {
+            let (sin, cos) = theta.sin_cos();
+            let mut point = end;
+            point[a] = params.radius.mul_add(cos, ca);
+            point[b] = params.radius.mul_add(sin, cb);
+            point
+        })
+        .collect()
+}
+
+impl FromIterator<Command> for ObjectTable {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Command>,
+    {
+        let mut table = Self::default();
+        for command in iter {
+            table.push(&command);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(lines: &[&str]) -> ObjectTable {
+        lines
+            .iter()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn names_and_cancellation() {
+        let table = table(&[
+            "M486 T2",
+            "M486 S0",
+            "M486 AFirst.stl",
+            "M486 S1 A\"cube copy 1\"",
+            "M486 C",
+            "M486 S-1",
+            "M486 P0",
+            "M486 U1",
+        ]);
+        let objects = table.iter().collect::<Vec<_>>();
+        assert_eq!(objects.iter().map(|o| o.id).collect::<Vec<_>>(), [-1, 0, 1]);
+        assert_eq!(objects[0].name, None);
+        assert_eq!(objects[1].name.as_deref(), Some("First.stl"));
+        assert_eq!(objects[2].name.as_deref(), Some("cube copy 1"));
+        assert!(objects[1].cancelled);
+        assert!(!objects[2].cancelled);
+        assert_eq!(table.current(), Some(-1));
+    }
+
+    #[test]
+    fn klipper() {
+        let table = table(&[
+            "EXCLUDE_OBJECT_DEFINE NAME=a CENTER=10,10 POLYGON=[[5,5],[15,5],[15,15]]",
+            "EXCLUDE_OBJECT_DEFINE NAME=b CENTER=30,10 POLYGON=[[25,5],[35,5],[35,15]]",
+            "EXCLUDE_OBJECT_START NAME=b",
+            "G1 X30 Y10 Z0.2 E1",
+            "EXCLUDE_OBJECT_END NAME=b",
+            "EXCLUDE_OBJECT NAME=a",
+        ]);
+        let a = table.get(0).unwrap();
+        assert_eq!(a.name.as_deref(), Some("a"));
+        assert!(a.cancelled);
+        assert!(a.bbox.is_none());
+        let b = table.get(1).unwrap();
+        assert_eq!(b.name.as_deref(), Some("b"));
+        assert!(b.bbox.is_some());
+        assert_eq!(table.current(), Some(-1));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let table = table(&[
+            "M486 S0",
+            "G1 X10 Y10 Z0.2",
+            "G1 X20 E1",
+            // Travel moves are ignored.
+            "G0 X100 Y100",
+            "G1 X10 Y10",
+            // A half circle, anticlockwise, around (15, 10).
+            "G3 X20 Y10 I5 J0 E2",
+        ]);
+        let bbox = table.get(0).unwrap().bbox.unwrap();
+        for (actual, expected) in bbox.min.iter().zip([10.0, 5.0, 0.2]) {
+            assert!((actual - expected).abs() < 1e-9, "{:?}", bbox.min);
+        }
+        for (actual, expected) in bbox.max.iter().zip([20.0, 10.0, 0.2]) {
+            assert!((actual - expected).abs() < 1e-9, "{:?}", bbox.max);
+        }
+    }
+
+    #[test]
+    fn turns() {
+        // With a full turn added, the quarter circle covers the whole circle.
+        let table = table(&["M486 S0", "G1 X10 Y0 Z0.2", "G3 X0 Y10 I-10 J0 P1 E1"]);
+        let bbox = table.get(0).unwrap().bbox.unwrap();
+        for (actual, expected) in bbox.min.iter().zip([-10.0, -10.0, 0.2]) {
+            assert!((actual - expected).abs() < 1e-9, "{:?}", bbox.min);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use nom::IResult;
+use nom::Parser;
+use nom::branch::alt;
+use nom::bytes::complete::tag_no_case;
+use nom::bytes::complete::take_till1;
+use nom::bytes::complete::take_until;
+use nom::character::complete::char;
+use nom::character::complete::space1;
+use nom::combinator::map;
+use nom::multi::many0;
+use nom::multi::separated_list0;
+use nom::sequence::delimited;
+use nom::sequence::preceded;
+use nom::sequence::separated_pair;
+
+use crate::double::double_no_exponent;
+
+/// An object declared by Klipper's `EXCLUDE_OBJECT_DEFINE`.
+///
+/// `EXCLUDE_OBJECT_DEFINE NAME=cube_id_0_copy_0 CENTER=110,110 POLYGON=[[105,105],[115,105],[115,115]]`
+#[derive(Clone, Debug, Default)]
+pub struct ObjectDefinition {
+    /// Identifies the object in `EXCLUDE_OBJECT_START` and `EXCLUDE_OBJECT_END`.
+    pub name: String,
+    /// The center of the object, in the XY plane.
+    pub center: Option<(f64, f64)>,
+    /// The outline of the object, in the XY plane.
+    pub polygon: Vec<(f64, f64)>,
+}
+
+impl Eq for ObjectDefinition {}
+
+/// Bit wise comparison cant' compare directly [NAN and inf]
+///
+/// N.B. Equality is not used in production code -  assertion testing only.
+impl PartialEq for ObjectDefinition {
+    fn eq(&self, other: &Self) -> bool {
+        let bits = |(x, y): &(f64, f64)| (x.to_bits(), y.to_bits());
+        self.name == other.name
+            && self.center.as_ref().map(bits) == other.center.as_ref().map(bits)
+            && self
+                .polygon
+                .iter()
+                .map(bits)
+                .eq(other.polygon.iter().map(bits))
+    }
+}
+
+/// Parameters of Klipper's `EXCLUDE_OBJECT` commands.
+#[derive(Clone, Debug)]
+pub enum ExcludeVal {
+    /// `NAME=cube_id_0_copy_0`
+    Name(String),
+    /// `CENTER=110,110`
+    Center((f64, f64)),
+    /// `POLYGON=[[105,105],[115,105],[115,115]]`
+    Polygon(Vec<(f64, f64)>),
+    /// Any other parameter, eg. `RESET=1`
+    Other(String),
+}
+
+/// Extracts the parameters which follow an `EXCLUDE_OBJECT` command.
+///
+/// # Errors
+///   When match fails.
+pub fn parse_exclude_vals(i: &str) -> IResult<&str, Vec<ExcludeVal>> {
+    many0(preceded(space1, exclude_val)).parse(i)
+}
+
+/// Collects the parameters of `EXCLUDE_OBJECT_DEFINE`.
+///
+/// Returns `None` if the name is missing.
+#[must_use]
+pub fn definition(vals: Vec<ExcludeVal>) -> Option<ObjectDefinition> {
+    let mut name = None;
+    let mut definition = ObjectDefinition::default();
+    for val in vals {
+        match val {
+            ExcludeVal::Name(val) => name = Some(val),
+            ExcludeVal::Center(val) => definition.center = Some(val),
+            ExcludeVal::Polygon(val) => definition.polygon = val,
+            ExcludeVal::Other(_) => {}
+        }
+    }
+    definition.name = name?;
+    Some(definition)
+}
+
+/// Returns the value of the NAME parameter.
+#[must_use]
+pub fn name(vals: Vec<ExcludeVal>) -> Option<String> {
+    vals.into_iter().find_map(|val| match val {
+        ExcludeVal::Name(name) => Some(name),
+        _ => None,
+    })
+}
+
+fn exclude_val(i: &str) -> IResult<&str, ExcludeVal> {
+    alt((
+        map(
+            preceded(
+                tag_no_case("NAME="),
+                alt((delimited(char('"'), take_until("\""), char('"')), value)),
+            ),
+            |name: &str| ExcludeVal::Name(name.to_string()),
+        ),
+        map(preceded(tag_no_case("CENTER="), point), ExcludeVal::Center),
+        map(
+            preceded(
+                tag_no_case("POLYGON="),
+                delimited(
+                    char('['),
+                    separated_list0(char(','), delimited(char('['), point, char(']'))),
+                    char(']'),
+                ),
+            ),
+            ExcludeVal::Polygon,
+        ),
+        map(value, |other: &str| ExcludeVal::Other(other.to_string())),
+    ))
+    .parse(i)
+}
+
+// Everything up to the next parameter, or comment.
+fn value(i: &str) -> IResult<&str, &str> {
+    take_till1(|c: char| c.is_whitespace() || c == ';').parse(i)
+}
+
+// "110.5,110"
+fn point(i: &str) -> IResult<&str, (f64, f64)> {
+    separated_pair(double_no_exponent, char(','), double_no_exponent).parse(i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn define() {
+        let (remain, vals) = parse_exclude_vals(
+            " NAME=cube_id_0_copy_0 CENTER=110,110.5 POLYGON=[[105,105],[115,105],[115,115]] ; c",
+        )
+        .unwrap();
+        assert_eq!(remain, " ; c");
+        assert_eq!(
+            definition(vals),
+            Some(ObjectDefinition {
+                name: String::from("cube_id_0_copy_0"),
+                center: Some((110.0, 110.5)),
+                polygon: vec![(105.0, 105.0), (115.0, 105.0), (115.0, 115.0)],
+            })
+        );
+    }
+
+    #[test]
+    fn names() {
+        let (_, vals) = parse_exclude_vals(r#" NAME="cube copy 3""#).unwrap();
+        assert_eq!(name(vals), Some(String::from("cube copy 3")));
+
+        // A parameter is required.
+        let (_, vals) = parse_exclude_vals(" RESET=1").unwrap();
+        assert_eq!(name(vals), None);
+    }
+}
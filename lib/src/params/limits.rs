@@ -0,0 +1,123 @@
+use nom::IResult;
+use nom::Parser;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::space0;
+use nom::combinator::map;
+use nom::multi::many0;
+use nom::sequence::preceded;
+
+use crate::double::double_no_exponent;
+
+/// Parameters of the motion limit commands M201, M203, M204 and M205.
+///
+/// The meaning of each letter depends on the command :-
+///
+/// ```text
+/// M201 X1000 Y1000 Z200 E5000  ; maximum accelerations, mm/sec^2
+/// M203 X200 Y200 Z12 E120      ; maximum feedrates, mm/sec
+/// M204 P1250 R1250 T1250       ; print, retract and travel acceleration, mm/sec^2
+/// M205 X8 Y8 Z0.4 E4.5 J0.013  ; jerk limits mm/sec, junction deviation mm
+/// ```
+#[derive(Clone, Debug)]
+pub enum LimitVal {
+    /// M205: Minimum segment time, in µs.
+    B(f64),
+    /// Extruder
+    E(f64),
+    /// M205: Junction deviation, in mm.
+    J(f64),
+    /// M204: Printing acceleration.
+    P(f64),
+    /// M204: Retract acceleration.
+    R(f64),
+    /// M204: Printing and travel acceleration, M205: minimum feedrate.
+    S(f64),
+    /// M204: Travel acceleration, M205: minimum travel feedrate.
+    T(f64),
+    /// Axis X
+    X(f64),
+    /// Axis Y
+    Y(f64),
+    /// Axis Z
+    Z(f64),
+}
+
+impl Eq for LimitVal {}
+
+/// Bit wise comparison cant' compare directly [NAN and inf]
+///
+/// N.B. Equality is not used in production code -  assertion testing only.
+impl PartialEq for LimitVal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::B(x), Self::B(y))
+            | (Self::E(x), Self::E(y))
+            | (Self::J(x), Self::J(y))
+            | (Self::P(x), Self::P(y))
+            | (Self::R(x), Self::R(y))
+            | (Self::S(x), Self::S(y))
+            | (Self::T(x), Self::T(y))
+            | (Self::X(x), Self::X(y))
+            | (Self::Y(x), Self::Y(y))
+            | (Self::Z(x), Self::Z(y)) => x.to_bits() == y.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+/// Extracts the parameters which follow a motion limit command.
+///
+/// # Errors
+///   When match fails.
+pub fn parse_limit_vals(i: &str) -> IResult<&str, Vec<LimitVal>> {
+    many0(preceded(space0, limit_val)).parse(i)
+}
+
+fn limit_val(i: &str) -> IResult<&str, LimitVal> {
+    alt((
+        map(preceded(tag("B"), double_no_exponent), LimitVal::B),
+        map(preceded(tag("E"), double_no_exponent), LimitVal::E),
+        map(preceded(tag("J"), double_no_exponent), LimitVal::J),
+        map(preceded(tag("P"), double_no_exponent), LimitVal::P),
+        map(preceded(tag("R"), double_no_exponent), LimitVal::R),
+        map(preceded(tag("S"), double_no_exponent), LimitVal::S),
+        map(preceded(tag("T"), double_no_exponent), LimitVal::T),
+        map(preceded(tag("X"), double_no_exponent), LimitVal::X),
+        map(preceded(tag("Y"), double_no_exponent), LimitVal::Y),
+        map(preceded(tag("Z"), double_no_exponent), LimitVal::Z),
+    ))
+    .parse(i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vals() {
+        assert_eq!(
+            parse_limit_vals(" X8.00 Y8.00 Z0.40 E4.50 ; sets the jerk limits"),
+            Ok((
+                " ; sets the jerk limits",
+                vec![
+                    LimitVal::X(8.0),
+                    LimitVal::Y(8.0),
+                    LimitVal::Z(0.4),
+                    LimitVal::E(4.5)
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_limit_vals("P2000R1250T2500"),
+            Ok((
+                "",
+                vec![
+                    LimitVal::P(2000.0),
+                    LimitVal::R(1250.0),
+                    LimitVal::T(2500.0)
+                ]
+            ))
+        );
+    }
+}
@@ -1,6 +1,12 @@
+/// Klipper's object exclusion parameters
+pub mod exclude;
 /// Parameter for commands that move the print head.
 ///
 /// i.e. G0/G1/G2/G3
 pub mod head;
+/// Motion limit parameters
+///
+/// i.e. M201/M203/M204/M205
+pub mod limits;
 /// Multipart parameters
 pub mod mp;
@@ -2,14 +2,19 @@ use core::hash::Hash;
 
 use nom::IResult;
 use nom::Parser;
+use nom::branch::alt;
 use nom::bytes::complete::tag;
+use nom::bytes::complete::take_till;
 use nom::bytes::complete::take_until;
 use nom::character::complete::char;
+use nom::character::complete::line_ending;
 use nom::character::complete::space0;
-use nom::combinator::complete;
+use nom::character::complete::space1;
 use nom::combinator::map;
+use nom::combinator::opt;
 use nom::sequence::delimited;
 use nom::sequence::preceded;
+use nom::sequence::terminated;
 
 /// Parameters used in M486 Commands
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -51,15 +56,32 @@ macro_rules! parse_mp_val {
 
 /// Extract multipart A parameter
 ///
-/// TODO: Should I used `line_ending` instead of newline?
+/// The name is either quoted, `A"cube copy 3"`, or runs to the end of the
+/// line, or the start of a comment.
 ///
 /// # Errors
 ///   when match fails.
 pub fn parse_mp_a(i: &str) -> IResult<&str, MultiPartVal> {
-    map(
-        complete(delimited(tag("A"), take_until("\n"), char('\n'))),
-        |s: &str| MultiPartVal::A(s.to_string()),
-    )
+    map(preceded((space0, tag("A")), parse_name), |s: &str| {
+        MultiPartVal::A(s.to_string())
+    })
+    .parse(i)
+}
+
+// A quoted name, or everything up to a comment or the end of the line.
+//
+// Trailing whitespace, and the line ending, are consumed.
+fn parse_name(i: &str) -> IResult<&str, &str> {
+    alt((
+        terminated(delimited(char('"'), take_until("\""), char('"')), space0),
+        map(
+            terminated(
+                take_till(|c| c == ';' || c == '\r' || c == '\n'),
+                opt(line_ending),
+            ),
+            str::trim_end,
+        ),
+    ))
     .parse(i)
 }
 
@@ -71,7 +93,9 @@ pub fn parse_mp_c(i: &str) -> IResult<&str, MultiPartVal> {
     map((space0, tag("C")), |_| MultiPartVal::C).parse(i)
 }
 
-/// Extract multipart S parameter
+/// Extract multipart S parameter, with an optional name.
+///
+/// M486 S3 A"cube copy 3"
 ///
 /// # Errors
 ///   when match fails.
@@ -80,12 +104,8 @@ pub fn parse_mp_s(i: &str) -> IResult<&str, MultiPartVal> {
         preceded(
             (space0::<&str, _>, tag("S")),
             (
-                // This is broken when a name is supplied, it is not recognized!!!
                 nom::character::complete::i128,
-                nom::combinator::opt(preceded(
-                    space0,
-                    delimited(char('"'), take_until("\""), char('"')),
-                )),
+                opt(preceded((space1, tag("A")), parse_name)),
             ),
         ),
         |(s, name)| MultiPartVal::S(s, name.map(std::string::ToString::to_string)),
@@ -94,9 +114,6 @@ pub fn parse_mp_s(i: &str) -> IResult<&str, MultiPartVal> {
 }
 
 parse_mp_val!(parse_mp_p, "P", P);
-// TODO must implement [ S<i> A"<name>" ]
-// M486 S3 A"cube copy 3" ; Indicate that the 4th object is starting now and name it
-
 parse_mp_val!(parse_mp_t, "T", T);
 parse_mp_val!(parse_mp_u, "U", U);
 
@@ -118,4 +135,27 @@ mod test {
         // Pass: - parameter wrapper and inner value match.
         assert_eq!(parse_mp_s("S-1"), Ok(("", MultiPartVal::S(-1, None))));
     }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            parse_mp_s(r#"S3 A"cube copy 3""#),
+            Ok(("", MultiPartVal::S(3, Some(String::from("cube copy 3")))))
+        );
+        assert_eq!(
+            parse_mp_s("S3 Acube.stl ; start"),
+            Ok((
+                "; start",
+                MultiPartVal::S(3, Some(String::from("cube.stl")))
+            ))
+        );
+        assert_eq!(
+            parse_mp_a(r#"A"cube copy 3" ; name"#),
+            Ok(("; name", MultiPartVal::A(String::from("cube copy 3"))))
+        );
+        assert_eq!(
+            parse_mp_a("ASpiralHourglass.stl"),
+            Ok(("", MultiPartVal::A(String::from("SpiralHourglass.stl"))))
+        );
+    }
 }
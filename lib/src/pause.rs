@@ -0,0 +1,193 @@
+//! Inserts a filament change, or a pause, where a layer starts.
+//!
+//! The layer is found with [`crate::layer::LayerSegmenter`], by number or
+//! by height. The G-code, `M600` by default, is inserted where the layer
+//! starts: before its marker comment, or, in files without markers, before
+//! its first extrusion. The head has finished the layer below, and has not
+//! yet moved up.
+
+use std::io::Write;
+
+use crate::binary::gcode_block::rewrite::rewrite_blocks;
+use crate::command::Command;
+use crate::layer::LayerFilter;
+use crate::layer::LayerSegmenter;
+
+/// Returns the index of the line where the print first enters a layer
+/// passing the filter.
+///
+/// ```rust
+/// use gcode_nom::layer::LayerFilter;
+/// use gcode_nom::pause::find_layer;
+///
+/// let text = ";LAYER_CHANGE\n;Z:0.2\nG1 Z0.2\nG1 X10 E1\n;LAYER_CHANGE\n;Z:0.4\nG1 Z0.4\nG1 X0 E2";
+/// assert_eq!(find_layer(text.lines(), LayerFilter::Index(1)), Some(4));
+/// let above = LayerFilter::ZRange { min: 0.3, max: f64::INFINITY };
+/// assert_eq!(find_layer(text.lines(), above), Some(4));
+/// assert_eq!(find_layer(text.lines(), LayerFilter::Index(2)), None);
+/// ```
+pub fn find_layer<'a, I>(lines: I, filter: LayerFilter) -> Option<usize>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut segmenter = LayerSegmenter::default();
+    for line in lines {
+        let command = Command::parse_line(line).map_or(Command::Nop, |(_, command)| command);
+        segmenter.push(&command);
+    }
+    let layers = segmenter.finish();
+    let passes = |index: usize| filter.contains(Some(layers[index].layer));
+    // Prefer a layer entered from one which fails the filter, so that an
+    // extrusion made high above the bed, such as priming the nozzle before
+    // a purge line, is passed over.
+    (1..layers.len())
+        .find(|index| passes(*index) && !passes(index - 1))
+        .or_else(|| (!layers.is_empty() && passes(0)).then_some(0))
+        .map(|index| layers[index].commands.start)
+}
+
+/// Inserts G-code before a given line.
+///
+/// ```rust
+/// use gcode_nom::pause::Pauser;
+///
+/// let mut pauser = Pauser::new(1);
+/// let mut out = vec![];
+/// for line in ["G1 X10 E1", ";LAYER_CHANGE", "G1 Z0.4"] {
+///     pauser.write_line(line, &mut out).unwrap();
+/// }
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "G1 X10 E1\nM600\n;LAYER_CHANGE\nG1 Z0.4\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pauser {
+    /// The index of the line the G-code is inserted before.
+    pub line: usize,
+    /// The lines inserted, `M600` by default.
+    pub gcode: Vec<String>,
+    // The index of the next line.
+    index: usize,
+}
+
+impl Pauser {
+    /// Inserts `M600` before the line with the given index.
+    #[must_use]
+    pub fn new(line: usize) -> Self {
+        Self {
+            line,
+            gcode: vec!["M600".to_string()],
+            index: 0,
+        }
+    }
+
+    /// Writes a single line of G-code, preceded by the inserted G-code when
+    /// it is the chosen line.
+    ///
+    /// # Errors
+    ///   When a write fails.
+    pub fn write_line<W>(&mut self, line: &str, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        if self.index == self.line {
+            for gcode in &self.gcode {
+                writeln!(out, "{gcode}")?;
+            }
+        }
+        self.index += 1;
+        writeln!(out, "{line}")
+    }
+
+    /// Rewrites a binary G-code file.
+    ///
+    /// Only the block holding the chosen line is re-encoded, along with
+    /// any blocks which split a line between them. Every other block is
+    /// copied unchanged.
+    ///
+    /// # Errors
+    ///   When the file cannot be parsed, or a block decompressed.
+    pub fn rewrite_bgcode(&mut self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        rewrite_blocks(input, |text| {
+            let start = self.index;
+            let mut out = vec![];
+            for line in text.lines() {
+                self.write_line(line, &mut out)?;
+            }
+            let inserted = (start..self.index).contains(&self.line);
+            Ok(inserted.then(|| String::from_utf8_lossy(&out).into_owned()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binary::bgcode_parser_with_checksum;
+    use crate::binary::gcode_block::decode::decode_text;
+    use crate::binary::gcode_block::extractor::extract_gcode;
+
+    #[test]
+    fn layers() {
+        // Cura, and a file without markers.
+        let cura = ";LAYER:0\nG0 Z0.3\nG1 X10 E1\n;LAYER:1\nG0 Z0.5\nG1 X0 E2";
+        assert_eq!(find_layer(cura.lines(), LayerFilter::Index(1)), Some(3));
+        let plain = "G28\nG1 Z0.2\nG1 X10 E1\nG1 Z0.4\nG1 X0 E2\nG1 Z0.6\nG1 X10 E3";
+        let above = |min| LayerFilter::ZRange {
+            min,
+            max: f64::INFINITY,
+        };
+        assert_eq!(find_layer(plain.lines(), above(0.4)), Some(4));
+        assert_eq!(find_layer(plain.lines(), above(0.5)), Some(6));
+        assert_eq!(find_layer(plain.lines(), above(1_f64)), None);
+
+        // Priming the nozzle, high above the bed, is not the layer wanted.
+        let primed = "G1 Z15\nG1 E2\n;LAYER_CHANGE\n;Z:0.2\nG1 Z0.2\nG1 X10 E3\n;LAYER_CHANGE\n;Z:5\nG1 Z5\nG1 X0 E4";
+        assert_eq!(find_layer(primed.lines(), above(5_f64)), Some(6));
+        assert_eq!(find_layer(primed.lines(), above(0_f64)), Some(1));
+    }
+
+    #[test]
+    fn custom() {
+        let mut pauser = Pauser::new(0);
+        pauser.gcode = vec!["M117 Swap colour".to_string(), "M0".to_string()];
+        let mut out = vec![];
+        for line in ["G1 X10 E1", "G1 X0 E2"] {
+            pauser.write_line(line, &mut out).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "M117 Swap colour\nM0\nG1 X10 E1\nG1 X0 E2\n"
+        );
+    }
+
+    #[test]
+    fn bgcode() {
+        let input = include_bytes!("../../assets/mini_cube_b.bgcode");
+        let (_, blocks) = extract_gcode(input).unwrap();
+        let before = decode_text(&blocks).unwrap();
+        let line = find_layer(before.lines(), LayerFilter::Index(10)).unwrap();
+
+        let output = Pauser::new(line).rewrite_bgcode(input).unwrap();
+        assert!(bgcode_parser_with_checksum(&output).is_ok());
+        // Only one block has changed.
+        let (_, after_blocks) = extract_gcode(&output).unwrap();
+        assert_eq!(after_blocks.len(), blocks.len());
+        let changed = blocks
+            .iter()
+            .zip(&after_blocks)
+            .filter(|(before, after)| before != after)
+            .count();
+        assert_eq!(changed, 1);
+
+        let after = decode_text(&after_blocks).unwrap();
+        let mut lines = before.lines().collect::<Vec<_>>();
+        lines.insert(line, "M600");
+        assert_eq!(after.lines().collect::<Vec<_>>(), lines);
+        assert_eq!(
+            find_layer(after.lines(), LayerFilter::Index(10)),
+            Some(line + 1)
+        );
+    }
+}
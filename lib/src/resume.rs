@@ -0,0 +1,381 @@
+//! Generates a file which resumes a failed print, from a given line.
+//!
+//! The start G-code, up to the first layer, is kept, without its moves: the
+//! temperatures, homing and other settings. Fan speeds set later, before
+//! the resume point, follow it, and the latest bed and hot end temperatures
+//! are waited for with M190 and M109. Then the head is raised,
+//! moved above the point where the print resumes, and lowered onto it. E
+//! and the position are set with G92, and the rest of the file is copied
+//! unchanged.
+//!
+//! Homing Z, or levelling the bed, would drive the probe into the part. By
+//! default G28 homes only X and Y, bed levelling (G29, G80) is dropped, and
+//! the nozzle is taken to be resting at the height the file gives for the
+//! resume point.
+
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Write;
+
+use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::arc_end;
+use crate::command::Command;
+use crate::layer::LayerSegmenter;
+use crate::params::head::PosVal;
+
+/// Writes a file which resumes a print.
+///
+/// ```rust
+/// use gcode_nom::resume::Resumer;
+///
+/// let text = "M104 S215\nG28\nG1 Z0.2 F720\nG1 X10 Y10 E1\nG1 Z0.4\nG1 X20 E2";
+/// let mut out = vec![];
+/// // Resume from the second layer, the sixth line.
+/// Resumer::default().write(text, 5, &mut out).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "M104 S215\nG28 X Y\nM109 S215\nG90\nM82\nG92 Z0.4\nG1 Z5.4 F3000\nG1 X10 Y10 F3000\nG1 Z0.4 F3000\nG92 E1\nG1 F720\nG1 X20 E2\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Resumer {
+    /// Home Z, and level the bed, as the start G-code does.
+    ///
+    /// Only safe when the probe cannot reach the part.
+    pub home_z: bool,
+    /// The height the head is raised above the part, in mm.
+    pub clearance: f64,
+    /// The feedrate of the moves to the resume point, in mm/min.
+    pub feedrate: f64,
+}
+
+impl Default for Resumer {
+    fn default() -> Self {
+        Self {
+            home_z: false,
+            clearance: 5_f64,
+            feedrate: 3000_f64,
+        }
+    }
+}
+
+impl Resumer {
+    /// Writes a file which resumes the print at the line with the given
+    /// index.
+    ///
+    /// # Errors
+    ///   When a write fails.
+    ///
+    ///   When the position of the head, at the resume point, is unknown.
+    pub fn write<W>(&self, text: &str, line: usize, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let lines = text.lines().collect::<Vec<_>>();
+        let line = line.min(lines.len());
+        let mut segmenter = LayerSegmenter::default();
+        let mut state = State::default();
+        for (index, text) in lines[..line].iter().enumerate() {
+            let command = Command::parse_line(text).map_or(Command::Nop, |(_, command)| command);
+            segmenter.push(&command);
+            state.push(index, text, &command);
+        }
+        let [x, y, z] = state.position;
+        if [x, y, z].iter().any(|value| value.is_nan()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The position of the head, where the print resumes, is unknown",
+            ));
+        }
+
+        // The start G-code, without its moves.
+        let preamble = segmenter
+            .finish()
+            .first()
+            .map_or(line, |layer| layer.commands.start.min(line));
+        for text in &lines[..preamble] {
+            let command = Command::parse_line(text).map_or(Command::Nop, |(_, command)| command);
+            match command {
+                Command::G0(_) | Command::G1(_) | Command::G2(_) | Command::G3(_) => {}
+                Command::GDrop(28) if !self.home_z => writeln!(out, "G28 X Y")?,
+                Command::GDrop(29 | 80) if !self.home_z => {}
+                _ => writeln!(out, "{text}")?,
+            }
+        }
+        for text in [&state.bed, &state.hot_end].into_iter().flatten() {
+            writeln!(out, "{text}")?;
+        }
+        for (index, text) in state.settings.values() {
+            if *index >= preamble {
+                writeln!(out, "{text}")?;
+            }
+        }
+
+        // Up, across and down, onto the part.
+        let feedrate = self.feedrate;
+        writeln!(out, "G90")?;
+        writeln!(out, "{}", if state.relative_e { "M83" } else { "M82" })?;
+        if !self.home_z {
+            writeln!(out, "G92 Z{}", round(z))?;
+        }
+        writeln!(out, "G1 Z{} F{feedrate}", round(z + self.clearance))?;
+        writeln!(out, "G1 X{} Y{} F{feedrate}", round(x), round(y))?;
+        writeln!(out, "G1 Z{} F{feedrate}", round(z))?;
+        writeln!(
+            out,
+            "G92 E{}",
+            if state.relative_e { 0_f64 } else { state.e }
+        )?;
+        if let Some(feedrate) = state.feedrate {
+            writeln!(out, "G1 F{feedrate}")?;
+        }
+        if state.position_mode == PositionMode::Relative {
+            writeln!(out, "G91")?;
+        }
+
+        for text in &lines[line..] {
+            writeln!(out, "{text}")?;
+        }
+        Ok(())
+    }
+}
+
+// The state of the printer, as the skipped lines are replayed.
+#[derive(Clone, Debug)]
+struct State {
+    // X, Y and Z, as given by the file. NaN when unknown.
+    position: [f64; 3],
+    position_mode: PositionMode,
+    // Set by M83, or G91.
+    relative_e: bool,
+    // The position of the E axis, as given by the file.
+    e: f64,
+    feedrate: Option<f64>,
+    // The latest bed and hot end temperatures, as M190 and M109.
+    bed: Option<String>,
+    hot_end: Option<String>,
+    // The latest fan commands, and their line, by M code.
+    settings: BTreeMap<u16, (usize, String)>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            position: [f64::NAN; 3],
+            position_mode: PositionMode::Absolute,
+            relative_e: false,
+            e: 0_f64,
+            feedrate: None,
+            bed: None,
+            hot_end: None,
+            settings: BTreeMap::new(),
+        }
+    }
+}
+
+impl State {
+    fn push(&mut self, index: usize, text: &str, command: &Command) {
+        match command {
+            Command::G0(params) | Command::G1(params) => {
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.set_axis(0, *val),
+                        PosVal::Y(val) => self.set_axis(1, *val),
+                        PosVal::Z(val) => self.set_axis(2, *val),
+                        PosVal::E(val) => self.set_e(*val),
+                        PosVal::F(val) => self.feedrate = Some(*val),
+                        _ => {}
+                    }
+                }
+            }
+            Command::G2(form) | Command::G3(form) => {
+                self.position = arc_end(self.position, form, self.position_mode);
+                let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+                for param in params {
+                    match param {
+                        ArcVal::E(val) => self.set_e(*val),
+                        ArcVal::F(val) => self.feedrate = Some(*val),
+                        _ => {}
+                    }
+                }
+            }
+            Command::G90 => {
+                self.position_mode = PositionMode::Absolute;
+                self.relative_e = false;
+            }
+            Command::G91 => {
+                self.position_mode = PositionMode::Relative;
+                self.relative_e = true;
+            }
+            Command::MDrop(82) => self.relative_e = false,
+            Command::MDrop(83) => self.relative_e = true,
+            Command::G92(params) => {
+                for param in params {
+                    match param {
+                        PosVal::X(val) => self.position[0] = *val,
+                        PosVal::Y(val) => self.position[1] = *val,
+                        PosVal::Z(val) => self.position[2] = *val,
+                        PosVal::E(val) => self.e = *val,
+                        _ => {}
+                    }
+                }
+            }
+            // Homing, the position is unknown until the next move.
+            Command::GDrop(28) => self.position = [f64::NAN; 3],
+            // The hot end and the bed, waiting for the temperature.
+            Command::MDrop(104 | 109) => self.hot_end = Some(with_code(text, 109)),
+            Command::MDrop(140 | 190) => self.bed = Some(with_code(text, 190)),
+            // The fan.
+            Command::MDrop(106 | 107) => {
+                self.settings.insert(106, (index, text.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    fn set_axis(&mut self, axis: usize, val: f64) {
+        self.position[axis] = match self.position_mode {
+            PositionMode::Absolute => val,
+            PositionMode::Relative => self.position[axis] + val,
+        };
+    }
+
+    fn set_e(&mut self, e: f64) {
+        self.e = if self.relative_e { self.e + e } else { e };
+    }
+}
+
+// Replaces the M code at the start of a line, keeping its parameters.
+fn with_code(text: &str, code: u16) -> String {
+    let params = text
+        .trim_start()
+        .trim_start_matches('M')
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit());
+    format!("M{code}{params}")
+}
+
+// Positions are written to the micron.
+fn round(value: f64) -> f64 {
+    (value * 1e3).round() / 1e3 + 0_f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::layer::LayerFilter;
+    use crate::pause::find_layer;
+
+    const TEXT: &str = "\
+M140 S60
+M104 S210
+G28 ; home
+G29
+M83
+G1 Z0.2 F720
+G1 X60 Y-3 E10 ; purge
+;LAYER_CHANGE
+;Z:0.2
+G1 Z0.2 F720
+G1 X10 Y10 F6000
+G1 X20 Y10 E0.5 F1200
+M106 S255
+M104 S200
+;LAYER_CHANGE
+;Z:0.4
+G1 Z0.4 F720
+G1 X10 Y10 E0.5 F1200";
+
+    fn resume(resumer: &Resumer, line: usize) -> String {
+        let mut out = vec![];
+        resumer.write(TEXT, line, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn layer() {
+        let line = find_layer(TEXT.lines(), LayerFilter::Index(1)).unwrap();
+        assert_eq!(line, 14);
+        assert_eq!(
+            resume(&Resumer::default(), line),
+            "\
+M140 S60
+M104 S210
+G28 X Y
+M83
+M190 S60
+M109 S200
+M106 S255
+G90
+M83
+G92 Z0.2
+G1 Z5.2 F3000
+G1 X20 Y10 F3000
+G1 Z0.2 F3000
+G92 E0
+G1 F1200
+;LAYER_CHANGE
+;Z:0.4
+G1 Z0.4 F720
+G1 X10 Y10 E0.5 F1200
+"
+        );
+
+        // Homed, the height of the nozzle is known.
+        let resumer = Resumer {
+            home_z: true,
+            clearance: 1_f64,
+            ..Resumer::default()
+        };
+        let output = resume(&resumer, line);
+        assert!(output.starts_with("M140 S60\nM104 S210\nG28 ; home\nG29\nM83\n"));
+        assert!(output.contains("G90\nM83\nG1 Z1.2 F3000\nG1 X20 Y10 F3000\n"));
+    }
+
+    #[test]
+    fn temperatures() {
+        // A later M104 or M140 changes the target, the resume still waits.
+        let text = "M190 S60\nM109 S215\nG28\nG1 Z0.2 F720\nG1 X10 Y10 E1\nM 104 S205 T0\nM140S55\nG1 Z0.4\nG1 X20 E2";
+        let mut out = vec![];
+        Resumer::default().write(text, 7, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.starts_with("M190 S60\nM109 S215\nG28 X Y\nM190S55\nM109 S205 T0\nG90\n"));
+    }
+
+    #[test]
+    fn unknown() {
+        // Straight after homing, the head has not moved.
+        let mut out = vec![];
+        let error = Resumer::default().write(TEXT, 3, &mut out).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn slicer_file() {
+        let input = include_str!("../../assets/II 2x4_0.15mm_PLA_MK3S_1h17m.gcode");
+        let line = find_layer(input.lines(), LayerFilter::Index(10)).unwrap();
+        let mut out = vec![];
+        Resumer::default().write(input, line, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+
+        // The rest of the print is unchanged.
+        let rest = input.lines().skip(line).collect::<Vec<_>>();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert!(lines.ends_with(&rest));
+        // The head stays above the part until it is over the resume point.
+        let commands = lines[..lines.len() - rest.len()]
+            .iter()
+            .map(|line| Command::parse_line(line).unwrap().1)
+            .collect::<Vec<_>>();
+        assert!(commands.contains(&Command::MDrop(109)));
+        assert!(!commands.contains(&Command::GDrop(80)));
+        let moves = commands
+            .iter()
+            .filter(|command| matches!(command, Command::G1(_)))
+            .collect::<Vec<_>>();
+        assert!(matches!(moves[0], Command::G1(params) if params.len() == 2));
+    }
+}
@@ -0,0 +1,176 @@
+//! Splits G2/G3 arcs into straight segments, for rendering.
+//!
+//! The number of segments is chosen so that no segment strays further than
+//! a given distance from the true arc. Small arcs keep their shape, while
+//! large arcs are not split into more points than they need.
+
+use core::f64::consts::PI;
+
+use crate::ArcParams;
+
+/// The most segments an arc is split into.
+const MAX_STEPS: u32 = 1 << 16;
+
+/// Controls how finely arcs are split into segments.
+///
+/// ```rust
+/// use gcode_nom::tessellation::Tessellation;
+///
+/// let tessellation = Tessellation::default();
+/// // A quarter circle of radius 1mm, and of radius 100mm.
+/// let small = tessellation.steps(1.0, core::f64::consts::FRAC_PI_2);
+/// let large = tessellation.steps(100.0, core::f64::consts::FRAC_PI_2);
+/// assert!(small >= 4);
+/// assert!(large < 100);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tessellation {
+    /// The maximum distance between a segment and the arc, in mm.
+    pub chord_tolerance: f64,
+    /// The maximum angle swept by a segment, in radians.
+    pub max_angle: Option<f64>,
+}
+
+impl Default for Tessellation {
+    fn default() -> Self {
+        Self {
+            chord_tolerance: 0.01,
+            max_angle: None,
+        }
+    }
+}
+
+impl Tessellation {
+    /// The number of segments needed to draw an arc, at least 1 and at
+    /// most 65536.
+    ///
+    /// `sweep` is the angle swept by the arc, in radians. A chord tolerance
+    /// which is not a positive number is replaced by the default.
+    #[must_use]
+    pub fn steps(&self, radius: f64, sweep: f64) -> u32 {
+        let radius = radius.abs();
+        let chord_tolerance = if self.chord_tolerance > 0_f64 && self.chord_tolerance.is_finite() {
+            self.chord_tolerance
+        } else {
+            Self::default().chord_tolerance
+        };
+        // The sagitta of a segment spanning the angle t is r(1 - cos(t/2)).
+        let mut step = if chord_tolerance >= radius {
+            PI
+        } else {
+            2_f64 * (1_f64 - chord_tolerance / radius).acos()
+        };
+        if let Some(max_angle) = self.max_angle.filter(|angle| *angle > 0_f64) {
+            step = step.min(max_angle);
+        }
+        let steps = (sweep.abs() / step).ceil();
+        if steps.is_finite() && steps >= 1_f64 {
+            // In range, the value is clamped and positive.
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let steps = steps.min(f64::from(MAX_STEPS)) as u32;
+            steps
+        } else {
+            1
+        }
+    }
+
+    /// The points along an arc, after its start.
+    ///
+    /// Each point is `[x, y, t]`, where `t` is the fraction of the sweep
+    /// covered, running from just above 0 to 1. Callers interpolate other
+    /// axes, such as Z and E, by `t`.
+    pub fn points(&self, params: &ArcParams, sweep: f64) -> impl Iterator<Item = [f64; 3]> + use<> {
+        let steps = self.steps(params.radius, sweep);
+        let (cx, cy) = params.center;
+        let radius = params.radius;
+        let theta_start = params.theta_start;
+        (1..=steps).map(move |i| {
+            let t = f64::from(i) / f64::from(steps);
+            let (sin, cos) = t.mul_add(sweep, theta_start).sin_cos();
+            [radius.mul_add(cos, cx), radius.mul_add(sin, cy), t]
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::f64::consts::TAU;
+
+    // The largest gap between the segments and the arc.
+    fn deviation(radius: f64, sweep: f64, steps: u32) -> f64 {
+        radius * (1_f64 - (sweep / f64::from(steps) / 2_f64).cos())
+    }
+
+    #[test]
+    fn tolerance() {
+        let tessellation = Tessellation::default();
+        for radius in [0.2, 1_f64, 10_f64, 250_f64] {
+            let steps = tessellation.steps(radius, TAU);
+            assert!(deviation(radius, TAU, steps) <= tessellation.chord_tolerance);
+            // One fewer step would be out of tolerance.
+            assert!(deviation(radius, TAU, steps - 1) > tessellation.chord_tolerance);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let coarse = Tessellation {
+            chord_tolerance: 5_f64,
+            max_angle: None,
+        };
+        // A tolerance larger than the arc, each segment spans at most half a circle.
+        assert_eq!(coarse.steps(1_f64, TAU), 2);
+        assert_eq!(coarse.steps(1_f64, 0.1), 1);
+        assert_eq!(coarse.steps(1_f64, 0_f64), 1);
+
+        let stepped = Tessellation {
+            max_angle: Some(PI / 8_f64),
+            ..coarse
+        };
+        assert_eq!(stepped.steps(1_f64, TAU), 16);
+        // Clockwise sweeps are negative.
+        assert_eq!(stepped.steps(1_f64, -TAU), 16);
+
+        // Without a usable tolerance, the default is used.
+        let default = Tessellation::default().steps(10_f64, TAU);
+        for chord_tolerance in [0_f64, -1_f64, f64::NAN, f64::INFINITY] {
+            let invalid = Tessellation {
+                chord_tolerance,
+                max_angle: None,
+            };
+            assert_eq!(invalid.steps(10_f64, TAU), default);
+        }
+
+        // However many turns are asked for, the segments are capped.
+        assert_eq!(Tessellation::default().steps(1_f64, 1e12 * TAU), MAX_STEPS);
+    }
+
+    #[test]
+    fn points() {
+        let tessellation = Tessellation {
+            chord_tolerance: 1_f64,
+            max_angle: Some(PI / 4_f64),
+        };
+        // A clockwise quarter circle, from [1, 0] to [0, -1].
+        let params = ArcParams {
+            center: (0_f64, 0_f64),
+            radius: 1_f64,
+            theta_start: 0_f64,
+            theta_end: 3_f64 * PI / 2_f64,
+            turns: 0_f64,
+        };
+        let sweep = params.sweep(true);
+        assert!((sweep + PI / 2_f64).abs() < 1e-12);
+        let points = tessellation.points(&params, sweep).collect::<Vec<_>>();
+        assert_eq!(points.len(), 2);
+        let [x, y, t] = points[0];
+        assert!((x - 0.5_f64.sqrt()).abs() < 1e-12);
+        assert!((y + 0.5_f64.sqrt()).abs() < 1e-12);
+        assert!((t - 0.5).abs() < 1e-12);
+        let [x, y, t] = points[1];
+        assert!(x.abs() < 1e-12);
+        assert!((y + 1_f64).abs() < 1e-12);
+        assert!((t - 1_f64).abs() < 1e-12);
+    }
+}
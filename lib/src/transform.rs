@@ -0,0 +1,606 @@
+//! Moves, rotates, scales and mirrors a print.
+//!
+//! A [`Transform`] is applied to the X, Y and Z values of G0, G1, G2, G3
+//! and G92. Arc centres (I and J) are turned with the print, a mirrored
+//! arc runs the other way so G2 and G3 swap. E and F are left alone.
+//!
+//! Transforms are built from rotations, uniform scales and mirrors, so
+//! circles stay circles and every arc can still be written as G2/G3.
+//!
+//! Rewritten lines are regenerated from the parsed command, comments are
+//! kept. Other lines are copied unchanged, including object outlines such
+//! as Klipper's `EXCLUDE_OBJECT_DEFINE`, which are not moved.
+
+use core::fmt::Write as _;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Write;
+
+use crate::Plane;
+use crate::PositionMode;
+use crate::arc::ArcVal;
+use crate::arc::Form as ArcForm;
+use crate::command::Command;
+use crate::params::head::PosVal;
+
+// The order in which parameters are written.
+const ORDER: &str = "XYZABCUVWIJKRPEFS";
+
+/// An affine transform of X and Y, with Z scaled and offset.
+///
+/// Transforms are combined with [`Transform::then`], the first is applied
+/// first.
+///
+/// ```rust
+/// use gcode_nom::transform::Transform;
+///
+/// // A quarter turn about [100, 100], then 10mm up the bed.
+/// let transform = Transform::rotate(90.0)
+///     .about([100.0, 100.0])
+///     .then(Transform::translate(0.0, 10.0, 0.0));
+/// let [x, y, z] = transform.apply([110.0, 100.0, 0.2]);
+/// assert!((x - 100.0).abs() < 1e-9);
+/// assert!((y - 120.0).abs() < 1e-9);
+/// assert!((z - 0.2).abs() < 1e-9);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    // Multiplies [X, Y], row by row.
+    linear: [[f64; 2]; 2],
+    // Multiplies Z.
+    z_scale: f64,
+    // Added to [X, Y, Z] last.
+    offset: [f64; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            linear: [[1_f64, 0_f64], [0_f64, 1_f64]],
+            z_scale: 1_f64,
+            offset: [0_f64; 3],
+        }
+    }
+}
+
+impl Transform {
+    /// Moves the print, in mm.
+    #[must_use]
+    pub fn translate(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            offset: [x, y, z],
+            ..Self::default()
+        }
+    }
+
+    /// Turns the print counter-clockwise about the origin, in degrees.
+    #[must_use]
+    pub fn rotate(degrees: f64) -> Self {
+        // Quarter turns are exact, so no axis picks up noise from the other.
+        let snap = |value: f64| if value.abs() < 1e-12 { 0_f64 } else { value };
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (sin, cos) = (snap(sin), snap(cos));
+        Self {
+            linear: [[cos, -sin], [sin, cos]],
+            ..Self::default()
+        }
+    }
+
+    /// Scales the print, along every axis, about the origin.
+    #[must_use]
+    pub fn scale(factor: f64) -> Self {
+        Self {
+            linear: [[factor, 0_f64], [0_f64, factor]],
+            z_scale: factor,
+            ..Self::default()
+        }
+    }
+
+    /// Reverses the X axis, reflecting the print in the line X = 0.
+    #[must_use]
+    pub fn mirror_x() -> Self {
+        Self {
+            linear: [[-1_f64, 0_f64], [0_f64, 1_f64]],
+            ..Self::default()
+        }
+    }
+
+    /// Reverses the Y axis, reflecting the print in the line Y = 0.
+    #[must_use]
+    pub fn mirror_y() -> Self {
+        Self {
+            linear: [[1_f64, 0_f64], [0_f64, -1_f64]],
+            ..Self::default()
+        }
+    }
+
+    /// Applies this transform, then `next`.
+    #[must_use]
+    pub fn then(self, next: Self) -> Self {
+        let [[a, b], [c, d]] = next.linear;
+        let [[e, f], [g, h]] = self.linear;
+        let [x, y] = next.vector([self.offset[0], self.offset[1]]);
+        Self {
+            linear: [
+                [a.mul_add(e, b * g), a.mul_add(f, b * h)],
+                [c.mul_add(e, d * g), c.mul_add(f, d * h)],
+            ],
+            z_scale: next.z_scale * self.z_scale,
+            offset: [
+                x + next.offset[0],
+                y + next.offset[1],
+                next.z_scale.mul_add(self.offset[2], next.offset[2]),
+            ],
+        }
+    }
+
+    /// Applies this transform about a point in XY, rather than the origin.
+    #[must_use]
+    pub fn about(self, center: [f64; 2]) -> Self {
+        let [x, y] = center;
+        Self::translate(-x, -y, 0_f64)
+            .then(self)
+            .then(Self::translate(x, y, 0_f64))
+    }
+
+    /// Returns the transformed point.
+    #[must_use]
+    pub fn apply(&self, point: [f64; 3]) -> [f64; 3] {
+        let [x, y] = self.vector([point[0], point[1]]);
+        [
+            x + self.offset[0],
+            y + self.offset[1],
+            self.z_scale.mul_add(point[2], self.offset[2]),
+        ]
+    }
+
+    /// Returns true if the print is reflected, arcs then run the other way.
+    #[must_use]
+    pub fn is_mirror(&self) -> bool {
+        self.determinant() < 0_f64
+    }
+
+    // Transforms an offset, such as a relative move or an arc centre.
+    fn vector(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let [[a, b], [c, d]] = self.linear;
+        [a.mul_add(x, b * y), c.mul_add(x, d * y)]
+    }
+
+    fn determinant(&self) -> f64 {
+        let [[a, b], [c, d]] = self.linear;
+        a.mul_add(d, -b * c)
+    }
+
+    // True if each of X and Y depends on the other.
+    fn mixes(&self) -> bool {
+        self.linear[0][1].abs() > f64::EPSILON || self.linear[1][0].abs() > f64::EPSILON
+    }
+
+    // True if nothing is turned, scaled or reflected.
+    fn is_translation(&self) -> bool {
+        let [[a, b], [c, d]] = self.linear;
+        [a - 1_f64, b, c, d - 1_f64, self.z_scale - 1_f64]
+            .iter()
+            .all(|value| value.abs() < f64::EPSILON)
+    }
+}
+
+/// Rewrites G-code, applying a [`Transform`] to each move.
+///
+/// ```rust
+/// use gcode_nom::transform::Transform;
+/// use gcode_nom::transform::Transformer;
+///
+/// let mut transformer = Transformer::new(Transform::mirror_x().about([100.0, 0.0]));
+/// let mut out = vec![];
+/// for line in ["G1 X90 Y10 E1 ; wall", "G2 X90 Y30 I0 J10 E2"] {
+///     transformer.write_line(line, &mut out).unwrap();
+/// }
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "G1 X110 Y10 E1 ; wall\nG3 X110 Y30 I0 J10 E2\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Transformer {
+    /// The transform applied to each move.
+    pub transform: Transform,
+    // X, Y and Z, as given by the file. NaN when unknown.
+    position: [f64; 3],
+    position_mode: PositionMode,
+    plane: Plane,
+}
+
+impl Transformer {
+    /// Rewrites moves with the given transform.
+    #[must_use]
+    pub const fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            position: [0_f64; 3],
+            position_mode: PositionMode::Absolute,
+            plane: Plane::XY,
+        }
+    }
+
+    /// Writes a single line of G-code, transformed if it moves the head.
+    ///
+    /// # Errors
+    ///   When a write fails.
+    ///
+    ///   When a rotation needs an axis whose position is unknown, such as
+    ///   `G1 X10` straight after `G28`.
+    ///
+    ///   When an arc outside of the XY plane is turned, scaled or mirrored.
+    ///
+    ///   When a move holds words which are not understood, rewriting the
+    ///   move would drop them.
+    pub fn write_line<W>(&mut self, line: &str, out: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let Ok((remain, command)) = Command::parse_line(line) else {
+            return writeln!(out, "{line}");
+        };
+        let relative = self.position_mode == PositionMode::Relative;
+        let rewritten = match &command {
+            Command::G0(params) | Command::G1(params) | Command::G92(params) => {
+                let code = match command {
+                    Command::G0(_) => "G0",
+                    Command::G1(_) => "G1",
+                    _ => "G92",
+                };
+                // G92 sets the position, it is never relative.
+                let relative = relative && code != "G92";
+                let params = params.iter().map(pos_letter).collect::<Vec<_>>();
+                if params.iter().any(|(letter, _)| "XYZ".contains(*letter)) {
+                    Some(self.rewrite(code, params, relative)?)
+                } else {
+                    None
+                }
+            }
+            Command::G2(form) | Command::G3(form) => {
+                let clockwise = matches!(command, Command::G2(_));
+                Some(self.rewrite_arc(form, clockwise, relative)?)
+            }
+            Command::G17 => {
+                self.plane = Plane::XY;
+                None
+            }
+            Command::G18 => {
+                self.plane = Plane::ZX;
+                None
+            }
+            Command::G19 => {
+                self.plane = Plane::YZ;
+                None
+            }
+            Command::G90 => {
+                self.position_mode = PositionMode::Absolute;
+                None
+            }
+            Command::G91 => {
+                self.position_mode = PositionMode::Relative;
+                None
+            }
+            // Homing, the position is unknown until the next move.
+            Command::GDrop(28) => {
+                self.position = [f64::NAN; 3];
+                None
+            }
+            _ => None,
+        };
+
+        let unparsed = remain.split(';').next().unwrap_or_default().trim();
+        if rewritten.is_some() && !unparsed.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown words \"{unparsed}\" in \"{line}\""),
+            ));
+        }
+
+        match rewritten {
+            Some(text) => match line.find(';') {
+                Some(index) => writeln!(out, "{text} {}", &line[index..]),
+                None => writeln!(out, "{text}"),
+            },
+            None => writeln!(out, "{line}"),
+        }
+    }
+
+    fn rewrite_arc(
+        &mut self,
+        form: &ArcForm,
+        clockwise: bool,
+        relative: bool,
+    ) -> std::io::Result<String> {
+        let transform = self.transform;
+        if self.plane != Plane::XY && !transform.is_translation() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "arcs outside of the XY plane can only be moved",
+            ));
+        }
+        let (ArcForm::IJ(params) | ArcForm::R(params)) = form;
+        let mut params = params.iter().map(arc_letter).collect::<Vec<_>>();
+
+        if self.plane == Plane::XY {
+            let i = value(&params, 'I');
+            let j = value(&params, 'J');
+            if i.is_some() || j.is_some() {
+                let [i, j] = transform.vector([i.unwrap_or(0_f64), j.unwrap_or(0_f64)]);
+                set(&mut params, 'I', Some(i));
+                set(&mut params, 'J', Some(j));
+            }
+            let scale = transform.determinant().abs().sqrt();
+            if let Some(r) = value(&params, 'R') {
+                set(&mut params, 'R', Some(r * scale));
+            }
+        }
+
+        let code = if clockwise == transform.is_mirror() {
+            "G3"
+        } else {
+            "G2"
+        };
+        self.rewrite(code, params, relative)
+    }
+
+    // Transforms the X, Y and Z of a command, then writes it.
+    fn rewrite(
+        &mut self,
+        code: &str,
+        mut params: Vec<(char, f64)>,
+        relative: bool,
+    ) -> std::io::Result<String> {
+        let transform = self.transform;
+        let given = [
+            value(&params, 'X'),
+            value(&params, 'Y'),
+            value(&params, 'Z'),
+        ];
+        let moves_xy = given[0].is_some() || given[1].is_some();
+
+        // Without a rotation each axis is transformed alone, otherwise both
+        // are needed.
+        let fill = if transform.mixes() && !relative {
+            [self.position[0], self.position[1]]
+        } else {
+            [0_f64; 2]
+        };
+        let xy = [given[0].unwrap_or(fill[0]), given[1].unwrap_or(fill[1])];
+        if moves_xy && xy.iter().any(|value| value.is_nan()) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{code} needs both X and Y, but the position is unknown"),
+            ));
+        }
+        let [x, y, z] = if relative {
+            let [x, y] = transform.vector(xy);
+            [x, y, transform.z_scale * given[2].unwrap_or(0_f64)]
+        } else {
+            transform.apply([xy[0], xy[1], given[2].unwrap_or(0_f64)])
+        };
+
+        for (axis, value) in given.iter().enumerate() {
+            if let Some(value) = value {
+                self.position[axis] = if relative {
+                    self.position[axis] + value
+                } else {
+                    *value
+                };
+            }
+        }
+
+        let both = moves_xy && transform.mixes();
+        set(&mut params, 'X', (both || given[0].is_some()).then_some(x));
+        set(&mut params, 'Y', (both || given[1].is_some()).then_some(y));
+        set(&mut params, 'Z', given[2].map(|_| z));
+
+        params.sort_by_key(|(letter, _)| ORDER.find(*letter));
+        let mut text = code.to_string();
+        for (letter, value) in params {
+            let value = if "XYZIJKR".contains(letter) {
+                round(value)
+            } else {
+                value
+            };
+            let _ = write!(text, " {letter}{value}");
+        }
+        Ok(text)
+    }
+}
+
+const fn pos_letter(param: &PosVal) -> (char, f64) {
+    match param {
+        PosVal::A(value) => ('A', *value),
+        PosVal::B(value) => ('B', *value),
+        PosVal::C(value) => ('C', *value),
+        PosVal::E(value) => ('E', *value),
+        PosVal::F(value) => ('F', *value),
+        PosVal::S(value) => ('S', *value),
+        PosVal::U(value) => ('U', *value),
+        PosVal::V(value) => ('V', *value),
+        PosVal::W(value) => ('W', *value),
+        PosVal::X(value) => ('X', *value),
+        PosVal::Y(value) => ('Y', *value),
+        PosVal::Z(value) => ('Z', *value),
+    }
+}
+
+const fn arc_letter(param: &ArcVal) -> (char, f64) {
+    match param {
+        ArcVal::A(value) => ('A', *value),
+        ArcVal::B(value) => ('B', *value),
+        ArcVal::C(value) => ('C', *value),
+        ArcVal::E(value) => ('E', *value),
+        ArcVal::F(value) => ('F', *value),
+        ArcVal::S(value) => ('S', *value),
+        ArcVal::I(value) => ('I', *value),
+        ArcVal::J(value) => ('J', *value),
+        ArcVal::K(value) => ('K', *value),
+        ArcVal::P(value) => ('P', *value),
+        ArcVal::R(value) => ('R', *value),
+        ArcVal::U(value) => ('U', *value),
+        ArcVal::V(value) => ('V', *value),
+        ArcVal::W(value) => ('W', *value),
+        ArcVal::X(value) => ('X', *value),
+        ArcVal::Y(value) => ('Y', *value),
+        ArcVal::Z(value) => ('Z', *value),
+    }
+}
+
+fn value(params: &[(char, f64)], letter: char) -> Option<f64> {
+    params
+        .iter()
+        .find_map(|(l, value)| (*l == letter).then_some(*value))
+}
+
+// Replaces, adds or removes a parameter.
+fn set(params: &mut Vec<(char, f64)>, letter: char, value: Option<f64>) {
+    params.retain(|(l, _)| *l != letter);
+    if let Some(value) = value {
+        params.push((letter, value));
+    }
+}
+
+// Positions are written to the micron.
+fn round(value: f64) -> f64 {
+    (value * 1e3).round() / 1e3 + 0_f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bounds::BoundsChecker;
+
+    fn transform(transform: Transform, input: &str) -> std::io::Result<String> {
+        let mut transformer = Transformer::new(transform);
+        let mut out = vec![];
+        for line in input.lines() {
+            transformer.write_line(line, &mut out)?;
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn combine() {
+        let close = |a: [f64; 3], b: [f64; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-9);
+        let rotate = Transform::rotate(90_f64);
+        assert!(close(
+            rotate.apply([1_f64, 0_f64, 1_f64]),
+            [0_f64, 1_f64, 1_f64]
+        ));
+        assert!(rotate.mixes());
+
+        let scale = Transform::scale(2_f64).about([10_f64, 10_f64]);
+        assert!(close(
+            scale.apply([11_f64, 9_f64, 0.2]),
+            [12_f64, 8_f64, 0.4]
+        ));
+
+        // Mirroring twice leaves the print where it was.
+        let twice = Transform::mirror_y()
+            .about([0_f64, 50_f64])
+            .then(Transform::mirror_y().about([0_f64, 50_f64]));
+        assert!(twice.is_translation());
+        assert!(!twice.is_mirror());
+        assert!(Transform::mirror_x().is_mirror());
+        assert!(!Transform::rotate(180_f64).is_mirror());
+
+        let moved = Transform::translate(1_f64, 2_f64, 3_f64).then(Transform::scale(2_f64));
+        assert!(close(moved.apply([0_f64; 3]), [2_f64, 4_f64, 6_f64]));
+    }
+
+    #[test]
+    fn moves() {
+        let output = transform(
+            Transform::translate(10_f64, -5_f64, 0.1),
+            "G28\nG1 Z0.2 F720\nG1 X10 Y20 E.5 ; first\nG0 Y40\nG92 E0\nG91\nG1 X1 Y1\nG90\nG92 X0\nM104 S200",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "G28\nG1 Z0.3 F720\nG1 X20 Y15 E0.5 ; first\nG0 Y35\nG92 E0\nG91\nG1 X1 Y1\nG90\nG92 X10\nM104 S200\n"
+        );
+
+        // Turning the print, the untouched axis is filled in.
+        let output = transform(
+            Transform::rotate(90_f64),
+            "G1 X10 Y20\nG1 X30 E1\nG91\nG1 Y5 F600\nG90\nG1 Z1",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "G1 X-20 Y10\nG1 X-20 Y30 E1\nG91\nG1 X-5 Y0 F600\nG90\nG1 Z1\n"
+        );
+
+        // After homing the other axis is unknown.
+        let error = transform(Transform::rotate(45_f64), "G28\nG1 X10").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        // Moving needs one axis at a time.
+        assert!(transform(Transform::translate(1_f64, 1_f64, 0_f64), "G28\nG1 X10").is_ok());
+
+        // Words which are not understood are not silently dropped.
+        let translate = Transform::translate(1_f64, 0_f64, 0_f64);
+        let error = transform(translate, "G1 X10 P1e3 E1").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            transform(translate, "G1 X10 E1 ; a comment").unwrap(),
+            "G1 X11 E1 ; a comment\n"
+        );
+    }
+
+    #[test]
+    fn arcs() {
+        let input =
+            "G1 X10 Y0\nG2 X0 Y-10 I-10 J0 E1\nG3 X-20 Y0 I-10 J10 E2 F1200\nG91\nG2 X10 Y10 J10";
+        // A mirror swaps the direction of each arc.
+        assert_eq!(
+            transform(Transform::mirror_x(), input).unwrap(),
+            "G1 X-10 Y0\nG3 X0 Y-10 I10 J0 E1\nG2 X20 Y0 I10 J10 E2 F1200\nG91\nG3 X-10 Y10 I0 J10\n"
+        );
+        // A turn keeps the direction, but moves the centre.
+        assert_eq!(
+            transform(
+                Transform::rotate(-90_f64).then(Transform::scale(0.5)),
+                input
+            )
+            .unwrap(),
+            "G1 X0 Y-5\nG2 X-5 Y0 I0 J5 E1\nG3 X0 Y10 I5 J5 E2 F1200\nG91\nG2 X5 Y-5 I5 J0\n"
+        );
+
+        let input = "G18\nG2 X10 Z10 I5 K5";
+        assert_eq!(
+            transform(Transform::translate(1_f64, 1_f64, 1_f64), input).unwrap(),
+            "G18\nG2 X11 Z11 I5 K5\n"
+        );
+        assert!(transform(Transform::mirror_x(), input).is_err());
+    }
+
+    #[test]
+    fn slicer_file() {
+        let input = include_str!("../../assets/3DBenchy.gcode");
+        let extent = |text: &str| {
+            let mut checker = BoundsChecker::default();
+            for (i, line) in text.lines().enumerate() {
+                checker.push(i + 1, &Command::parse_line(line).unwrap().1);
+            }
+            checker.finish().extrusion.unwrap()
+        };
+        let before = extent(input);
+
+        // Turned half way about its centre, the print covers the same area.
+        let center = [
+            f64::midpoint(before.min[0], before.max[0]),
+            f64::midpoint(before.min[1], before.max[1]),
+        ];
+        let output = transform(Transform::rotate(180_f64).about(center), input).unwrap();
+        assert_eq!(output.lines().count(), input.lines().count());
+        let after = extent(&output);
+        for axis in 0..3 {
+            assert!((after.min[axis] - before.min[axis]).abs() < 2e-3);
+            assert!((after.max[axis] - before.max[axis]).abs() < 2e-3);
+        }
+    }
+}